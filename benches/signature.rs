@@ -0,0 +1,127 @@
+//
+// シグネチャ照合のベンチマーク
+// cargo bench --bench signature
+//
+// RUSTY_BLUE_BENCH_EVTXにSysmonのevtxファイルを指定すると、そのEventID 1のCommandLineで計測する
+// 指定しない場合は、よくあるコマンドラインを組み合わせた文字列で計測する
+//
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use evtx::EvtxParser;
+use regex::Regex;
+use rusty_blue::detections::signature::{self, Signature, SignatureSet, Target};
+
+// シグネチャ数を増やしたときの比較用に追加するシグネチャ数
+const EXTRA_SIGNATURES: usize = 2000;
+const SYNTHETIC_COMMAND_LINES: usize = 20000;
+
+fn load_signatures() -> Vec<Signature> {
+    let (mut signatures, errors) = signature::read_signatures("signatures.yml");
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    // IOCとして配布されるようなツール名、パス、正規表現を模したシグネチャ
+    let mut yaml = String::from("signatures:\n");
+    for i in 0..EXTRA_SIGNATURES {
+        let regex = match i % 4 {
+            0 => format!("evil-tool-{}\\.exe", i),
+            1 => format!("\\\\Temp\\\\payload{}\\.(dll|ps1)", i),
+            2 => format!("Invoke-Attack{} -Target", i),
+            _ => format!("[a-f0-9]{{8}}-bad{}", i),
+        };
+        yaml.push_str(&format!(
+            "  - description: 'bench {}'\n    target: command_line\n    regex: '{}'\n    case_sensitive: {}\n",
+            i,
+            regex,
+            i % 2 == 0
+        ));
+    }
+    let (extra, errors) = signature::parse_signatures(&yaml, "bench");
+    assert!(errors.is_empty(), "{:?}", errors);
+    signatures.extend(extra);
+    return signatures;
+}
+
+fn load_command_lines() -> Vec<String> {
+    if let Ok(path) = std::env::var("RUSTY_BLUE_BENCH_EVTX") {
+        let data_regex = Regex::new(r#"(?s)<Data Name="CommandLine">(.*?)</Data>"#).unwrap();
+        let mut parser = EvtxParser::from_path(&path).expect("cannot open RUSTY_BLUE_BENCH_EVTX");
+        let command_lines: Vec<String> = parser
+            .records()
+            .filter_map(|record| record.ok())
+            .filter_map(|record| {
+                data_regex
+                    .captures(&record.data)
+                    .map(|captures| captures[1].to_string())
+            })
+            .collect();
+        if !command_lines.is_empty() {
+            return command_lines;
+        }
+    }
+
+    let templates = [
+        "C:\\Windows\\system32\\svchost.exe -k netsvcs -p -s Schedule",
+        "\"C:\\Program Files\\Google\\Chrome\\Application\\chrome.exe\" --type=renderer --field-trial-handle=1234",
+        "C:\\Windows\\system32\\conhost.exe 0xffffffff -ForceV1",
+        "powershell.exe -NoProfile -ExecutionPolicy Bypass -File C:\\scripts\\backup.ps1",
+        "C:\\Windows\\Microsoft.NET\\Framework64\\v4.0.30319\\csc.exe /noconfig /fullpaths @\"C:\\Users\\user\\AppData\\Local\\Temp\\x.cmdline\"",
+        "cmd.exe /c echo abcdef > \\\\.\\pipe\\ghijkl",
+        "rundll32.exe C:\\Windows\\system32\\shell32.dll,Control_RunDLL",
+        "C:\\Windows\\system32\\wbem\\wmiprvse.exe -secured -Embedding",
+    ];
+    return (0..SYNTHETIC_COMMAND_LINES)
+        .map(|i| format!("{} {}", templates[i % templates.len()], i))
+        .collect();
+}
+
+// 変更前のutils::check_regexと同じく、全てのシグネチャを1つずつ照合する
+fn match_linear<'a>(signatures: &'a Vec<Signature>, string: &str) -> Vec<&'a Signature> {
+    return signatures
+        .iter()
+        .filter(|s| s.is_target(Target::CommandLine) && s.is_match(string))
+        .collect();
+}
+
+fn bench_signatures(c: &mut Criterion) {
+    let signatures = load_signatures();
+    let set = SignatureSet::new(signatures.clone());
+    let command_lines = load_command_lines();
+
+    // 照合結果が変わらないことを確認してから計測する
+    command_lines.iter().for_each(|command_line| {
+        let expected: Vec<&str> = match_linear(&signatures, command_line)
+            .iter()
+            .map(|s| s.description.as_str())
+            .collect();
+        let actual: Vec<&str> = set
+            .matches(command_line, Target::CommandLine)
+            .iter()
+            .map(|s| s.description.as_str())
+            .collect();
+        assert_eq!(expected, actual);
+    });
+
+    let mut group = c.benchmark_group(format!(
+        "{} signatures x {} command lines",
+        signatures.len(),
+        command_lines.len()
+    ));
+    group.sample_size(10);
+    group.bench_function("linear", |b| {
+        b.iter(|| {
+            command_lines.iter().for_each(|command_line| {
+                black_box(match_linear(&signatures, command_line));
+            })
+        })
+    });
+    group.bench_function("signature_set", |b| {
+        b.iter(|| {
+            command_lines.iter().for_each(|command_line| {
+                black_box(set.matches(command_line, Target::CommandLine));
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_signatures);
+criterion_main!(benches);
@@ -1,7 +1,7 @@
 extern crate regex;
 
 use crate::detections::configs;
-use crate::detections::print::MessageNotation;
+use crate::detections::print::{AlertMessages, MessageNotation};
 use crate::models::event;
 use std::collections::HashMap;
 
@@ -44,25 +44,15 @@ impl Application {
                         .application_regex
                         .replace_all(application, "");
                     let username = message_split[4];
-                    let stdout = std::io::stdout();
-                    let mut stdout = stdout.lock();
 
-                    MessageNotation::info_noheader(
-                        &mut stdout,
-                        format!("Date    : {}", system.time_created.system_time),
-                    )
-                    .ok();
-                    MessageNotation::info_noheader(
-                        &mut stdout,
-                        format!("Message: Message EMET Block"),
-                    )
-                    .ok();
-                    MessageNotation::info_noheader(&mut stdout, "EventID: 2".to_string()).ok();
-                    MessageNotation::info_noheader(&mut stdout, format!("Command: {}", command))
-                        .ok();
-                    MessageNotation::info_noheader(&mut stdout, format!("Results: {}", text)).ok();
-                    MessageNotation::info_noheader(&mut stdout, format!("Results: {}", username))
-                        .ok();
+                    let mut msges: Vec<String> = Vec::new();
+                    msges.push(format!("Date: {}", system.time_created.system_time));
+                    msges.push("Message: EMET Block".to_string());
+                    msges.push("EventID: 2".to_string());
+                    msges.push(format!("Command: {}", command));
+                    msges.push(format!("Results: {}", text));
+                    msges.push(format!("Results: {}", username));
+                    AlertMessages::output(msges);
                 }
             }
             None => {
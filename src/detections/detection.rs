@@ -4,19 +4,75 @@ extern crate quick_xml;
 use crate::detections::application;
 use crate::detections::applocker;
 use crate::detections::common;
+use crate::detections::defender;
+use crate::detections::filter;
+use crate::detections::identity;
 use crate::detections::powershell;
-use crate::detections::print::MessageNotation;
+use crate::detections::print::{AlertMessages, MessageNotation, ALERT_MESSAGES};
+use crate::detections::process_tree;
+use crate::detections::rdp;
 use crate::detections::security;
+use crate::detections::service;
+use crate::detections::state;
+use crate::detections::summary;
 use crate::detections::sysmon;
 use crate::detections::system;
+use crate::detections::tampering;
+use crate::detections::task;
+use crate::detections::timestamp;
 use crate::models::event;
-use evtx::EvtxParser;
+use evtx::{EvtxParser, ParserSettings};
 use quick_xml::de::DeError;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
 
-#[derive(Debug)]
 pub struct Detection {
     timeline_list: BTreeMap<String, String>,
+    filter: filter::RecordFilter,
+    time_formatter: timestamp::TimeFormatter,
+    summary: summary::FileSummary,
+    detectors: Detectors,
+    // 処理済みの最後のEventRecordID。--followと--stateで追記されたレコードだけを処理するために使う
+    last_record_id: u64,
+    // --followで前回flushした時に表示した集計の検知
+    flushed_alerts: HashSet<Vec<String>>,
+}
+
+// 各検知の状態。--followでは追記のたびに作り直さずに引き継ぐ
+struct Detectors {
+    common: common::Common,
+    security: security::Security,
+    system: system::System,
+    application: application::Application,
+    applocker: applocker::AppLocker,
+    defender: defender::Defender,
+    sysmon: sysmon::Sysmon,
+    powershell: powershell::PowerShell,
+    process_tree: process_tree::ProcessTree,
+    service: service::Service,
+    task: task::ScheduledTask,
+    identity: identity::Identity,
+    tampering: tampering::Tampering,
+}
+
+impl Detectors {
+    fn new() -> Detectors {
+        Detectors {
+            common: common::Common::new(),
+            security: security::Security::new(),
+            system: system::System::new(),
+            application: application::Application::new(),
+            applocker: applocker::AppLocker::new(),
+            defender: defender::Defender::new(),
+            sysmon: sysmon::Sysmon::new(),
+            powershell: powershell::PowerShell::new(),
+            process_tree: process_tree::ProcessTree::new(),
+            service: service::Service::new(),
+            task: task::ScheduledTask::new(),
+            identity: identity::Identity::new(),
+            tampering: tampering::Tampering::new(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -25,100 +81,388 @@ struct ExampleCopyTraitImpl {
 }
 
 impl Detection {
-    pub fn new() -> Detection {
+    pub fn new(
+        filepath: &str,
+        filter: filter::RecordFilter,
+        time_formatter: timestamp::TimeFormatter,
+    ) -> Detection {
         Detection {
             timeline_list: BTreeMap::new(),
+            filter: filter,
+            time_formatter: time_formatter,
+            summary: summary::FileSummary::new(filepath),
+            detectors: Detectors::new(),
+            last_record_id: 0,
+            flushed_alerts: HashSet::new(),
         }
     }
 
-    pub fn start(&mut self, mut parser: EvtxParser<std::fs::File>) -> Result<(), DeError> {
-        let mut common: common::Common = common::Common::new();
-        let mut security = security::Security::new();
-        let mut system = system::System::new();
-        let mut application = application::Application::new();
-        let mut applocker = applocker::AppLocker::new();
-        let mut sysmon = sysmon::Sysmon::new();
-        let mut powershell = powershell::PowerShell::new();
-
-        for record in parser.records() {
-            match record {
-                Ok(r) => match quick_xml::de::from_str(&r.data) {
-                    Ok(event) => {
-                        let event: event::Evtx = event;
-                        let event_id = event.system.event_id.to_string();
-                        let channel = event.system.channel.to_string();
-                        let event_data = event.parse_event_data();
-
-                        &common.detection(&event.system, &event_data);
-                        if channel == "Security" {
-                            match event_id.as_str() {
-                                "4688" | "4672" | "4720" | "4728" | "4732" | "4756" | "4625"
-                                | "4673" | "4674" | "4648" | "1102" => {
-                                    &security.detection(
-                                        event_id,
-                                        &event.system,
-                                        &event.user_data,
-                                        event_data,
-                                    );
-                                }
-                                _ => (),
+    pub fn last_record_id(&self) -> u64 {
+        return self.last_record_id;
+    }
+
+    //
+    // 前回処理したEventRecordIDまでのレコードは読み飛ばし、集計値とファイルの最後でまとめて表示する検知は前回までの分から数える
+    // 前回の実行で表示した検知結果は引き継がない
+    //
+    pub fn resume(&mut self, file_state: &state::FileState) {
+        self.last_record_id = file_state.last_record_id;
+        file_state.counters.add_to(&mut self.summary);
+        self.detectors
+            .security
+            .resume(&file_state.counters.security);
+        self.detectors
+            .identity
+            .resume(&file_state.counters.identity);
+        self.detectors
+            .applocker
+            .resume(&file_state.counters.applocker);
+    }
+
+    // 状態ファイルに残す集計値
+    pub fn counters(&self) -> state::Counters {
+        let mut counters = state::Counters::from_summary(&self.summary);
+        counters.security = self.detectors.security.aggregate();
+        counters.identity = self.detectors.identity.aggregate();
+        counters.applocker = self.detectors.applocker.aggregate();
+        return counters;
+    }
+
+    pub fn summary(&self) -> &summary::FileSummary {
+        return &self.summary;
+    }
+
+    pub fn into_summary(self) -> summary::FileSummary {
+        return self.summary;
+    }
+
+    pub fn start(&mut self, parser: EvtxParser<std::fs::File>) -> Result<(), DeError> {
+        self.process(parser)?;
+        self.finish();
+        return Ok(());
+    }
+
+    //
+    // 処理済みのEventRecordIDより後のレコードを検査する
+    // 検知の状態は呼び出しをまたいで引き継ぐので、追記されたファイルを繰り返し渡せる
+    //
+    pub fn process(&mut self, mut parser: EvtxParser<std::fs::File>) -> Result<(), DeError> {
+        ALERT_MESSAGES
+            .lock()
+            .unwrap()
+            .set_file(&self.summary.filepath);
+
+        let settings = Arc::new(ParserSettings::default());
+        for chunk_data in parser.chunks() {
+            let mut chunk_data = match chunk_data {
+                Ok(chunk_data) => chunk_data,
+                Err(e) => {
+                    self.parse_error(format!("{}", e));
+                    continue;
+                }
+            };
+            // 処理済みのレコードしかないチャンクは、レコードを解析せずに読み飛ばす
+            if chunk_data.header.last_event_record_id <= self.last_record_id {
+                continue;
+            }
+            let mut chunk = match chunk_data.parse(settings.clone()) {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    self.parse_error(format!("{}", e));
+                    continue;
+                }
+            };
+            for record in chunk
+                .iter()
+                .map(|record| record.and_then(|record| record.into_xml()))
+            {
+                if let Ok(r) = &record {
+                    if r.event_record_id <= self.last_record_id {
+                        continue;
+                    }
+                    self.last_record_id = r.event_record_id;
+                }
+                match record {
+                    Ok(r) => match quick_xml::de::from_str(&r.data) {
+                        Ok(event) => {
+                            let mut event: event::Evtx = event;
+                            let time_created = timestamp::parse_system_time(
+                                &event.system.time_created.system_time,
+                            );
+                            let event_id = event.system.event_id.to_string();
+                            let channel = event.system.channel.to_string();
+                            let event_data = event.parse_event_data();
+
+                            // Record IDの連続性をチェックするため、日時とEventID、Channelで絞り込む前に実行する
+                            &self.detectors.common.detection(
+                                &event.system,
+                                &event_data,
+                                &time_created,
+                            );
+                            if !self.filter.is_in_time_range(&time_created) {
+                                continue;
                             }
-                        } else if channel == "System" {
-                            match event_id.as_str() {
-                                "7030" | "7036" | "7045" | "7040" | "104" => {
-                                    &system.detection(event_id, &event.system, event_data);
-                                }
-                                _ => (),
+                            // 各検知で表示する日時をここで一度だけ変換しておく
+                            if let Some(time_created) = &time_created {
+                                event.system.time_created.system_time =
+                                    self.time_formatter.format(time_created);
                             }
-                        } else if channel == "Application" {
-                            match event_id.as_str() {
-                                "2" => {
-                                    &application.detection(event_id, &event.system, event_data);
-                                }
-                                _ => (),
+                            if !self.filter.is_target_event(&event.system) {
+                                continue;
                             }
-                        } else if channel == "Microsoft-Windows-PowerShell/Operational" {
-                            match event_id.as_str() {
-                                "4103" | "4104" => {
-                                    &powershell.detection(event_id, &event.system, event_data);
-                                }
-                                _ => (),
+                            self.summary.add_record(&event.system, &time_created);
+                            ALERT_MESSAGES.lock().unwrap().set_record(
+                                &event.system,
+                                &event_data,
+                                &time_created,
+                            );
+                            // 4698-4702とTaskScheduler/Operationalのタスクの登録、実行、削除を関連付ける
+                            self.detectors.task.detection(
+                                &channel,
+                                &event_id,
+                                &event.system,
+                                &event_data,
+                                &time_created,
+                            );
+                            // RDPのイベントは複数のファイルをまとめてセッションにするので、集計に残しておく
+                            if let Some(rdp_event) =
+                                rdp::parse_event(&event.system, &event_data, &time_created)
+                            {
+                                self.summary.rdp_events.push(rdp_event);
                             }
-                        } else if channel == "Microsoft-Windows-Sysmon/Operational" {
-                            match event_id.as_str() {
-                                "1" | "7" => {
-                                    &sysmon.detection(event_id, &event.system, event_data);
+                            // 4688とSysmonの1はチャンネルをまたいでプロセスツリーを作る
+                            self.detectors.process_tree.detection(
+                                &channel,
+                                &event_id,
+                                &event.system,
+                                &event_data,
+                            );
+                            // 監査ポリシーの変更やログの消去は、Record IDの欠落と関連付けるため記録しておく
+                            self.detectors.tampering.detection(
+                                &channel,
+                                &event_id,
+                                &event.system,
+                                &event_data,
+                                &time_created,
+                            );
+                            // 4624, 4672, 4688のアカウントをユーザー名とSID毎に集計する
+                            self.detectors.identity.detection(
+                                &channel,
+                                &event_id,
+                                &event.system,
+                                &event_data,
+                                &time_created,
+                            );
+                            // 7045と4697のインストールはここだけで検査し、7036の開始と関連付ける
+                            self.detectors.service.detection(
+                                &channel,
+                                &event_id,
+                                &event.system,
+                                &event_data,
+                                &time_created,
+                            );
+                            if channel == "Security" {
+                                match event_id.as_str() {
+                                    "4688" | "4672" | "4720" | "4722" | "4724" | "4726"
+                                    | "4728" | "4729" | "4732" | "4733" | "4756" | "4757"
+                                    | "4767" | "4781" | "4625" | "4673" | "4674" | "4648"
+                                    | "1102" => {
+                                        &self.detectors.security.detection(
+                                            event_id,
+                                            &event.system,
+                                            &event.user_data,
+                                            event_data,
+                                            &time_created,
+                                        );
+                                    }
+                                    _ => (),
                                 }
-                                _ => (),
-                            }
-                        } else if channel == "Microsoft-Windows-AppLocker/EXE and DLL" {
-                            match event_id.as_str() {
-                                "8003" | "8004" | "8006" | "8007" => {
-                                    &applocker.detection(event_id, &event.system, event_data);
+                            } else if channel == "System" {
+                                match event_id.as_str() {
+                                    "7030" | "7036" | "7040" | "104" => {
+                                        &self.detectors.system.detection(
+                                            event_id,
+                                            &event.system,
+                                            event_data,
+                                        );
+                                    }
+                                    _ => (),
+                                }
+                            } else if channel == "Application" {
+                                match event_id.as_str() {
+                                    "2" => {
+                                        &self.detectors.application.detection(
+                                            event_id,
+                                            &event.system,
+                                            event_data,
+                                        );
+                                    }
+                                    _ => (),
+                                }
+                            } else if channel == "Microsoft-Windows-PowerShell/Operational" {
+                                match event_id.as_str() {
+                                    "4103" | "4104" => {
+                                        &self.detectors.powershell.detection(
+                                            event_id,
+                                            &event.system,
+                                            event_data,
+                                            &time_created,
+                                        );
+                                    }
+                                    _ => (),
+                                }
+                            } else if channel == "Windows PowerShell" {
+                                match event_id.as_str() {
+                                    "400" | "403" | "600" | "800" => {
+                                        &self.detectors.powershell.detection(
+                                            event_id,
+                                            &event.system,
+                                            event_data,
+                                            &time_created,
+                                        );
+                                    }
+                                    _ => (),
+                                }
+                            } else if channel == "Microsoft-Windows-Sysmon/Operational" {
+                                match event_id.as_str() {
+                                    "1" | "7" => {
+                                        &self.detectors.sysmon.detection(
+                                            event_id,
+                                            &event.system,
+                                            event_data,
+                                        );
+                                    }
+                                    _ => (),
+                                }
+                            } else if channel == "Microsoft-Windows-Windows Defender/Operational" {
+                                match event_id.as_str() {
+                                    "1116" | "1117" | "1118" | "1119" | "5001" | "5007"
+                                    | "5010" | "5012" | "5013" => {
+                                        &self.detectors.defender.detection(
+                                            event_id,
+                                            &event.system,
+                                            event_data,
+                                        );
+                                    }
+                                    _ => (),
+                                }
+                            } else if channel == "Microsoft-Windows-AppLocker/EXE and DLL"
+                                || channel == "Microsoft-Windows-AppLocker/MSI and Script"
+                                || channel == "Microsoft-Windows-AppLocker/Packaged app-Execution"
+                                || channel == "Microsoft-Windows-AppLocker/Packaged app-Deployment"
+                            {
+                                // 許可されたイベント(8002, 8005, 8020, 8023)は対象外
+                                match event_id.as_str() {
+                                    "8003" | "8004" | "8006" | "8007" | "8021" | "8022"
+                                    | "8024" | "8025" | "8027" => {
+                                        &self.detectors.applocker.detection(
+                                            event_id,
+                                            &event.system,
+                                            event_data,
+                                        );
+                                    }
+                                    _ => (),
                                 }
-                                _ => (),
                             }
                         }
-                    }
-                    Err(err) => {
-                        let stdout = std::io::stdout();
-                        let mut stdout = stdout.lock();
-                        MessageNotation::alert(&mut stdout, format!("{}", err)).ok();
-                    }
-                },
-                Err(e) => {
-                    let stdout = std::io::stdout();
-                    let mut stdout = stdout.lock();
-                    MessageNotation::alert(&mut stdout, format!("{}", e)).ok();
+                        Err(err) => self.parse_error(format!("{}", err)),
+                    },
+                    Err(e) => self.parse_error(format!("{}", e)),
                 }
             }
         }
 
-        ////////////////////////////
-        // 表示
-        ////////////////////////////
-        security.disp();
-
         return Ok(());
     }
+
+    fn parse_error(&mut self, message: String) {
+        self.summary.add_parse_error();
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        MessageNotation::alert(&mut stdout, message).ok();
+    }
+
+    //
+    // --follow: 定期的に呼び出して、集計が必要な検知のうち前回から変わったものを表示する
+    // 検知の状態はfinishと違って残すので、次の呼び出しでもそれまでの分から集計する
+    //
+    pub fn flush(&mut self) {
+        ALERT_MESSAGES
+            .lock()
+            .unwrap()
+            .set_file(&self.summary.filepath);
+        ALERT_MESSAGES.lock().unwrap().clear_record();
+
+        let alerts: Vec<Vec<String>> = self
+            .detectors
+            .security
+            .aggregate_alerts()
+            .into_iter()
+            .chain(self.detectors.identity.aggregate_alerts())
+            .chain(self.detectors.applocker.aggregate_alerts())
+            .collect();
+        alerts
+            .iter()
+            .filter(|msges| !self.flushed_alerts.contains(*msges))
+            .for_each(|msges| {
+                AlertMessages::output(msges.to_vec());
+            });
+        self.flushed_alerts = alerts.into_iter().collect();
+        // 足すと呼び出すたびに重複するので、finishと同じく検知の集計で置き換える
+        self.summary.security = self.detectors.security.aggregate();
+        self.detectors.powershell.disp_stale();
+        // 状態ファイルに残すため
+        self.take_tamper_events();
+    }
+
+    //
+    // ファイルを最後まで処理した後に、集計が必要な検知を表示する
+    //
+    pub fn finish(&mut self) {
+        ALERT_MESSAGES.lock().unwrap().clear_record();
+        self.detectors.security.disp();
+        self.detectors.identity.disp();
+        // 続きから処理した場合も、前回までの分は検知の集計に含まれている
+        self.summary.security = self.detectors.security.aggregate();
+        self.detectors.applocker.disp();
+        self.detectors.powershell.disp();
+        self.take_tamper_events();
+    }
+
+    // 続きから処理した場合は、前回までの分に追加する
+    fn take_tamper_events(&mut self) {
+        self.summary
+            .anti_forensics
+            .extend(self.detectors.tampering.take_events());
+        self.summary
+            .record_gaps
+            .extend(self.detectors.common.take_record_gaps());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::detections::detection::Detection;
+    use crate::detections::filter;
+    use crate::detections::summary;
+    use crate::detections::timestamp;
+
+    #[test]
+    fn test_flush_security_aggregate() {
+        let mut detection = Detection::new(
+            "security.evtx",
+            filter::RecordFilter::new(),
+            timestamp::TimeFormatter::new(),
+        );
+        let mut aggregate = summary::SecurityAggregate::default();
+        aggregate.total_failed_logons = 3;
+        aggregate.failed_logons.insert("admin".to_string(), 3);
+        detection.detectors.security.resume(&aggregate);
+
+        // 何回flushしても重複して数えない
+        detection.flush();
+        detection.flush();
+        assert_eq!(&aggregate, &detection.summary().security);
+        assert_eq!(aggregate, detection.counters().security);
+    }
 }
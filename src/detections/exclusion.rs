@@ -0,0 +1,369 @@
+extern crate chrono;
+extern crate yaml_rust;
+
+use chrono::{Local, NaiveDate};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use yaml_rust::{Yaml, YamlLoader};
+
+// 同じ意味でもイベントによって名前が違うフィールド
+// 指定したフィールドがレコードにない場合は、ここに書いた順にフィールドを探す
+const FIELD_ALIASES: &[(&str, &[&str])] = &[
+    ("Image", &["NewProcessName", "Application", "FilePath"]),
+    ("ParentImage", &["ParentProcessName"]),
+    ("User", &["SubjectUserName", "TargetUserName", "TargetUser"]),
+    ("ServiceName", &["param1"]),
+    ("Hash", &["Hashes", "FileHash"]),
+    ("Signer", &["Signature"]),
+];
+
+//
+// 除外設定1件分
+// 指定した条件を全て満たすアラートを除外する
+//
+#[derive(Debug)]
+pub struct Exclusion {
+    pub comment: String,
+    // 検知結果のMessageの値
+    detections: Vec<String>,
+    // 小文字
+    channels: Vec<String>,
+    event_ids: Vec<String>,
+    // key: フィールド名, value: 値の正規表現
+    fields: Vec<(String, Regex)>,
+    pub expires: Option<NaiveDate>,
+    // 除外したアラートの数
+    pub suppressed: u64,
+}
+
+impl Exclusion {
+    pub fn is_expired(&self, today: &NaiveDate) -> bool {
+        return self
+            .expires
+            .map(|expires| expires < *today)
+            .unwrap_or(false);
+    }
+
+    fn is_match(&self, record: &ExclusionTarget) -> bool {
+        if !self.detections.is_empty() && !self.detections.iter().any(|d| d == record.detection) {
+            return false;
+        }
+        if !self.channels.is_empty() && !self.channels.contains(&record.channel.to_lowercase()) {
+            return false;
+        }
+        if !self.event_ids.is_empty() && !self.event_ids.iter().any(|id| id == record.event_id) {
+            return false;
+        }
+        return self.fields.iter().all(|(field, regex)| {
+            return record
+                .get_field(field)
+                .map(|value| regex.is_match(value))
+                .unwrap_or(false);
+        });
+    }
+}
+
+// 除外するかを判定するアラートの情報
+pub struct ExclusionTarget<'a> {
+    pub detection: &'a str,
+    pub channel: &'a str,
+    pub event_id: &'a str,
+    pub computer: &'a str,
+    pub fields: &'a HashMap<String, String>,
+}
+
+impl<'a> ExclusionTarget<'a> {
+    fn get_field(&self, field: &str) -> Option<&'a str> {
+        if field == "Computer" {
+            return Option::Some(self.computer);
+        }
+        if let Some(value) = self.fields.get(field) {
+            return Option::Some(value);
+        }
+        return FIELD_ALIASES
+            .iter()
+            .find(|(name, _)| *name == field)
+            .and_then(|(_, aliases)| aliases.iter().find_map(|alias| self.fields.get(*alias)))
+            .map(|value| value.as_str());
+    }
+}
+
+#[derive(Debug)]
+pub struct ExclusionList {
+    pub exclusions: Vec<Exclusion>,
+}
+
+impl ExclusionList {
+    pub fn new() -> ExclusionList {
+        ExclusionList { exclusions: vec![] }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.exclusions.is_empty();
+    }
+
+    // 最初に一致した除外設定の件数を数えてtrueを返す
+    pub fn check(&mut self, record: &ExclusionTarget) -> bool {
+        return match self.exclusions.iter_mut().find(|e| e.is_match(record)) {
+            Some(exclusion) => {
+                exclusion.suppressed += 1;
+                true
+            }
+            None => false,
+        };
+    }
+
+    // 除外設定毎に除外したアラートの数を表示する
+    pub fn create_report(&self) -> Vec<String> {
+        let mut msges: Vec<String> = vec![];
+        if self.exclusions.is_empty() {
+            return msges;
+        }
+        msges.push("==================== Exclusions ====================".to_string());
+        msges.push("Suppressed  Exclusion".to_string());
+        self.exclusions.iter().for_each(|exclusion| {
+            msges.push(format!(
+                "{:>10}  {}{}",
+                exclusion.suppressed,
+                exclusion.comment,
+                exclusion
+                    .expires
+                    .map(|expires| format!(" (expires {})", expires))
+                    .unwrap_or("".to_string())
+            ));
+        });
+        return msges;
+    }
+}
+
+//
+// 除外設定ファイル(YAML)を読み込む
+// 読み込めなかった設定と期限切れの設定は使わずに、その内容をエラーとして返す
+//
+pub fn read_exclusions(path: &str) -> (ExclusionList, Vec<String>) {
+    return match fs::read_to_string(path) {
+        Ok(contents) => parse_exclusions(&contents, path, &Local::today().naive_local()),
+        Err(e) => (
+            ExclusionList::new(),
+            vec![format!("{} not found , {}", path, e)],
+        ),
+    };
+}
+
+pub fn parse_exclusions(
+    contents: &str,
+    path: &str,
+    today: &NaiveDate,
+) -> (ExclusionList, Vec<String>) {
+    let mut list = ExclusionList::new();
+    let yaml = match YamlLoader::load_from_str(contents) {
+        Ok(yaml) => yaml,
+        Err(e) => return (list, vec![format!("{}: fail to read file, {}", path, e)]),
+    };
+    let entries = match yaml.get(0).map(|doc| &doc["exclusions"]) {
+        Some(Yaml::Array(entries)) => entries,
+        // 空のリストはNullになる
+        Some(Yaml::Null) | Some(Yaml::BadValue) => return (list, vec![]),
+        _ => return (list, vec![format!("{}: exclusions must be a list", path)]),
+    };
+
+    let mut errors = vec![];
+    for (i, entry) in entries.iter().enumerate() {
+        match parse_exclusion(entry) {
+            Ok(exclusion) => {
+                if exclusion.is_expired(today) {
+                    errors.push(format!(
+                        "{}: exclusion #{} ({}) expired on {}. it is not applied.",
+                        path,
+                        i + 1,
+                        exclusion.comment,
+                        exclusion.expires.unwrap()
+                    ));
+                    continue;
+                }
+                list.exclusions.push(exclusion);
+            }
+            Err(e) => errors.push(format!("{}: exclusion #{}: {}", path, i + 1, e)),
+        }
+    }
+    return (list, errors);
+}
+
+// 文字列でも数値でもリストでも書けるようにする
+fn get_strings(yaml: &Yaml) -> Vec<String> {
+    return match yaml {
+        Yaml::String(s) => vec![s.to_string()],
+        Yaml::Integer(i) => vec![i.to_string()],
+        Yaml::Array(values) => values.iter().flat_map(|v| get_strings(v)).collect(),
+        _ => vec![],
+    };
+}
+
+fn parse_exclusion(entry: &Yaml) -> Result<Exclusion, String> {
+    let comment = entry["comment"].as_str().unwrap_or("").to_string();
+
+    let mut fields = vec![];
+    if let Some(hash) = entry["fields"].as_hash() {
+        for (field, pattern) in hash {
+            let field = field.as_str().ok_or("field name must be a string")?;
+            let pattern = get_strings(pattern)
+                .into_iter()
+                .next()
+                .ok_or(format!("value of {} must be a string", field))?;
+            let regex = Regex::new(&pattern)
+                .map_err(|e| format!("invalid regex for {} ({}), {}", field, comment, e))?;
+            fields.push((field.to_string(), regex));
+        }
+    }
+
+    let expires = match &entry["expires"] {
+        Yaml::Null | Yaml::BadValue => Option::None,
+        expires => {
+            let expires = get_strings(expires).into_iter().next().unwrap_or_default();
+            Option::Some(
+                NaiveDate::parse_from_str(&expires, "%Y-%m-%d").map_err(|_| {
+                    format!(
+                        "invalid expires ({}): {}. use YYYY-MM-DD.",
+                        comment, expires
+                    )
+                })?,
+            )
+        }
+    };
+
+    let exclusion = Exclusion {
+        comment: comment,
+        detections: get_strings(&entry["detection"]),
+        channels: get_strings(&entry["channel"])
+            .into_iter()
+            .map(|s| s.to_lowercase())
+            .collect(),
+        event_ids: get_strings(&entry["event_id"]),
+        fields: fields,
+        expires: expires,
+        suppressed: 0,
+    };
+
+    // 条件のない除外設定は全てのアラートを消してしまうので受け付けない
+    if exclusion.detections.is_empty()
+        && exclusion.channels.is_empty()
+        && exclusion.event_ids.is_empty()
+        && exclusion.fields.is_empty()
+    {
+        return Result::Err(format!(
+            "no condition ({}). set detection, channel, event_id or fields.",
+            exclusion.comment
+        ));
+    }
+    return Result::Ok(exclusion);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::detections::exclusion;
+    use crate::detections::exclusion::ExclusionTarget;
+    use chrono::NaiveDate;
+    use std::collections::HashMap;
+
+    const EXCLUSIONS: &str = r#"
+exclusions:
+  - comment: Chrome renderer
+    detection: Suspicious Command Line
+    channel: Microsoft-Windows-Sysmon/Operational
+    event_id: [1, 4688]
+    fields:
+      Image: '\\chrome\.exe$'
+      ParentImage: '\\chrome\.exe$'
+  - comment: Backup service account
+    detection: [High number of logon failures for one account]
+    fields:
+      User: '^svc_backup$'
+    expires: 2021-12-31
+  - comment: Expired
+    detection: Suspicious Service Installed
+    expires: 2021-01-31
+  - comment: No condition
+  - comment: Invalid regex
+    fields:
+      Image: '(chrome'
+  - comment: Invalid date
+    detection: Suspicious Service Installed
+    expires: next week
+"#;
+
+    #[test]
+    fn test_parse_exclusions() {
+        let today = NaiveDate::from_ymd(2021, 8, 1);
+        let (list, errors) = exclusion::parse_exclusions(EXCLUSIONS, "test.yml", &today);
+        assert_eq!(2, list.exclusions.len());
+        assert_eq!(4, errors.len());
+        assert_eq!(
+            "test.yml: exclusion #3 (Expired) expired on 2021-01-31. it is not applied.",
+            errors[0]
+        );
+        assert!(errors[1].starts_with("test.yml: exclusion #4: no condition"));
+        assert!(errors[2].starts_with("test.yml: exclusion #5: invalid regex for Image"));
+        assert!(errors[3].starts_with("test.yml: exclusion #6: invalid expires"));
+
+        let (list, errors) = exclusion::parse_exclusions("exclusions: []\n", "empty.yml", &today);
+        assert!(list.is_empty());
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_check() {
+        let today = NaiveDate::from_ymd(2021, 8, 1);
+        let (mut list, _) = exclusion::parse_exclusions(EXCLUSIONS, "test.yml", &today);
+
+        let mut fields = HashMap::new();
+        fields.insert(
+            "NewProcessName".to_string(),
+            "C:\\Program Files\\Google\\Chrome\\Application\\chrome.exe".to_string(),
+        );
+        fields.insert(
+            "ParentProcessName".to_string(),
+            "C:\\Program Files\\Google\\Chrome\\Application\\chrome.exe".to_string(),
+        );
+        let mut target = ExclusionTarget {
+            detection: "Suspicious Command Line",
+            channel: "microsoft-windows-sysmon/operational",
+            event_id: "4688",
+            computer: "WS01",
+            fields: &fields,
+        };
+        // 4688のNewProcessNameとParentProcessNameはImageとParentImageとして扱う
+        assert_eq!(true, list.check(&target));
+
+        target.detection = "Suspicious Service Installed";
+        assert_eq!(false, list.check(&target));
+
+        let mut cmd_fields = fields.clone();
+        cmd_fields.insert(
+            "ParentProcessName".to_string(),
+            "C:\\Windows\\System32\\cmd.exe".to_string(),
+        );
+        target.detection = "Suspicious Command Line";
+        target.fields = &cmd_fields;
+        assert_eq!(false, list.check(&target));
+
+        let mut fields = HashMap::new();
+        fields.insert("TargetUserName".to_string(), "svc_backup".to_string());
+        let target = ExclusionTarget {
+            detection: "High number of logon failures for one account",
+            channel: "Security",
+            event_id: "4625",
+            computer: "DC01",
+            fields: &fields,
+        };
+        assert_eq!(true, list.check(&target));
+        assert_eq!(true, list.check(&target));
+
+        let report = list.create_report();
+        assert_eq!("         1  Chrome renderer", report[2]);
+        assert_eq!(
+            "         2  Backup service account (expires 2021-12-31)",
+            report[3]
+        );
+    }
+}
@@ -0,0 +1,260 @@
+extern crate chrono;
+
+use crate::detections::timestamp;
+use crate::models::event;
+use chrono::{DateTime, Utc};
+use clap::ArgMatches;
+use std::collections::HashSet;
+
+//
+// --start-time, --end-time, --event-id, --channelで検知対象のレコードを絞り込む
+//
+#[derive(Debug, Clone)]
+pub struct RecordFilter {
+    start_time: Option<DateTime<Utc>>,
+    end_time: Option<DateTime<Utc>>,
+    event_ids: HashSet<String>,
+    channels: HashSet<String>,
+}
+
+impl RecordFilter {
+    pub fn new() -> RecordFilter {
+        RecordFilter {
+            start_time: Option::None,
+            end_time: Option::None,
+            event_ids: HashSet::new(),
+            channels: HashSet::new(),
+        }
+    }
+
+    pub fn from_args(args: &ArgMatches) -> Result<RecordFilter, String> {
+        return RecordFilter::build(
+            args.value_of("start-time"),
+            args.value_of("end-time"),
+            args.value_of("event-id"),
+            args.value_of("channel"),
+        );
+    }
+
+    fn build(
+        start_time: Option<&str>,
+        end_time: Option<&str>,
+        event_ids: Option<&str>,
+        channels: Option<&str>,
+    ) -> Result<RecordFilter, String> {
+        let mut filter = RecordFilter::new();
+        if let Some(start_time) = start_time {
+            filter.start_time = Option::Some(timestamp::parse_arg_time(start_time)?);
+        }
+        if let Some(end_time) = end_time {
+            filter.end_time = Option::Some(timestamp::parse_arg_time(end_time)?);
+        }
+        if let (Some(start_time), Some(end_time)) = (filter.start_time, filter.end_time) {
+            if start_time > end_time {
+                return Result::Err(format!(
+                    "--start-time ({}) is later than --end-time ({})",
+                    start_time, end_time
+                ));
+            }
+        }
+
+        // カンマ区切りで複数指定できる
+        if let Some(event_ids) = event_ids {
+            for event_id in event_ids.split(",").map(|s| s.trim()) {
+                if event_id.is_empty() {
+                    continue;
+                }
+                if event_id.parse::<u32>().is_err() {
+                    return Result::Err(format!("invalid event id: {}", event_id));
+                }
+                filter.event_ids.insert(event_id.to_string());
+            }
+        }
+        if let Some(channels) = channels {
+            channels
+                .split(",")
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .for_each(|s| {
+                    filter.channels.insert(s.to_lowercase());
+                });
+        }
+
+        return Result::Ok(filter);
+    }
+
+    // TimeCreated/SystemTimeが--start-timeと--end-timeの範囲内か
+    // 期間が指定されている場合、日時が読み取れないレコードは対象外とする
+    pub fn is_in_time_range(&self, time_created: &Option<DateTime<Utc>>) -> bool {
+        if self.start_time.is_none() && self.end_time.is_none() {
+            return true;
+        }
+        if time_created.is_none() {
+            return false;
+        }
+        let time_created = time_created.unwrap();
+
+        if let Some(start_time) = self.start_time {
+            if time_created < start_time {
+                return false;
+            }
+        }
+        if let Some(end_time) = self.end_time {
+            if time_created > end_time {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    // EventIDとChannelが検知対象か
+    pub fn is_target_event(&self, system: &event::System) -> bool {
+        if !self.event_ids.is_empty() && !self.event_ids.contains(&system.event_id) {
+            return false;
+        }
+        if !self.channels.is_empty() && !self.channels.contains(&system.channel.to_lowercase()) {
+            return false;
+        }
+        return true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate quick_xml;
+
+    use crate::detections::filter::RecordFilter;
+    use crate::detections::timestamp;
+    use crate::models::event;
+
+    #[test]
+    fn test_is_in_time_range() {
+        let time_created = timestamp::parse_system_time("2019-04-30T19:27:00.2974504Z");
+
+        let filter = RecordFilter::new();
+        assert_eq!(true, filter.is_in_time_range(&time_created));
+
+        let filter = RecordFilter::build(
+            Option::Some("2019-04-30T00:00:00Z"),
+            Option::Some("2019-05-01T00:00:00Z"),
+            Option::None,
+            Option::None,
+        )
+        .unwrap();
+        assert_eq!(true, filter.is_in_time_range(&time_created));
+
+        // 2019-04-30T19:27:00Z is 2019-05-01T04:27:00+09:00
+        let filter = RecordFilter::build(
+            Option::Some("2019-05-01T05:00:00+09:00"),
+            Option::None,
+            Option::None,
+            Option::None,
+        )
+        .unwrap();
+        assert_eq!(false, filter.is_in_time_range(&time_created));
+
+        let filter = RecordFilter::build(
+            Option::None,
+            Option::Some("2019-04-30 19:00:00"),
+            Option::None,
+            Option::None,
+        )
+        .unwrap();
+        assert_eq!(false, filter.is_in_time_range(&time_created));
+
+        // 日時が読み取れないレコードは対象外
+        assert_eq!(false, filter.is_in_time_range(&Option::None));
+        assert_eq!(true, RecordFilter::new().is_in_time_range(&Option::None));
+
+        // evtx形式の日時も比較できる
+        let time_created = timestamp::parse_system_time("2019-04-30 19:27:00.297450 UTC");
+        let filter = RecordFilter::build(
+            Option::Some("2019-04-30T19:27:00Z"),
+            Option::Some("2019-04-30T19:28:00Z"),
+            Option::None,
+            Option::None,
+        )
+        .unwrap();
+        assert_eq!(true, filter.is_in_time_range(&time_created));
+    }
+
+    #[test]
+    fn test_is_target_event() {
+        let event: event::Evtx = quick_xml::de::from_str(&get_audit_log_cleared_xml()).unwrap();
+
+        let filter = RecordFilter::build(
+            Option::None,
+            Option::None,
+            Option::Some("4624, 1102"),
+            Option::Some("security"),
+        )
+        .unwrap();
+        assert_eq!(true, filter.is_target_event(&event.system));
+
+        let filter = RecordFilter::build(
+            Option::None,
+            Option::None,
+            Option::Some("4624"),
+            Option::None,
+        )
+        .unwrap();
+        assert_eq!(false, filter.is_target_event(&event.system));
+
+        let filter = RecordFilter::build(
+            Option::None,
+            Option::None,
+            Option::None,
+            Option::Some("System,Application"),
+        )
+        .unwrap();
+        assert_eq!(false, filter.is_target_event(&event.system));
+    }
+
+    #[test]
+    fn test_build_error() {
+        assert!(RecordFilter::build(
+            Option::Some("2019-05-01T00:00:00Z"),
+            Option::Some("2019-04-30T00:00:00Z"),
+            Option::None,
+            Option::None,
+        )
+        .is_err());
+        assert!(RecordFilter::build(
+            Option::Some("last week"),
+            Option::None,
+            Option::None,
+            Option::None,
+        )
+        .is_err());
+        assert!(RecordFilter::build(
+            Option::None,
+            Option::None,
+            Option::Some("4624,abc"),
+            Option::None,
+        )
+        .is_err());
+    }
+
+    fn get_audit_log_cleared_xml() -> String {
+        return r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>
+            <Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'>
+                <System>
+                    <Provider Name='Microsoft-Windows-Eventlog' Guid='{fc65ddd8-d6ef-4962-83d5-6e5cfe9ce148}'/>
+                    <EventID>1102</EventID>
+                    <Version>0</Version>
+                    <Level>4</Level>
+                    <Task>104</Task>
+                    <Opcode>0</Opcode>
+                    <Keywords>0x4020000000000000</Keywords>
+                    <TimeCreated SystemTime='2019-04-30T19:27:00.2974504Z'/>
+                    <EventRecordID>42803</EventRecordID>
+                    <Correlation/>
+                    <Execution ProcessID='1228' ThreadID='6280'/>
+                    <Channel>Security</Channel>
+                    <Computer>DESKTOP-AIUEO</Computer>
+                    <Security/>
+                </System>
+            </Event>"#
+            .to_string();
+    }
+}
@@ -0,0 +1,229 @@
+extern crate yaml_rust;
+
+use crate::detections::level::Level;
+use crate::detections::mitre;
+use regex::{Regex, RegexBuilder};
+use std::fs;
+use yaml_rust::{Yaml, YamlLoader};
+
+//
+// 検査するプロセスと親プロセス
+// 4688とSysmonの1以外のイベントでは空にする
+//
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ProcessInfo<'a> {
+    pub image: &'a str,
+    pub command_line: &'a str,
+    pub parent_image: &'a str,
+    pub parent_command_line: &'a str,
+}
+
+impl<'a> ProcessInfo<'a> {
+    // Imageがない場合はコマンドラインの先頭をファイル名として使う
+    fn image_name(&self) -> String {
+        let image = if self.image.is_empty() {
+            let command_line = self.command_line.trim_start();
+            if command_line.starts_with('"') {
+                command_line[1..].split('"').next().unwrap_or("")
+            } else {
+                command_line.split_whitespace().next().unwrap_or("")
+            }
+        } else {
+            self.image
+        };
+        let mut name = basename(image).to_lowercase();
+        if !name.is_empty() && !name.contains('.') {
+            name.push_str(".exe");
+        }
+        return name;
+    }
+}
+
+//
+// process_heuristics.ymlの親子関係のヒューリスティック1件分
+// ファイル名は大文字小文字を区別せずに比較する
+//
+#[derive(Debug, Clone)]
+pub struct ProcessHeuristic {
+    pub description: String,
+    // 小文字のファイル名
+    parents: Vec<String>,
+    // 空の場合は全ての子プロセス
+    children: Vec<String>,
+    parent_command_line: Option<Regex>,
+    pub level: Option<Level>,
+    pub techniques: Vec<String>,
+}
+
+impl ProcessHeuristic {
+    pub fn is_match(&self, process: &ProcessInfo) -> bool {
+        let parent = basename(process.parent_image).to_lowercase();
+        if parent.is_empty() || !self.parents.contains(&parent) {
+            return false;
+        }
+        if !self.children.is_empty() && !self.children.contains(&process.image_name()) {
+            return false;
+        }
+        return self
+            .parent_command_line
+            .as_ref()
+            .map(|regex| regex.is_match(process.parent_command_line))
+            .unwrap_or(true);
+    }
+}
+
+// C:\Windows\System32\cmd.exe -> cmd.exe
+pub fn basename(path: &str) -> &str {
+    return path
+        .rsplit(|c| c == '\\' || c == '/')
+        .next()
+        .unwrap_or(path);
+}
+
+//
+// ヒューリスティックのファイル(YAML)を読み込む
+// 読み込めなかったものは飛ばして、エラーの内容を返す
+//
+pub fn read_heuristics(path: &str) -> (Vec<ProcessHeuristic>, Vec<String>) {
+    return match fs::read_to_string(path) {
+        Ok(contents) => parse_heuristics(&contents, path),
+        Err(e) => (vec![], vec![format!("{} not found , {}", path, e)]),
+    };
+}
+
+pub fn parse_heuristics(contents: &str, path: &str) -> (Vec<ProcessHeuristic>, Vec<String>) {
+    let yaml = match YamlLoader::load_from_str(contents) {
+        Ok(yaml) => yaml,
+        Err(e) => return (vec![], vec![format!("{}: fail to read file, {}", path, e)]),
+    };
+    let entries = match yaml.get(0).and_then(|doc| doc["heuristics"].as_vec()) {
+        Some(entries) => entries,
+        None => return (vec![], vec![format!("{}: heuristics not found", path)]),
+    };
+
+    let mut heuristics = vec![];
+    let mut errors = vec![];
+    for (i, entry) in entries.iter().enumerate() {
+        match parse_heuristic(entry) {
+            Ok(heuristic) => heuristics.push(heuristic),
+            Err(e) => errors.push(format!("{}: heuristic #{}: {}", path, i + 1, e)),
+        }
+    }
+    return (heuristics, errors);
+}
+
+// 1つでもリストでも書けるようにする
+fn get_names(yaml: &Yaml) -> Vec<String> {
+    return match yaml {
+        Yaml::String(name) => vec![name.to_lowercase()],
+        Yaml::Array(names) => names
+            .iter()
+            .filter_map(|name| name.as_str())
+            .map(|name| name.to_lowercase())
+            .collect(),
+        _ => vec![],
+    };
+}
+
+fn parse_heuristic(entry: &Yaml) -> Result<ProcessHeuristic, String> {
+    let description = entry["description"]
+        .as_str()
+        .filter(|s| !s.is_empty())
+        .ok_or("description is required")?;
+    let parents = get_names(&entry["parent"]);
+    if parents.is_empty() {
+        return Result::Err(format!("parent is required ({})", description));
+    }
+    let level = match entry["level"].as_str() {
+        Some(level) => {
+            Option::Some(Level::from_str(level).map_err(|e| format!("{} ({})", e, description))?)
+        }
+        None => Option::None,
+    };
+    let parent_command_line = match entry["parent_command_line"].as_str() {
+        Some(pattern) => Option::Some(
+            RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| format!("invalid regex ({}), {}", description, e))?,
+        ),
+        None => Option::None,
+    };
+
+    return Result::Ok(ProcessHeuristic {
+        description: description.to_string(),
+        parents: parents,
+        children: get_names(&entry["child"]),
+        parent_command_line: parent_command_line,
+        level: level,
+        techniques: mitre::techniques_of_rule(entry),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::detections::heuristic;
+    use crate::detections::heuristic::ProcessInfo;
+    use crate::detections::level::Level;
+
+    #[test]
+    fn test_parse_heuristics() {
+        let contents = r#"
+heuristics:
+  - description: Shell launched by a scheduled task
+    parent: [taskeng.exe, svchost.exe]
+    parent_command_line: '-k netsvcs'
+    child: [cmd.exe, powershell.exe]
+    level: medium
+    tags: [attack.execution, attack.t1053.005]
+  - description: Any child of mshta
+    parent: MSHTA.EXE
+  - parent: winword.exe
+  - description: Invalid level
+    parent: winword.exe
+    level: severe
+"#;
+        let (heuristics, errors) = heuristic::parse_heuristics(contents, "test.yml");
+        assert_eq!(2, heuristics.len());
+        assert_eq!(
+            vec![
+                "test.yml: heuristic #3: description is required",
+                "test.yml: heuristic #4: invalid level: severe. use informational, low, medium, high or critical. (Invalid level)",
+            ],
+            errors
+        );
+        assert_eq!(Option::Some(Level::Medium), heuristics[0].level);
+        assert_eq!(vec!["T1053.005"], heuristics[0].techniques);
+
+        // 4688は親のコマンドラインがないので、子のコマンドラインからファイル名を取る
+        let mut process = ProcessInfo {
+            command_line: "\"PowerShell\" -nop -w hidden",
+            parent_image: "C:\\Windows\\System32\\svchost.exe",
+            parent_command_line: "C:\\Windows\\system32\\svchost.exe -k netsvcs -p -s Schedule",
+            ..Default::default()
+        };
+        assert!(heuristics[0].is_match(&process));
+        process.parent_command_line = "C:\\Windows\\system32\\svchost.exe -k DcomLaunch -p";
+        assert!(!heuristics[0].is_match(&process));
+
+        let process = ProcessInfo {
+            image: "C:\\Windows\\System32\\rundll32.exe",
+            parent_image: "c:\\windows\\system32\\mshta.exe",
+            ..Default::default()
+        };
+        assert!(heuristics[1].is_match(&process));
+        assert!(!heuristics[0].is_match(&process));
+    }
+
+    #[test]
+    fn test_read_heuristics() {
+        let (heuristics, errors) = heuristic::read_heuristics("process_heuristics.yml");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let process = ProcessInfo {
+            image: "C:\\Windows\\System32\\WindowsPowerShell\\v1.0\\powershell.exe",
+            parent_image: "C:\\Windows\\PSEXESVC.exe",
+            ..Default::default()
+        };
+        assert!(heuristics.iter().any(|h| h.is_match(&process)));
+    }
+}
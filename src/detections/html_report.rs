@@ -0,0 +1,627 @@
+extern crate chrono;
+
+use crate::detections::level::Level;
+use crate::detections::mitre;
+use crate::detections::print::Alert;
+use crate::detections::summary;
+use crate::detections::summary::FileSummary;
+use crate::detections::tampering;
+use crate::detections::tampering::{RecordGap, TamperEvent};
+use crate::detections::timestamp::TimeFormatter;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::fs;
+
+const STYLE: &str = r#"
+body { font-family: Segoe UI, Meiryo, sans-serif; margin: 2em; color: #222; }
+h1 { border-bottom: 2px solid #b22; }
+h2 { margin-top: 2em; border-bottom: 1px solid #ccc; }
+table { border-collapse: collapse; margin: 0.5em 0; }
+th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: left; vertical-align: top; }
+th { background: #f0f0f0; }
+th.sortable { cursor: pointer; }
+td.num { text-align: right; }
+td.details { font-family: Consolas, monospace; font-size: 0.9em; white-space: pre-wrap; word-break: break-all; }
+.bar { background: #b22; height: 1em; }
+.critical { background: #f4c7c3; font-weight: bold; }
+.high { background: #fce8b2; }
+.medium { background: #fff8d6; }
+#alert-filter { width: 40em; padding: 4px; }
+"#;
+
+const SCRIPT: &str = r#"
+function sortTable(th) {
+  var table = th.closest('table');
+  var index = Array.prototype.indexOf.call(th.parentNode.children, th);
+  var tbody = table.tBodies[0];
+  var rows = Array.prototype.slice.call(tbody.rows);
+  var asc = th.getAttribute('data-order') !== 'asc';
+  th.setAttribute('data-order', asc ? 'asc' : 'desc');
+  rows.sort(function (a, b) {
+    var x = a.cells[index].getAttribute('data-sort') || a.cells[index].textContent;
+    var y = b.cells[index].getAttribute('data-sort') || b.cells[index].textContent;
+    var nx = Number(x), ny = Number(y);
+    var r = (x !== '' && y !== '' && !isNaN(nx) && !isNaN(ny)) ? nx - ny : x.localeCompare(y);
+    return asc ? r : -r;
+  });
+  rows.forEach(function (row) { tbody.appendChild(row); });
+}
+function filterTable(input, id) {
+  var words = input.value.toLowerCase().split(/\s+/).filter(function (w) { return w; });
+  Array.prototype.forEach.call(document.getElementById(id).tBodies[0].rows, function (row) {
+    var text = row.textContent.toLowerCase();
+    row.style.display = words.every(function (w) { return text.indexOf(w) >= 0; }) ? '' : 'none';
+  });
+}
+"#;
+
+pub fn write_html_report(
+    path: &str,
+    summaries: &Vec<FileSummary>,
+    alerts: &Vec<Alert>,
+    time_formatter: &TimeFormatter,
+) -> Result<(), String> {
+    let html = create_html_report(summaries, alerts, time_formatter);
+    return fs::write(path, html).map_err(|e| format!("cannot write {}, {}", path, e));
+}
+
+//
+// 外部のファイルを参照しない1ファイルのHTMLレポートを作成する
+//
+pub fn create_html_report(
+    summaries: &Vec<FileSummary>,
+    alerts: &Vec<Alert>,
+    time_formatter: &TimeFormatter,
+) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>RustyBlue Report</title>\n");
+    html.push_str(&format!("<style>{}</style>\n", STYLE));
+    html.push_str(&format!("<script>{}</script>\n", SCRIPT));
+    html.push_str("</head>\n<body>\n<h1>RustyBlue Report</h1>\n");
+    html.push_str(&format!(
+        "<p>Files: {} / Alerts: {}</p>\n",
+        summaries.len(),
+        alerts.len()
+    ));
+
+    html.push_str(&create_summary_section(summaries, alerts, time_formatter));
+    html.push_str(&create_timeline_section(alerts, time_formatter));
+    html.push_str(&create_alert_section(alerts, time_formatter));
+    html.push_str(&create_detection_section(alerts, time_formatter));
+    html.push_str(&create_attack_section(alerts));
+    html.push_str(&create_security_section(summaries));
+    html.push_str(&create_anti_forensics_section(summaries, time_formatter));
+
+    html.push_str("</body>\n</html>\n");
+    return html;
+}
+
+fn escape(s: &str) -> String {
+    return s
+        .replace("&", "&amp;")
+        .replace("<", "&lt;")
+        .replace(">", "&gt;")
+        .replace("\"", "&quot;")
+        .replace("'", "&#39;");
+}
+
+fn format_time(time: &Option<DateTime<Utc>>, time_formatter: &TimeFormatter) -> String {
+    return time
+        .map(|time| time_formatter.format(&time))
+        .unwrap_or("-".to_string());
+}
+
+// 表示形式に関わらず日時順で並び替えられるように、data-sortにはRFC3339(UTC)を入れる
+fn time_cell(time: &Option<DateTime<Utc>>, time_formatter: &TimeFormatter) -> String {
+    let sort_key = time.map(|time| time.to_rfc3339()).unwrap_or("".to_string());
+    return format!(
+        "<td data-sort=\"{}\">{}</td>",
+        sort_key,
+        escape(&format_time(time, time_formatter))
+    );
+}
+
+fn sortable_header(columns: &[&str]) -> String {
+    let ths: Vec<String> = columns
+        .iter()
+        .map(|column| {
+            format!(
+                "<th class=\"sortable\" onclick=\"sortTable(this)\">{}</th>",
+                column
+            )
+        })
+        .collect();
+    return format!("<thead><tr>{}</tr></thead>\n", ths.join(""));
+}
+
+fn create_summary_section(
+    summaries: &Vec<FileSummary>,
+    alerts: &Vec<Alert>,
+    time_formatter: &TimeFormatter,
+) -> String {
+    let alert_stats = summary::create_alert_stats(alerts);
+
+    let mut html = String::new();
+    html.push_str("<h2>Summary</h2>\n<table id=\"summary\">\n");
+    html.push_str(&sortable_header(&[
+        "Risk",
+        "Computer",
+        "File",
+        "Alerts",
+        "Records",
+        "First record",
+        "Last record",
+        "Parse errors",
+    ]));
+    html.push_str("<tbody>\n");
+    summaries.iter().for_each(|file| {
+        file.hosts.iter().for_each(|(computer, host)| {
+            let (risk_score, total) = alert_stats
+                .get(&(file.filepath.to_string(), computer.to_string()))
+                .map(|stats| (stats.risk_score, stats.total))
+                .unwrap_or((0, 0));
+            html.push_str(&format!(
+                "<tr><td class=\"num\">{}</td><td>{}</td><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td>{}{}<td class=\"num\">{}</td></tr>\n",
+                risk_score,
+                escape(computer),
+                escape(&file.filepath),
+                total,
+                host.total_records,
+                time_cell(&host.first_time, time_formatter),
+                time_cell(&host.last_time, time_formatter),
+                file.parse_errors
+            ));
+        });
+    });
+    html.push_str("</tbody>\n</table>\n");
+
+    // Channel/EventID毎のレコード数は量が多いので折りたたむ
+    summaries.iter().for_each(|file| {
+        file.hosts.iter().for_each(|(computer, host)| {
+            html.push_str(&format!(
+                "<details><summary>Records per Channel/EventID: {} ({})</summary>\n<table>\n",
+                escape(computer),
+                escape(&file.filepath)
+            ));
+            html.push_str("<tr><th>Channel</th><th>EventID</th><th>Records</th></tr>\n");
+            host.event_counts
+                .iter()
+                .for_each(|((channel, event_id), count)| {
+                    html.push_str(&format!(
+                        "<tr><td>{}</td><td>{}</td><td class=\"num\">{}</td></tr>\n",
+                        escape(channel),
+                        escape(event_id),
+                        count
+                    ));
+                });
+            html.push_str("</table>\n</details>\n");
+        });
+    });
+    return html;
+}
+
+//
+// アラートの時系列ヒストグラム
+// 期間が2日以内なら1時間毎、60日以内なら1日毎、それ以上は1週間毎に集計する
+// 件数と合わせて、その期間のリスクスコア(重要度の重みの合計)も表示する
+//
+fn create_timeline_section(alerts: &Vec<Alert>, time_formatter: &TimeFormatter) -> String {
+    let mut html = String::new();
+    html.push_str("<h2>Timeline</h2>\n");
+
+    let times: Vec<(DateTime<Utc>, Level)> = alerts
+        .iter()
+        .filter_map(|alert| alert.time.map(|time| (time, alert.level)))
+        .collect();
+    if times.is_empty() {
+        html.push_str("<p>No alerts with a timestamp.</p>\n");
+        return html;
+    }
+
+    let min = times.iter().map(|(time, _)| time).min().unwrap();
+    let max = times.iter().map(|(time, _)| time).max().unwrap();
+    let span = *max - *min;
+    let bucket = if span <= Duration::days(2) {
+        Duration::hours(1)
+    } else if span <= Duration::days(60) {
+        Duration::days(1)
+    } else {
+        Duration::weeks(1)
+    };
+
+    let bucket_secs = bucket.num_seconds();
+    // value: (件数, リスクスコア)
+    let mut buckets: BTreeMap<i64, (u64, u64)> = BTreeMap::new();
+    times.iter().for_each(|(time, level)| {
+        let start = time.timestamp().div_euclid(bucket_secs) * bucket_secs;
+        let bucket = buckets.entry(start).or_insert((0, 0));
+        bucket.0 += 1;
+        bucket.1 += level.score();
+    });
+    let max_count = buckets.values().map(|(count, _)| *count).max().unwrap_or(1);
+
+    html.push_str(&format!(
+        "<p>Alerts per {}</p>\n<table id=\"timeline\">\n<tr><th>Date</th><th>Alerts</th><th>Risk</th><th></th></tr>\n",
+        if bucket == Duration::hours(1) {
+            "hour"
+        } else if bucket == Duration::days(1) {
+            "day"
+        } else {
+            "week"
+        }
+    ));
+    let first = *buckets.keys().next().unwrap();
+    let last = *buckets.keys().last().unwrap();
+    let mut start = first;
+    // 検知がない期間も空の行として表示する
+    while start <= last {
+        let (count, risk_score) = *buckets.get(&start).unwrap_or(&(0, 0));
+        html.push_str(&format!(
+            "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td style=\"width: 30em\"><div class=\"bar\" style=\"width: {}%\"></div></td></tr>\n",
+            escape(&time_formatter.format(&Utc.timestamp(start, 0))),
+            count,
+            risk_score,
+            count * 100 / max_count
+        ));
+        start += bucket_secs;
+    }
+    html.push_str("</table>\n");
+    return html;
+}
+
+// 重要度の高い順、同じ重要度の中では日時順に並べる
+fn sort_alerts(alerts: &Vec<Alert>) -> Vec<&Alert> {
+    let mut sorted: Vec<&Alert> = alerts.iter().collect();
+    sorted.sort_by(|a, b| b.level.cmp(&a.level).then(a.time.cmp(&b.time)));
+    return sorted;
+}
+
+fn level_cell(level: &Level) -> String {
+    return format!(
+        "<td class=\"{}\" data-sort=\"{}\">{}</td>",
+        level, *level as u8, level
+    );
+}
+
+fn create_alert_section(alerts: &Vec<Alert>, time_formatter: &TimeFormatter) -> String {
+    let mut html = String::new();
+    html.push_str("<h2>Alerts</h2>\n");
+    html.push_str("<p><input id=\"alert-filter\" type=\"text\" placeholder=\"Filter (e.g. 4688 powershell)\" onkeyup=\"filterTable(this, 'alerts')\"></p>\n");
+    html.push_str("<table id=\"alerts\">\n");
+    html.push_str(&sortable_header(&[
+        "Level",
+        "Date",
+        "Computer",
+        "Channel",
+        "EventID",
+        "Detection",
+        "ATT&amp;CK",
+        "Details",
+    ]));
+    html.push_str("<tbody>\n");
+    sort_alerts(alerts).iter().for_each(|alert| {
+        html.push_str(&format!(
+            "<tr>{}{}<td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"details\">{}</td></tr>\n",
+            level_cell(&alert.level),
+            time_cell(&alert.time, time_formatter),
+            escape(&alert.computer),
+            escape(&alert.channel),
+            escape(&alert.event_id),
+            escape(&alert.title),
+            escape(&alert.techniques.join(", ")),
+            escape(&alert.details.join("\n").trim_end())
+        ));
+    });
+    html.push_str("</tbody>\n</table>\n");
+    return html;
+}
+
+fn create_detection_section(alerts: &Vec<Alert>, time_formatter: &TimeFormatter) -> String {
+    // key: (重要度, 検知名)
+    let mut detections: BTreeMap<(Level, &str), Vec<&Alert>> = BTreeMap::new();
+    sort_alerts(alerts).into_iter().for_each(|alert| {
+        detections
+            .entry((alert.level, &alert.title))
+            .or_insert_with(Vec::new)
+            .push(alert);
+    });
+
+    let mut html = String::new();
+    html.push_str("<h2>Detections</h2>\n");
+    detections
+        .iter()
+        .rev()
+        .for_each(|((level, title), alerts)| {
+            html.push_str(&format!(
+                "<h3 class=\"{}\">[{}] {} ({})</h3>\n<table>\n",
+                level,
+                level,
+                escape(title),
+                alerts.len()
+            ));
+            html.push_str("<tr><th>Date</th><th>Computer</th><th>Details</th></tr>\n");
+            alerts.iter().for_each(|alert| {
+                html.push_str(&format!(
+                    "<tr>{}<td>{}</td><td class=\"details\">{}</td></tr>\n",
+                    time_cell(&alert.time, time_formatter),
+                    escape(&alert.computer),
+                    escape(&alert.details.join("\n").trim_end())
+                ));
+            });
+            html.push_str("</table>\n");
+        });
+    return html;
+}
+
+// 報告書の構成に合わせて、検知したテクニックを戦術毎に表示する
+fn create_attack_section(alerts: &Vec<Alert>) -> String {
+    let mut html = String::new();
+    html.push_str("<h2>MITRE ATT&amp;CK</h2>\n");
+    let tactics = mitre::group_by_tactic(alerts);
+    if tactics.is_empty() {
+        html.push_str("<p>No techniques detected.</p>\n");
+        return html;
+    }
+    html.push_str("<table id=\"attack\">\n<tr><th>Tactic</th><th>Technique</th><th>Name</th><th>Alerts</th></tr>\n");
+    tactics.values().for_each(|(tactic, techniques)| {
+        techniques.iter().for_each(|(technique, count)| {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"num\">{}</td></tr>\n",
+                tactic,
+                escape(technique),
+                mitre::technique_name(technique),
+                count
+            ));
+        });
+    });
+    html.push_str("</table>\n");
+    return html;
+}
+
+// Security::dispの集計値(管理者ログオン、アカウント毎のログオン失敗、複数のSIDを持つユーザー)
+fn create_security_section(summaries: &Vec<FileSummary>) -> String {
+    let mut html = String::new();
+    html.push_str("<h2>Security log aggregates</h2>\n");
+    summaries.iter().for_each(|file| {
+        let security = &file.security;
+        if security.total_admin_logons == 0 && security.total_failed_logons == 0 {
+            return;
+        }
+        html.push_str(&format!("<h3>{}</h3>\n", escape(&file.filepath)));
+        html.push_str(&format!(
+            "<p>Total admin logons (4672 SeDebugPrivilege): {} / Total logon failures (4625): {}</p>\n",
+            security.total_admin_logons, security.total_failed_logons
+        ));
+
+        if !security.admin_logons.is_empty() {
+            html.push_str("<h4>Admin logons</h4>\n<table>\n");
+            html.push_str(&sortable_header(&["Username", "SID count", "SIDs"]));
+            html.push_str("<tbody>\n");
+            security.admin_logons.iter().for_each(|(username, sids)| {
+                let sids: Vec<&str> = sids.iter().map(|sid| sid.as_str()).collect();
+                html.push_str(&format!(
+                    "<tr><td>{}</td><td class=\"num\">{}</td><td>{}</td></tr>\n",
+                    escape(username),
+                    sids.len(),
+                    escape(&sids.join(", "))
+                ));
+            });
+            html.push_str("</tbody>\n</table>\n");
+        }
+
+        let multiple_sids: Vec<(&String, usize)> = security
+            .admin_logons
+            .iter()
+            .filter(|(_, sids)| sids.len() > 1)
+            .map(|(username, sids)| (username, sids.len()))
+            .collect();
+        if !multiple_sids.is_empty() {
+            html.push_str("<h4>Users with multiple SIDs</h4>\n<ul>\n");
+            multiple_sids.iter().for_each(|(username, cnt)| {
+                html.push_str(&format!("<li>{} ({} SIDs)</li>\n", escape(username), cnt));
+            });
+            html.push_str("</ul>\n");
+        }
+
+        if !security.failed_logons.is_empty() {
+            html.push_str("<h4>Failed logons per account</h4>\n<table>\n");
+            html.push_str(&sortable_header(&["Username", "Failed logons"]));
+            html.push_str("<tbody>\n");
+            security.failed_logons.iter().for_each(|(username, cnt)| {
+                html.push_str(&format!(
+                    "<tr><td>{}</td><td class=\"num\">{}</td></tr>\n",
+                    escape(username),
+                    cnt
+                ));
+            });
+            html.push_str("</tbody>\n</table>\n");
+        }
+    });
+    return html;
+}
+
+// 改ざんの痕跡とRecord IDの欠落を日時順に並べ、欠落には前後の痕跡を添える
+fn create_anti_forensics_section(
+    summaries: &Vec<FileSummary>,
+    time_formatter: &TimeFormatter,
+) -> String {
+    let events: Vec<&TamperEvent> = summaries
+        .iter()
+        .flat_map(|file| file.anti_forensics.iter())
+        .collect();
+    let gaps: Vec<&RecordGap> = summaries
+        .iter()
+        .flat_map(|file| file.record_gaps.iter())
+        .collect();
+    if events.is_empty() && gaps.is_empty() {
+        return String::new();
+    }
+
+    // (日時, Computer, Channel, EventID, 内容, 関連する痕跡)
+    let window = tampering::correlation_window();
+    let mut rows: Vec<(Option<DateTime<Utc>>, &str, &str, &str, String, Vec<String>)> = events
+        .iter()
+        .map(|event| {
+            (
+                event.time,
+                event.computer.as_str(),
+                event.channel.as_str(),
+                event.event_id.as_str(),
+                event.description.to_string(),
+                vec![],
+            )
+        })
+        .collect();
+    gaps.iter().for_each(|gap| {
+        let related = tampering::correlate(gap, &events, window)
+            .iter()
+            .map(|event| format!("{} {}", event.event_id, event.description))
+            .collect();
+        rows.push((
+            gap.from_time,
+            gap.computer.as_str(),
+            gap.channel.as_str(),
+            "-",
+            format!(
+                "Record ID gap {} - {} ({} missing) until {}",
+                gap.from_record,
+                gap.to_record,
+                gap.missing(),
+                format_time(&gap.to_time, time_formatter)
+            ),
+            related,
+        ));
+    });
+    rows.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(b.1)));
+
+    let mut html = String::new();
+    html.push_str("<h2>Anti-Forensics</h2>\n<table>\n");
+    html.push_str(&sortable_header(&[
+        "Time",
+        "Computer",
+        "Channel",
+        "EventID",
+        "Description",
+        "Related",
+    ]));
+    html.push_str("<tbody>\n");
+    rows.iter().for_each(
+        |(time, computer, channel, event_id, description, related)| {
+            html.push_str(&format!(
+                "<tr>{}<td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                time_cell(time, time_formatter),
+                escape(computer),
+                escape(channel),
+                escape(event_id),
+                escape(description),
+                escape(&related.join(" / "))
+            ));
+        },
+    );
+    html.push_str("</tbody>\n</table>\n");
+    return html;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::detections::html_report;
+    use crate::detections::level::Level;
+    use crate::detections::mitre;
+    use crate::detections::print::Alert;
+    use crate::detections::summary::FileSummary;
+    use crate::detections::tampering::{RecordGap, TamperEvent};
+    use crate::detections::timestamp;
+    use chrono::{TimeZone, Utc};
+    use std::collections::BTreeSet;
+
+    fn get_alert(title: &str, hour: u32) -> Alert {
+        return Alert {
+            filepath: "security.evtx".to_string(),
+            computer: "DC01".to_string(),
+            channel: "Security".to_string(),
+            event_id: "4688".to_string(),
+            time: Option::Some(Utc.ymd(2021, 8, 1).and_hms(hour, 0, 0)),
+            title: title.to_string(),
+            level: Level::of_detection(title),
+            techniques: mitre::techniques_of_detection(title),
+            details: vec![
+                format!("Message: {}", title),
+                "command: <script>".to_string(),
+            ],
+        };
+    }
+
+    #[test]
+    fn test_create_html_report() {
+        let mut file_summary = FileSummary::new("security.evtx");
+        let mut sids = BTreeSet::new();
+        sids.insert("S-1-5-21-1".to_string());
+        sids.insert("S-1-5-21-2".to_string());
+        file_summary
+            .security
+            .admin_logons
+            .insert("admin".to_string(), sids);
+        file_summary.security.total_admin_logons = 2;
+        file_summary
+            .security
+            .failed_logons
+            .insert("guest".to_string(), 12);
+        file_summary.security.total_failed_logons = 12;
+        file_summary.anti_forensics.push(TamperEvent {
+            computer: "DC01".to_string(),
+            channel: "Security".to_string(),
+            event_id: "1102".to_string(),
+            time: Option::Some(Utc.ymd(2021, 8, 1).and_hms(3, 0, 0)),
+            description: "Security log cleared".to_string(),
+        });
+        file_summary.record_gaps.push(RecordGap {
+            computer: "DC01".to_string(),
+            channel: "Security".to_string(),
+            from_record: 10,
+            to_record: 20,
+            from_time: Option::Some(Utc.ymd(2021, 8, 1).and_hms(2, 55, 0)),
+            to_time: Option::Some(Utc.ymd(2021, 8, 1).and_hms(3, 0, 0)),
+        });
+
+        let alerts = vec![
+            get_alert("Suspicious Command Line", 1),
+            get_alert("Suspicious Command Line", 1),
+            get_alert("New User Created", 4),
+        ];
+        let html = html_report::create_html_report(
+            &vec![file_summary],
+            &alerts,
+            &timestamp::TimeFormatter::new(),
+        );
+
+        // 値はエスケープされる
+        assert!(html.contains("command: &lt;script&gt;"));
+        assert!(!html.contains("command: <script>"));
+        assert!(html.contains("<h3 class=\"high\">[high] Suspicious Command Line (2)</h3>"));
+        // 重要度の高い検知が先に表示される
+        assert!(
+            html.find("[high] Suspicious Command Line").unwrap()
+                < html.find("[medium] New User Created").unwrap()
+        );
+        assert!(html.contains("<li>admin (2 SIDs)</li>"));
+        assert!(html.contains("<tr><td>guest</td><td class=\"num\">12</td></tr>"));
+        // Record IDの欠落には前後の痕跡が添えられる
+        assert!(html.contains("<h2>Anti-Forensics</h2>"));
+        assert!(html.contains("<td>Record ID gap 10 - 20 (9 missing) until 2021-08-01T03:00:00.000000Z</td><td>1102 Security log cleared</td>"));
+        // 1時間毎に集計され、検知がない時間帯も表示される
+        assert!(html.contains("<p>Alerts per hour</p>"));
+        assert!(html.contains("<tr><td>Persistence</td><td>T1136.001</td><td>Create Account: Local Account</td><td class=\"num\">1</td></tr>"));
+        assert!(html.contains("<tr><td>2021-08-01T01:00:00.000000Z</td><td class=\"num\">2</td><td class=\"num\">40</td>"));
+        assert!(html.contains("<tr><td>2021-08-01T02:00:00.000000Z</td><td class=\"num\">0</td><td class=\"num\">0</td>"));
+        assert!(html.contains("<tr><td>2021-08-01T04:00:00.000000Z</td><td class=\"num\">1</td><td class=\"num\">5</td>"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;",
+            html_report::escape("<a href=\"x\">&'")
+        );
+    }
+}
@@ -0,0 +1,579 @@
+use crate::detections::configs;
+use crate::detections::print::AlertMessages;
+use crate::models::event;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+// 同じユーザー名でもSIDが変わらない、またはホスト毎に変わるアカウント
+// (SYSTEM, LOCAL SERVICE, NETWORK SERVICE, 匿名ログオン, NULL SID)
+const IGNORE_SIDS: &[&str] = &["S-1-5-18", "S-1-5-19", "S-1-5-20", "S-1-5-7", "S-1-0-0"];
+
+//
+// ユーザー名に紐づくSID1件分の、初めてと最後に見た日時
+//
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SidSighting {
+    domain: String,
+    first_seen: String,
+    last_seen: String,
+    event_ids: BTreeSet<String>,
+}
+
+//
+// --stateで次回の実行に引き継ぐ、ユーザー名とSIDの組み合わせの集計
+//
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IdentityAggregate {
+    // key: ユーザー名(小文字), value: (ユーザー名, key: SID)
+    users: BTreeMap<String, (String, BTreeMap<String, SidSighting>)>,
+}
+
+//
+// 4624, 4672, 4688のアカウントを集計し、ユーザー名とSIDの不整合、ビルトインのAdministratorの使用、
+// 作成直後のアカウントのログオンを検知する
+//
+pub struct Identity {
+    // key: ユーザー名(小文字), value: (ユーザー名, key: SID)
+    users: BTreeMap<String, (String, BTreeMap<String, SidSighting>)>,
+    // key: SID, value: (ユーザー名, 作成日時(表示用), 作成日時)
+    created_accounts: HashMap<String, (String, String, Option<DateTime<Utc>>)>,
+    // 一度出力したSID
+    alerted_sids: HashSet<String>,
+}
+
+impl Identity {
+    pub fn new() -> Identity {
+        Identity {
+            users: BTreeMap::new(),
+            created_accounts: HashMap::new(),
+            alerted_sids: HashSet::new(),
+        }
+    }
+
+    pub fn detection(
+        &mut self,
+        channel: &str,
+        event_id: &str,
+        system: &event::System,
+        event_data: &HashMap<String, String>,
+        time: &Option<DateTime<Utc>>,
+    ) {
+        if channel != "Security" {
+            return;
+        }
+
+        let default = String::from("");
+        let get = |key: &str| event_data.get(key).unwrap_or(&default).to_string();
+        let system_time = &system.time_created.system_time;
+        if event_id == "4720" {
+            self.created_accounts.insert(
+                get("TargetSid"),
+                (get("TargetUserName"), system_time.to_string(), *time),
+            );
+            return;
+        }
+
+        // 4624はログオンしたアカウント、4672と4688は操作したアカウント
+        let (username, sid, domain) = match event_id {
+            "4624" => (
+                get("TargetUserName"),
+                get("TargetUserSid"),
+                get("TargetDomainName"),
+            ),
+            "4672" | "4688" => (
+                get("SubjectUserName"),
+                get("SubjectUserSid"),
+                get("SubjectDomainName"),
+            ),
+            _ => return,
+        };
+        if !self.add_sighting(event_id, &username, &sid, &domain, system_time) {
+            return;
+        }
+
+        self.builtin_administrator(event_id, &username, &sid, event_data, system_time)
+            .and_then(Identity::print_console);
+        if event_id == "4624" {
+            self.new_account_logon(&username, &sid, event_data, time, system_time)
+                .and_then(Identity::print_console);
+        }
+    }
+
+    pub fn disp(&self) {
+        self.aggregate_alerts().into_iter().for_each(|msges| {
+            Identity::print_console(msges);
+        });
+    }
+
+    pub fn aggregate_alerts(&self) -> Vec<Vec<String>> {
+        return self.multiple_sids();
+    }
+
+    pub fn aggregate(&self) -> IdentityAggregate {
+        return IdentityAggregate {
+            users: self.users.clone(),
+        };
+    }
+
+    // --stateで続きから処理する場合に、前回までに見たSIDから数える
+    pub fn resume(&mut self, aggregate: &IdentityAggregate) {
+        self.users = aggregate.users.clone();
+    }
+
+    fn print_console(v: Vec<String>) -> Option<Vec<String>> {
+        return AlertMessages::output(v);
+    }
+
+    // ユーザー名とSIDの組み合わせを記録する。対象外のアカウントはfalse
+    fn add_sighting(
+        &mut self,
+        event_id: &str,
+        username: &str,
+        sid: &str,
+        domain: &str,
+        system_time: &str,
+    ) -> bool {
+        // コンピューターアカウントはローカルではSYSTEMのSIDで記録されるので対象外
+        if username.is_empty() || username == "-" || username.ends_with('$') {
+            return false;
+        }
+        if sid.is_empty() || sid == "-" || IGNORE_SIDS.contains(&sid) {
+            return false;
+        }
+
+        let (_, sids) = self
+            .users
+            .entry(username.to_lowercase())
+            .or_insert_with(|| (username.to_string(), BTreeMap::new()));
+        let sighting = sids.entry(sid.to_string()).or_insert_with(|| SidSighting {
+            domain: domain.to_string(),
+            first_seen: system_time.to_string(),
+            last_seen: system_time.to_string(),
+            event_ids: BTreeSet::new(),
+        });
+        sighting.last_seen = system_time.to_string();
+        sighting.event_ids.insert(event_id.to_string());
+        return true;
+    }
+
+    // 同じユーザー名で複数のSIDが使われたアカウント
+    fn multiple_sids(&self) -> Vec<Vec<String>> {
+        return self
+            .users
+            .values()
+            .filter(|(_, sids)| sids.len() > 1)
+            .map(|(username, sids)| {
+                let event_ids: BTreeSet<&String> =
+                    sids.values().flat_map(|s| s.event_ids.iter()).collect();
+                let event_ids: Vec<&str> = event_ids.iter().map(|s| s.as_str()).collect();
+
+                let mut msges: Vec<String> = Vec::new();
+                msges.push("Message: Multiple SIDs for one account".to_string());
+                msges.push(format!("EventID: {}", event_ids.join(", ")));
+                msges.push(format!("Username: {}", username));
+                msges.push(format!("SID count: {}", sids.len()));
+                sids.iter().for_each(|(sid, sighting)| {
+                    let event_ids: Vec<&str> =
+                        sighting.event_ids.iter().map(|s| s.as_str()).collect();
+                    msges.push(format!(
+                        "SID: {} (Domain: {}, First seen: {}, Last seen: {}, EventID: {})",
+                        sid,
+                        sighting.domain,
+                        sighting.first_seen,
+                        sighting.last_seen,
+                        event_ids.join(", ")
+                    ));
+                });
+                return msges;
+            })
+            .collect();
+    }
+
+    // ビルトインのAdministrator(RID 500)の使用。SID毎に一度だけ出力する
+    fn builtin_administrator(
+        &mut self,
+        event_id: &str,
+        username: &str,
+        sid: &str,
+        event_data: &HashMap<String, String>,
+        system_time: &str,
+    ) -> Option<Vec<String>> {
+        if !sid.starts_with("S-1-5-21-") || !sid.ends_with("-500") {
+            return Option::None;
+        }
+        if !self.alerted_sids.insert(format!("500:{}", sid)) {
+            return Option::None;
+        }
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: Built-in Administrator Account Used".to_string());
+        msges.push(format!("EventID: {}", event_id));
+        msges.push(format!("Username: {}", username));
+        msges.push(format!("User SID: {}", sid));
+        if let Some(logon_type) = event_data.get("LogonType") {
+            msges.push(format!("Logon type: {}", logon_type));
+        }
+        if let Some(image) = event_data.get("NewProcessName") {
+            msges.push(format!("Process: {}", image));
+        }
+
+        return Option::Some(msges);
+    }
+
+    // 作成(4720)から一定時間以内にログオンしたアカウント。SID毎に一度だけ出力する
+    fn new_account_logon(
+        &mut self,
+        username: &str,
+        sid: &str,
+        event_data: &HashMap<String, String>,
+        time: &Option<DateTime<Utc>>,
+        system_time: &str,
+    ) -> Option<Vec<String>> {
+        let (_, created_time, created) = self.created_accounts.get(sid)?;
+        let elapsed = time.as_ref()?.signed_duration_since((*created)?);
+        if elapsed.num_seconds() < 0 || elapsed.num_seconds() > logon_window() {
+            return Option::None;
+        }
+        let created_time = created_time.to_string();
+        if !self.alerted_sids.insert(format!("4720:{}", sid)) {
+            return Option::None;
+        }
+
+        let default = String::from("");
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: Logon by Recently Created Account".to_string());
+        msges.push("EventID: 4624".to_string());
+        msges.push(format!("Username: {}", username));
+        msges.push(format!("User SID: {}", sid));
+        msges.push(format!("Created: {}", created_time));
+        msges.push(format!(
+            "Logon type: {}",
+            event_data.get("LogonType").unwrap_or(&default)
+        ));
+        msges.push(format!(
+            "Source IP: {}",
+            event_data.get("IpAddress").unwrap_or(&default)
+        ));
+
+        return Option::Some(msges);
+    }
+}
+
+fn logon_window() -> i64 {
+    let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
+    return configs["new_account_logon_window"]
+        .as_i64()
+        .unwrap_or(86400);
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate quick_xml;
+
+    use crate::detections::identity;
+    use crate::detections::timestamp;
+    use crate::models::event;
+
+    fn get_4672(username: &str, user_sid: &str, time: &str) -> event::Evtx {
+        let xml_str = get_special_logon_xml()
+            .replace("hogehoge", username)
+            .replace("S-1-5-21-1-2-3-1001", user_sid)
+            .replace("2021-07-11T06:16:25Z", time);
+        return quick_xml::de::from_str(&xml_str).unwrap();
+    }
+
+    fn get_4624(username: &str, user_sid: &str, time: &str) -> event::Evtx {
+        let xml_str = get_logon_xml()
+            .replace("HogeHoge", username)
+            .replace("S-1-5-21-1-2-3-1002", user_sid)
+            .replace("2021-07-11T07:00:00Z", time);
+        return quick_xml::de::from_str(&xml_str).unwrap();
+    }
+
+    fn detect(identity: &mut identity::Identity, event: &event::Evtx) {
+        identity.detection(
+            "Security",
+            &event.system.event_id,
+            &event.system,
+            &event.parse_event_data(),
+            &timestamp::parse_system_time(&event.system.time_created.system_time),
+        );
+    }
+
+    #[test]
+    fn test_multiple_sids() {
+        let mut identity = identity::Identity::new();
+
+        // 1Userで1SIDなら何回やっても表示されない
+        for _ in 0..3 {
+            detect(
+                &mut identity,
+                &get_4672("hogehoge", "S-1-5-21-1-2-3-1001", "2021-07-11T06:16:25Z"),
+            );
+        }
+        assert_eq!(0, identity.multiple_sids().len());
+
+        // SIDを追加
+        detect(
+            &mut identity,
+            &get_4624("HogeHoge", "S-1-5-21-1-2-3-1002", "2021-07-11T07:00:00Z"),
+        );
+        detect(
+            &mut identity,
+            &get_4672("hogehoge", "S-1-5-21-1-2-3-1001", "2021-07-11T08:00:00Z"),
+        );
+        let msges = identity.multiple_sids();
+        assert_eq!(1, msges.len());
+        let expected = vec![
+            "Message: Multiple SIDs for one account",
+            "EventID: 4624, 4672",
+            "Username: hogehoge",
+            "SID count: 2",
+            "SID: S-1-5-21-1-2-3-1001 (Domain: ICHIICHI, First seen: 2021-07-11T06:16:25Z, Last seen: 2021-07-11T08:00:00Z, EventID: 4672)",
+            "SID: S-1-5-21-1-2-3-1002 (Domain: ICHIICHI, First seen: 2021-07-11T07:00:00Z, Last seen: 2021-07-11T07:00:00Z, EventID: 4624)",
+        ];
+        assert_eq!(expected, msges[0]);
+
+        // Userを追加
+        detect(
+            &mut identity,
+            &get_4672("ariai", "S-1-5-21-1-2-3-1003", "2021-07-11T09:00:00Z"),
+        );
+        assert_eq!(1, identity.multiple_sids().len());
+        detect(
+            &mut identity,
+            &get_4672("ariai", "S-1-5-21-1-2-3-1004", "2021-07-11T09:00:00Z"),
+        );
+        assert_eq!(2, identity.multiple_sids().len());
+
+        // コンピューターアカウントやSYSTEMは対象外
+        detect(
+            &mut identity,
+            &get_4672("WS01$", "S-1-5-18", "2021-07-11T09:00:00Z"),
+        );
+        detect(
+            &mut identity,
+            &get_4624("WS01$", "S-1-5-21-1-2-3-1105", "2021-07-11T09:00:00Z"),
+        );
+        assert_eq!(2, identity.multiple_sids().len());
+    }
+
+    // --stateで状態ファイルに残したSIDから数える
+    #[test]
+    fn test_multiple_sids_resume() {
+        let mut identity = identity::Identity::new();
+        detect(
+            &mut identity,
+            &get_4672("hogehoge", "S-1-5-21-1-2-3-1001", "2021-07-11T06:16:25Z"),
+        );
+        let json = serde_json::to_string(&identity.aggregate()).unwrap();
+
+        let mut resumed = identity::Identity::new();
+        resumed.resume(&serde_json::from_str(&json).unwrap());
+        assert_eq!(identity.aggregate(), resumed.aggregate());
+        assert_eq!(0, resumed.multiple_sids().len());
+
+        detect(
+            &mut resumed,
+            &get_4624("HogeHoge", "S-1-5-21-1-2-3-1002", "2021-07-11T07:00:00Z"),
+        );
+        let msges = resumed.multiple_sids();
+        assert_eq!(1, msges.len());
+        assert_eq!("SID count: 2", msges[0][3]);
+    }
+
+    #[test]
+    fn test_builtin_administrator() {
+        let mut identity = identity::Identity::new();
+        let event = get_4624(
+            "Administrator",
+            "S-1-5-21-1-2-3-500",
+            "2021-07-11T06:16:25Z",
+        );
+        let event_data = event.parse_event_data();
+        let v = identity
+            .builtin_administrator(
+                "4624",
+                "Administrator",
+                "S-1-5-21-1-2-3-500",
+                &event_data,
+                "2021-07-11T06:16:25Z",
+            )
+            .unwrap();
+        let expected = vec![
+            "Date: 2021-07-11T06:16:25Z",
+            "Message: Built-in Administrator Account Used",
+            "EventID: 4624",
+            "Username: Administrator",
+            "User SID: S-1-5-21-1-2-3-500",
+            "Logon type: 3",
+        ];
+        assert_eq!(expected, v);
+
+        // SID毎に一度だけ
+        let option_v = identity.builtin_administrator(
+            "4624",
+            "Administrator",
+            "S-1-5-21-1-2-3-500",
+            &event_data,
+            "2021-07-11T06:16:25Z",
+        );
+        assert_eq!(Option::None, option_v);
+
+        // 名前を変えてもRIDで検知する
+        let option_v = identity.builtin_administrator(
+            "4672",
+            "admin2",
+            "S-1-5-21-4-5-6-500",
+            &event_data,
+            "2021-07-11T06:16:25Z",
+        );
+        assert!(option_v.is_some());
+
+        let option_v = identity.builtin_administrator(
+            "4672",
+            "Administrator",
+            "S-1-5-21-4-5-6-1500",
+            &event_data,
+            "2021-07-11T06:16:25Z",
+        );
+        assert_eq!(Option::None, option_v);
+    }
+
+    #[test]
+    fn test_new_account_logon() {
+        let mut identity = identity::Identity::new();
+        let created: event::Evtx = quick_xml::de::from_str(&get_account_created_xml()).unwrap();
+        detect(&mut identity, &created);
+
+        let event = get_4624("backdoor", "S-1-5-21-1-2-3-1010", "2021-07-11T06:30:00Z");
+        let event_data = event.parse_event_data();
+        let time = timestamp::parse_system_time(&event.system.time_created.system_time);
+        let v = identity
+            .new_account_logon(
+                "backdoor",
+                "S-1-5-21-1-2-3-1010",
+                &event_data,
+                &time,
+                "2021-07-11T06:30:00Z",
+            )
+            .unwrap();
+        let expected = vec![
+            "Date: 2021-07-11T06:30:00Z",
+            "Message: Logon by Recently Created Account",
+            "EventID: 4624",
+            "Username: backdoor",
+            "User SID: S-1-5-21-1-2-3-1010",
+            "Created: 2021-07-11T06:00:00Z",
+            "Logon type: 3",
+            "Source IP: 10.0.0.5",
+        ];
+        assert_eq!(expected, v);
+
+        // 一度出力したアカウントは出力しない
+        let option_v = identity.new_account_logon(
+            "backdoor",
+            "S-1-5-21-1-2-3-1010",
+            &event_data,
+            &time,
+            "2021-07-11T06:30:00Z",
+        );
+        assert_eq!(Option::None, option_v);
+
+        // 期限を過ぎたログオン
+        let mut identity = identity::Identity::new();
+        detect(&mut identity, &created);
+        let time = timestamp::parse_system_time("2021-07-13T06:30:00Z");
+        let option_v = identity.new_account_logon(
+            "backdoor",
+            "S-1-5-21-1-2-3-1010",
+            &event_data,
+            &time,
+            "2021-07-13T06:30:00Z",
+        );
+        assert_eq!(Option::None, option_v);
+    }
+
+    fn get_special_logon_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-Security-Auditing"/>
+            <EventID>4672</EventID>
+            <Version>0</Version>
+            <Level>0</Level>
+            <Task>0</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x8020000000000000</Keywords>
+            <TimeCreated SystemTime="2021-07-11T06:16:25Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Channel>Security</Channel>
+            <Computer>DESKTOP-ICHIICHI</Computer>
+            <Security/>
+          </System>
+          <EventData>
+            <Data Name="SubjectUserSid">S-1-5-21-1-2-3-1001</Data>
+            <Data Name="SubjectUserName">hogehoge</Data>
+            <Data Name="SubjectDomainName">ICHIICHI</Data>
+            <Data Name="PrivilegeList">SeDebugPrivilege</Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_logon_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-Security-Auditing"/>
+            <EventID>4624</EventID>
+            <Version>0</Version>
+            <Level>0</Level>
+            <Task>0</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x8020000000000000</Keywords>
+            <TimeCreated SystemTime="2021-07-11T07:00:00Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Channel>Security</Channel>
+            <Computer>DESKTOP-ICHIICHI</Computer>
+            <Security/>
+          </System>
+          <EventData>
+            <Data Name="TargetUserSid">S-1-5-21-1-2-3-1002</Data>
+            <Data Name="TargetUserName">HogeHoge</Data>
+            <Data Name="TargetDomainName">ICHIICHI</Data>
+            <Data Name="LogonType">3</Data>
+            <Data Name="IpAddress">10.0.0.5</Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_account_created_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-Security-Auditing"/>
+            <EventID>4720</EventID>
+            <Version>0</Version>
+            <Level>0</Level>
+            <Task>0</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x8020000000000000</Keywords>
+            <TimeCreated SystemTime="2021-07-11T06:00:00Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Channel>Security</Channel>
+            <Computer>DESKTOP-ICHIICHI</Computer>
+            <Security/>
+          </System>
+          <EventData>
+            <Data Name="TargetUserName">backdoor</Data>
+            <Data Name="TargetSid">S-1-5-21-1-2-3-1010</Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+}
@@ -0,0 +1,244 @@
+use std::fmt;
+
+//
+// 検知結果の重要度
+// YAMLルールのlevelと同じ5段階
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Informational,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+// key: 検知結果のMessageの値
+// ここにない検知はMediumとして扱う
+const DETECTION_LEVELS: &[(&str, Level)] = &[
+    // Security
+    ("The Audit log was cleared", Level::High),
+    ("Event Log Service Stopped", Level::High),
+    ("System Audit Policy Changed", Level::Low),
+    ("CrashOnAuditFail Changed", Level::Low),
+    ("Object Audit Settings Changed", Level::Low),
+    ("Security Log Full", Level::Medium),
+    ("Event Logging Service Shut Down", Level::Low),
+    ("Event Log Cleared via Command Line", Level::High),
+    ("New User Created", Level::Medium),
+    ("User added to local Administrators group", Level::High),
+    ("User added to global Administrators group", Level::High),
+    ("User added to universal Administrators group", Level::High),
+    ("User added to sensitive group", Level::High),
+    ("User removed from sensitive group", Level::Medium),
+    ("User Account Enabled", Level::Low),
+    ("User Password Reset", Level::Medium),
+    ("User Account Unlocked", Level::Low),
+    ("User Account Renamed", Level::Medium),
+    ("User Created and Deleted", Level::High),
+    ("Logon with SeDebugPrivilege (admin access)", Level::Low),
+    ("Multiple SIDs for one account", Level::Medium),
+    ("Built-in Administrator Account Used", Level::Medium),
+    ("Logon by Recently Created Account", Level::Medium),
+    (
+        "High number of logon failures for one account",
+        Level::Medium,
+    ),
+    (
+        "High number of total logon failures for multiple accounts",
+        Level::Medium,
+    ),
+    ("Sensitive Privilege Use Exceeds Threshold", Level::High),
+    ("Possible Hidden Service Attempt", Level::High),
+    (
+        "Distributed Account Explicit Credential Use (Password Spray Attack)",
+        Level::Critical,
+    ),
+    // System
+    ("System Log Clear", Level::High),
+    ("Unexpected Shutdown", Level::Low),
+    ("Event Log Service Started", Level::Informational),
+    ("Suspicious Service Name", Level::High),
+    ("Interactive service warning", Level::Medium),
+    ("New Service Created", Level::Informational),
+    ("Suspicious Service Installed", Level::High),
+    ("Service Installed and Started", Level::Low),
+    // スケジュールされたタスク
+    ("Suspicious Scheduled Task", Level::High),
+    ("Scheduled Task Deleted Shortly After Running", Level::High),
+    // PowerShell, Sysmon, コマンドライン
+    ("Suspicious Command Line", Level::High),
+    ("Unsigned Image (DLL)", Level::Medium),
+    (
+        "PowerShell script block logged as suspicious",
+        Level::Medium,
+    ),
+    ("PowerShell v2 downgrade", Level::High),
+    ("Suspicious parent-child process", Level::High),
+    // Windows Defender
+    ("Windows Defender Malware Detected", Level::High),
+    ("Windows Defender Malware Action Taken", Level::Medium),
+    ("Windows Defender Remediation Failed", Level::High),
+    ("Windows Defender Protection Disabled", Level::High),
+    ("Windows Defender Exclusion Added", Level::High),
+    ("Windows Defender Configuration Weakened", Level::High),
+    (
+        "Windows Defender Tamper Protection Blocked a Change",
+        Level::Medium,
+    ),
+    // Application, AppLocker
+    ("EMET Block", Level::High),
+    ("Applocker Block", Level::High),
+    ("Applocker Warning", Level::Medium),
+    (
+        "Applocker Packaged app rules not configured",
+        Level::Informational,
+    ),
+];
+
+impl Level {
+    pub fn from_str(level: &str) -> Result<Level, String> {
+        return match level.trim().to_lowercase().as_str() {
+            "informational" | "info" => Result::Ok(Level::Informational),
+            "low" => Result::Ok(Level::Low),
+            "medium" => Result::Ok(Level::Medium),
+            "high" => Result::Ok(Level::High),
+            "critical" => Result::Ok(Level::Critical),
+            _ => Result::Err(format!(
+                "invalid level: {}. use informational, low, medium, high or critical.",
+                level
+            )),
+        };
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            Level::Informational => "informational",
+            Level::Low => "low",
+            Level::Medium => "medium",
+            Level::High => "high",
+            Level::Critical => "critical",
+        };
+    }
+
+    // リスクスコアの重み
+    // Criticalが1件あればLowが何件あっても上に来るように、段階毎に大きく差をつける
+    pub fn score(&self) -> u64 {
+        return match self {
+            Level::Informational => 0,
+            Level::Low => 1,
+            Level::Medium => 5,
+            Level::High => 20,
+            Level::Critical => 100,
+        };
+    }
+
+    pub fn of_detection(title: &str) -> Level {
+        return DETECTION_LEVELS
+            .iter()
+            .find(|(detection, _)| *detection == title)
+            .map(|(_, level)| *level)
+            .unwrap_or(Level::Medium);
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::detections::level::{Level, DETECTION_LEVELS};
+    use regex::Regex;
+    use std::fs;
+
+    #[test]
+    fn test_level() {
+        assert_eq!(Result::Ok(Level::Critical), Level::from_str("Critical"));
+        assert_eq!(Result::Ok(Level::Informational), Level::from_str("info"));
+        assert!(Level::from_str("severe").is_err());
+        assert!(Level::Critical > Level::High);
+        assert!(Level::Low > Level::Informational);
+
+        assert_eq!(
+            Level::High,
+            Level::of_detection("The Audit log was cleared")
+        );
+        assert_eq!(
+            Level::Critical,
+            Level::of_detection(
+                "Distributed Account Explicit Credential Use (Password Spray Attack)"
+            )
+        );
+        assert_eq!(Level::Medium, Level::of_detection("Unknown"));
+    }
+
+    // 検知のソースコード(テストを除く)を読む
+    fn get_detection_sources() -> Vec<(String, String)> {
+        return fs::read_dir("src/detections")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                let name = path.file_name().unwrap().to_str().unwrap();
+                return name != "level.rs" && name != "mitre.rs";
+            })
+            .map(|path| {
+                let source = fs::read_to_string(&path).unwrap();
+                let source = source
+                    .split("#[cfg(test)]")
+                    .next()
+                    .unwrap()
+                    .lines()
+                    .filter(|line| !line.trim_start().starts_with("//"))
+                    .collect::<Vec<&str>>()
+                    .join("\n");
+                return (path.display().to_string(), source);
+            })
+            .collect();
+    }
+
+    // タイトルを変えたり書き間違えたりすると、黙ってMediumになってしまうので、表と検知を突き合わせる
+    #[test]
+    fn test_detection_levels_match_titles() {
+        let sources = get_detection_sources();
+
+        // 検知で出力するMessageは全て表にある
+        let re = Regex::new(r#""Message ?: ([^"{}]+)""#).unwrap();
+        sources.iter().for_each(|(path, source)| {
+            re.captures_iter(source).for_each(|cap| {
+                assert!(
+                    DETECTION_LEVELS.iter().any(|(title, _)| *title == &cap[1]),
+                    "{}: \"{}\" is not in DETECTION_LEVELS",
+                    path,
+                    &cap[1]
+                );
+            });
+        });
+
+        // 表のタイトルは全てどこかの検知で使われている
+        // "Message: User added to {} Administrators group"のようにformat!で作るタイトルは前後が一致すればよい
+        let template_re = Regex::new(r#""Message ?: ([^"{}]*)\{\}([^"{}]*)""#).unwrap();
+        let templates: Vec<(String, String)> = sources
+            .iter()
+            .flat_map(|(_, source)| template_re.captures_iter(source))
+            .map(|cap| (cap[1].to_string(), cap[2].to_string()))
+            .filter(|(prefix, suffix)| !prefix.is_empty() || !suffix.is_empty())
+            .collect();
+        DETECTION_LEVELS.iter().for_each(|(title, _)| {
+            let literal = format!("\"{}\"", title);
+            let message = format!(": {}\"", title);
+            assert!(
+                sources
+                    .iter()
+                    .any(|(_, source)| source.contains(&literal) || source.contains(&message))
+                    || templates.iter().any(
+                        |(prefix, suffix)| title.starts_with(prefix) && title.ends_with(suffix)
+                    ),
+                "\"{}\" in DETECTION_LEVELS is not emitted by any detection",
+                title
+            );
+        });
+    }
+}
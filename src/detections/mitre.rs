@@ -0,0 +1,515 @@
+extern crate yaml_rust;
+
+use crate::detections::print::Alert;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+// ATT&CK Enterprise Matrixの並び順
+pub const TACTICS: &[&str] = &[
+    "Reconnaissance",
+    "Resource Development",
+    "Initial Access",
+    "Execution",
+    "Persistence",
+    "Privilege Escalation",
+    "Defense Evasion",
+    "Credential Access",
+    "Discovery",
+    "Lateral Movement",
+    "Collection",
+    "Command and Control",
+    "Exfiltration",
+    "Impact",
+];
+
+// 表に載っていないテクニック(YAMLルールのtagsで指定されたもの等)の戦術
+pub const OTHER_TACTIC: &str = "Other";
+
+// (Technique ID, Technique名, 戦術)
+const TECHNIQUES: &[(&str, &str, &[&str])] = &[
+    (
+        "T1003.001",
+        "OS Credential Dumping: LSASS Memory",
+        &["Credential Access"],
+    ),
+    (
+        "T1027",
+        "Obfuscated Files or Information",
+        &["Defense Evasion"],
+    ),
+    (
+        "T1027.004",
+        "Obfuscated Files or Information: Compile After Delivery",
+        &["Defense Evasion"],
+    ),
+    (
+        "T1021.002",
+        "Remote Services: SMB/Windows Admin Shares",
+        &["Lateral Movement"],
+    ),
+    (
+        "T1021.006",
+        "Remote Services: Windows Remote Management",
+        &["Lateral Movement"],
+    ),
+    ("T1014", "Rootkit", &["Defense Evasion"]),
+    (
+        "T1047",
+        "Windows Management Instrumentation",
+        &["Execution"],
+    ),
+    (
+        "T1053.005",
+        "Scheduled Task/Job: Scheduled Task",
+        &["Execution", "Persistence", "Privilege Escalation"],
+    ),
+    ("T1059", "Command and Scripting Interpreter", &["Execution"]),
+    (
+        "T1059.001",
+        "Command and Scripting Interpreter: PowerShell",
+        &["Execution"],
+    ),
+    (
+        "T1059.003",
+        "Command and Scripting Interpreter: Windows Command Shell",
+        &["Execution"],
+    ),
+    ("T1070", "Indicator Removal", &["Defense Evasion"]),
+    (
+        "T1070.001",
+        "Indicator Removal: Clear Windows Event Logs",
+        &["Defense Evasion"],
+    ),
+    (
+        "T1071.001",
+        "Application Layer Protocol: Web Protocols",
+        &["Command and Control"],
+    ),
+    (
+        "T1078",
+        "Valid Accounts",
+        &[
+            "Initial Access",
+            "Persistence",
+            "Privilege Escalation",
+            "Defense Evasion",
+        ],
+    ),
+    (
+        "T1078.003",
+        "Valid Accounts: Local Accounts",
+        &[
+            "Initial Access",
+            "Persistence",
+            "Privilege Escalation",
+            "Defense Evasion",
+        ],
+    ),
+    ("T1098", "Account Manipulation", &["Persistence"]),
+    ("T1105", "Ingress Tool Transfer", &["Command and Control"]),
+    ("T1110", "Brute Force", &["Credential Access"]),
+    (
+        "T1110.001",
+        "Brute Force: Password Guessing",
+        &["Credential Access"],
+    ),
+    (
+        "T1110.003",
+        "Brute Force: Password Spraying",
+        &["Credential Access"],
+    ),
+    (
+        "T1134",
+        "Access Token Manipulation",
+        &["Privilege Escalation", "Defense Evasion"],
+    ),
+    (
+        "T1134.001",
+        "Access Token Manipulation: Token Impersonation/Theft",
+        &["Privilege Escalation", "Defense Evasion"],
+    ),
+    (
+        "T1136.001",
+        "Create Account: Local Account",
+        &["Persistence"],
+    ),
+    ("T1203", "Exploitation for Client Execution", &["Execution"]),
+    (
+        "T1204.002",
+        "User Execution: Malicious File",
+        &["Execution"],
+    ),
+    ("T1531", "Account Access Removal", &["Impact"]),
+    (
+        "T1543.003",
+        "Create or Modify System Process: Windows Service",
+        &["Persistence", "Privilege Escalation"],
+    ),
+    (
+        "T1562.001",
+        "Impair Defenses: Disable or Modify Tools",
+        &["Defense Evasion"],
+    ),
+    (
+        "T1562.002",
+        "Impair Defenses: Disable Windows Event Logging",
+        &["Defense Evasion"],
+    ),
+    (
+        "T1562.010",
+        "Impair Defenses: Downgrade Attack",
+        &["Defense Evasion"],
+    ),
+    (
+        "T1218.005",
+        "System Binary Proxy Execution: Mshta",
+        &["Defense Evasion"],
+    ),
+    ("T1564", "Hide Artifacts", &["Defense Evasion"]),
+    (
+        "T1566.001",
+        "Phishing: Spearphishing Attachment",
+        &["Initial Access"],
+    ),
+    (
+        "T1564.003",
+        "Hide Artifacts: Hidden Window",
+        &["Defense Evasion"],
+    ),
+    (
+        "T1569.002",
+        "System Services: Service Execution",
+        &["Execution"],
+    ),
+    (
+        "T1574",
+        "Hijack Execution Flow",
+        &["Persistence", "Privilege Escalation", "Defense Evasion"],
+    ),
+];
+
+// key: 検知結果のMessageの値
+const DETECTION_TECHNIQUES: &[(&str, &[&str])] = &[
+    // Security
+    ("The Audit log was cleared", &["T1070.001"]),
+    ("Event Log Service Stopped", &["T1562.002"]),
+    ("System Audit Policy Changed", &["T1562.002"]),
+    ("CrashOnAuditFail Changed", &["T1562.002"]),
+    ("Object Audit Settings Changed", &["T1562.002"]),
+    ("Security Log Full", &["T1562.002"]),
+    ("Event Logging Service Shut Down", &["T1562.002"]),
+    ("Event Log Cleared via Command Line", &["T1070.001"]),
+    ("New User Created", &["T1136.001"]),
+    ("User added to local Administrators group", &["T1098"]),
+    ("User added to global Administrators group", &["T1098"]),
+    ("User added to universal Administrators group", &["T1098"]),
+    ("User added to sensitive group", &["T1098"]),
+    ("User removed from sensitive group", &["T1531"]),
+    ("User Account Enabled", &["T1098"]),
+    ("User Password Reset", &["T1098"]),
+    ("User Account Unlocked", &["T1098"]),
+    ("User Account Renamed", &["T1098"]),
+    ("User Created and Deleted", &["T1136.001", "T1070"]),
+    ("Logon with SeDebugPrivilege (admin access)", &["T1078"]),
+    ("Multiple SIDs for one account", &["T1078"]),
+    ("Built-in Administrator Account Used", &["T1078.003"]),
+    (
+        "Logon by Recently Created Account",
+        &["T1136.001", "T1078.003"],
+    ),
+    (
+        "High number of logon failures for one account",
+        &["T1110.001"],
+    ),
+    (
+        "High number of total logon failures for multiple accounts",
+        &["T1110"],
+    ),
+    ("Sensitive Privilege Use Exceeds Threshold", &["T1134"]),
+    ("Possible Hidden Service Attempt", &["T1543.003", "T1564"]),
+    (
+        "Distributed Account Explicit Credential Use (Password Spray Attack)",
+        &["T1110.003"],
+    ),
+    // System
+    ("System Log Clear", &["T1070.001"]),
+    ("Suspicious Service Name", &["T1543.003"]),
+    ("Interactive service warning", &["T1543.003"]),
+    ("New Service Created", &["T1543.003"]),
+    ("Suspicious Service Installed", &["T1543.003"]),
+    ("Service Installed and Started", &["T1543.003", "T1569.002"]),
+    // スケジュールされたタスク
+    ("Suspicious Scheduled Task", &["T1053.005"]),
+    (
+        "Scheduled Task Deleted Shortly After Running",
+        &["T1053.005", "T1070"],
+    ),
+    // PowerShell, Sysmon, コマンドライン
+    ("Suspicious Command Line", &["T1059"]),
+    ("Unsigned Image (DLL)", &["T1574"]),
+    (
+        "PowerShell script block logged as suspicious",
+        &["T1059.001"],
+    ),
+    ("PowerShell v2 downgrade", &["T1059.001", "T1562.010"]),
+    // Windows Defender
+    ("Windows Defender Malware Detected", &["T1204.002"]),
+    ("Windows Defender Malware Action Taken", &["T1204.002"]),
+    ("Windows Defender Remediation Failed", &["T1204.002"]),
+    ("Windows Defender Protection Disabled", &["T1562.001"]),
+    ("Windows Defender Exclusion Added", &["T1562.001"]),
+    ("Windows Defender Configuration Weakened", &["T1562.001"]),
+    (
+        "Windows Defender Tamper Protection Blocked a Change",
+        &["T1562.001"],
+    ),
+    // Application, AppLocker
+    ("EMET Block", &["T1203"]),
+    ("Applocker Block", &["T1204.002"]),
+    ("Applocker Warning", &["T1204.002"]),
+];
+
+// utils::check_commandの結果の行とテクニックの対応
+// シグネチャのテクニックはsignatures.ymlの、親子関係のテクニックはprocess_heuristics.ymlのtagsに書く
+// key: check_command内で追加する結果の先頭部分
+const RESULT_TECHNIQUES: &[(&str, &[&str])] = &[
+    ("Possible command obfuscation", &["T1027"]),
+    ("Base64-encoded", &["T1027"]),
+];
+
+pub fn technique_name(id: &str) -> &'static str {
+    return TECHNIQUES
+        .iter()
+        .find(|(technique, _, _)| *technique == id)
+        .map(|(_, name, _)| *name)
+        .unwrap_or("");
+}
+
+pub fn technique_tactics(id: &str) -> Vec<&'static str> {
+    return TECHNIQUES
+        .iter()
+        .find(|(technique, _, _)| *technique == id)
+        .map(|(_, _, tactics)| tactics.to_vec())
+        .unwrap_or(vec![OTHER_TACTIC]);
+}
+
+pub fn techniques_of_detection(title: &str) -> Vec<String> {
+    return DETECTION_TECHNIQUES
+        .iter()
+        .find(|(detection, _)| *detection == title)
+        .map(|(_, techniques)| techniques.iter().map(|s| s.to_string()).collect())
+        .unwrap_or(vec![]);
+}
+
+// check_commandの結果(改行区切り)に対応するテクニック
+pub fn techniques_of_results(results: &str) -> Vec<String> {
+    let mut techniques: BTreeSet<String> = BTreeSet::new();
+    results.lines().for_each(|line| {
+        RESULT_TECHNIQUES
+            .iter()
+            .filter(|(result, _)| line.trim().starts_with(result))
+            .for_each(|(_, ids)| {
+                ids.iter().for_each(|id| {
+                    techniques.insert(id.to_string());
+                });
+            });
+    });
+    return techniques.into_iter().collect();
+}
+
+//
+// signatures.ymlとprocess_heuristics.yml、YAMLルールのtagsからテクニックを取り出す
+// SigmaとおなじくTechniqueは"attack.t1070.001"の形式で書く。"attack.defense_evasion"等の戦術のタグは無視する
+//
+pub fn techniques_of_rule(rule: &yaml_rust::Yaml) -> Vec<String> {
+    let tags = match rule["tags"].as_vec() {
+        Some(tags) => tags,
+        None => return vec![],
+    };
+    return tags
+        .iter()
+        .filter_map(|tag| tag.as_str())
+        .filter_map(|tag| {
+            let tag = tag.trim().to_lowercase();
+            if !tag.starts_with("attack.t") {
+                return Option::None;
+            }
+            let id = tag["attack.".len()..].to_uppercase();
+            if !id[1..].chars().all(|c| c.is_ascii_digit() || c == '.') {
+                return Option::None;
+            }
+            return Option::Some(id);
+        })
+        .collect();
+}
+
+//
+// 検知したテクニックを戦術毎にまとめる
+// value: key: Technique ID, value: 検知数
+//
+pub fn group_by_tactic(
+    alerts: &Vec<Alert>,
+) -> BTreeMap<usize, (&'static str, BTreeMap<String, u64>)> {
+    let mut tactics: BTreeMap<usize, (&'static str, BTreeMap<String, u64>)> = BTreeMap::new();
+    alerts.iter().for_each(|alert| {
+        alert.techniques.iter().for_each(|technique| {
+            technique_tactics(technique).into_iter().for_each(|tactic| {
+                let order = TACTICS
+                    .iter()
+                    .position(|t| *t == tactic)
+                    .unwrap_or(TACTICS.len());
+                *tactics
+                    .entry(order)
+                    .or_insert((tactic, BTreeMap::new()))
+                    .1
+                    .entry(technique.to_string())
+                    .or_insert(0) += 1;
+            });
+        });
+    });
+    return tactics;
+}
+
+//
+// 検知したテクニックをATT&CK NavigatorのLayer形式で出力する
+// scoreは検知数、commentには検知名を入れる
+//
+pub fn create_navigator_layer(alerts: &Vec<Alert>) -> serde_json::Value {
+    // key: Technique ID
+    let mut techniques: BTreeMap<&str, (u64, BTreeSet<&str>)> = BTreeMap::new();
+    alerts.iter().for_each(|alert| {
+        alert.techniques.iter().for_each(|technique| {
+            let entry = techniques.entry(technique).or_insert((0, BTreeSet::new()));
+            entry.0 += 1;
+            entry.1.insert(&alert.title);
+        });
+    });
+    let max_score = techniques
+        .values()
+        .map(|(count, _)| *count)
+        .max()
+        .unwrap_or(1);
+
+    let techniques: Vec<serde_json::Value> = techniques
+        .iter()
+        .map(|(technique, (count, titles))| {
+            let titles: Vec<&str> = titles.iter().map(|s| *s).collect();
+            return json!({
+                "techniqueID": technique,
+                "score": count,
+                "comment": titles.join(", "),
+                "enabled": true,
+                "showSubtechniques": true,
+            });
+        })
+        .collect();
+
+    return json!({
+        "name": "RustyBlue detections",
+        "versions": {
+            "attack": "9",
+            "navigator": "4.3",
+            "layer": "4.2",
+        },
+        "domain": "enterprise-attack",
+        "description": "Techniques detected by RustyBlue. score is the number of alerts.",
+        "sorting": 3,
+        "hideDisabled": false,
+        "techniques": techniques,
+        "gradient": {
+            "colors": ["#ffe766ff", "#ff6666ff"],
+            "minValue": 0,
+            "maxValue": max_score,
+        },
+        "legendItems": [],
+        "showTacticRowBackground": false,
+        "selectTechniquesAcrossTactics": true,
+        "selectSubtechniquesWithParent": false,
+    });
+}
+
+pub fn write_navigator_layer(path: &str, alerts: &Vec<Alert>) -> Result<(), String> {
+    let layer = create_navigator_layer(alerts);
+    let json = serde_json::to_string_pretty(&layer).map_err(|e| e.to_string())?;
+    return fs::write(path, json).map_err(|e| format!("cannot write {}, {}", path, e));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::detections::mitre;
+    use crate::detections::print::AlertMessages;
+    use yaml_rust::YamlLoader;
+
+    #[test]
+    fn test_techniques_of_detection() {
+        assert_eq!(
+            vec!["T1070.001"],
+            mitre::techniques_of_detection("The Audit log was cleared")
+        );
+        assert_eq!(
+            vec!["T1543.003"],
+            mitre::techniques_of_detection("Suspicious Service Installed")
+        );
+        assert_eq!(
+            vec!["T1204.002"],
+            mitre::techniques_of_detection("Windows Defender Malware Action Taken")
+        );
+        assert!(mitre::techniques_of_detection("Event Log Service Started").is_empty());
+    }
+
+    #[test]
+    fn test_techniques_of_results() {
+        let results = "Possible command obfuscation: only 10% alphanumeric and common symbols\nBase64-encoded function (UTF-8)\nCommand referencing Mimikatz\n";
+        assert_eq!(vec!["T1027"], mitre::techniques_of_results(results));
+    }
+
+    #[test]
+    fn test_techniques_of_rule() {
+        let rule = YamlLoader::load_from_str(
+            r#"
+            title: Test
+            tags:
+                - attack.defense_evasion
+                - attack.t1070.001
+                - attack.T1562
+                - car.2016-04-002
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec!["T1070.001", "T1562"],
+            mitre::techniques_of_rule(&rule[0])
+        );
+    }
+
+    #[test]
+    fn test_navigator_layer() {
+        let mut alert_messages = AlertMessages::new();
+        alert_messages.insert(&vec!["Message: The Audit log was cleared".to_string()]);
+        alert_messages.insert(&vec!["Message: System Log Clear".to_string()]);
+        alert_messages.insert(&vec![
+            "Message: Suspicious Command Line".to_string(),
+            "MITRE ATT&CK: T1003.001, T1027".to_string(),
+        ]);
+
+        let tactics = mitre::group_by_tactic(&alert_messages.alerts);
+        let (tactic, techniques) = tactics.values().next().unwrap();
+        assert_eq!("Execution", *tactic);
+        assert_eq!(Some(&1), techniques.get("T1059"));
+
+        let layer = mitre::create_navigator_layer(&alert_messages.alerts);
+        assert_eq!("enterprise-attack", layer["domain"]);
+        let techniques = layer["techniques"].as_array().unwrap();
+        assert_eq!(4, techniques.len());
+        let technique = techniques
+            .iter()
+            .find(|technique| technique["techniqueID"] == "T1070.001")
+            .unwrap();
+        assert_eq!(2, technique["score"]);
+        assert_eq!(
+            "System Log Clear, The Audit log was cleared",
+            technique["comment"]
+        );
+    }
+}
@@ -2,11 +2,29 @@ mod application;
 mod applocker;
 mod common;
 pub mod configs;
+mod defender;
+mod deobfuscate;
 pub mod detection;
+pub mod exclusion;
+pub mod filter;
+pub mod heuristic;
+pub mod html_report;
+mod identity;
+pub mod level;
+pub mod mitre;
 mod powershell;
 pub mod print;
+mod process_tree;
+pub mod rdp;
 mod security;
+mod service;
+pub mod signature;
+pub mod state;
+pub mod summary;
 mod sysmon;
 mod system;
+pub mod tampering;
+mod task;
+pub mod timestamp;
 mod utils;
 mod yaml;
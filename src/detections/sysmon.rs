@@ -1,4 +1,6 @@
-use crate::detections::print::MessageNotation;
+use crate::detections::heuristic::ProcessInfo;
+use crate::detections::print::AlertMessages;
+use crate::detections::process_tree;
 use crate::detections::utils::check_command;
 use crate::models::event;
 use std::collections::HashMap;
@@ -37,7 +39,12 @@ impl Sysmon {
 
         if let Some(_command_line) = event_data.get("CommandLine") {
             let default = "".to_string();
-            let _creater = event_data.get("ParentImage").unwrap_or(&default);
+            let process = ProcessInfo {
+                image: event_data.get("Image").unwrap_or(&default),
+                command_line: _command_line,
+                parent_image: event_data.get("ParentImage").unwrap_or(&default),
+                parent_command_line: event_data.get("ParentCommandLine").unwrap_or(&default),
+            };
 
             let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
             let value = configs["minlength"].as_i64().unwrap_or(1000).clone();
@@ -45,9 +52,8 @@ impl Sysmon {
                 1,
                 _command_line,
                 value as usize,
-                0,
+                &process_tree::check_parent(&process),
                 "",
-                _creater,
                 &system_time,
             );
         }
@@ -69,22 +75,13 @@ impl Sysmon {
             if _signed == "false" {
                 let _image = event_data.get("Image").unwrap_or(&default);
                 let _command_line = event_data.get("ImageLoaded").unwrap_or(&default);
-                let stdout = std::io::stdout();
-                let mut stdout = stdout.lock();
-                MessageNotation::info_noheader(&mut stdout, format!("Date: {}", system_time)).ok();
-                MessageNotation::info_noheader(&mut stdout, "EventID: 7".to_string()).ok();
-                MessageNotation::info_noheader(
-                    &mut stdout,
-                    format!("Message: Unsigned Image (DLL)"),
-                )
-                .ok();
-                MessageNotation::info_noheader(
-                    &mut stdout,
-                    format!("Result: Loaded by: {}", _image),
-                )
-                .ok();
-                MessageNotation::info_noheader(&mut stdout, format!("Command: {}", _command_line))
-                    .ok();
+                let mut msges: Vec<String> = Vec::new();
+                msges.push(format!("Date: {}", system_time));
+                msges.push("EventID: 7".to_string());
+                msges.push("Message: Unsigned Image (DLL)".to_string());
+                msges.push(format!("Result: Loaded by: {}", _image));
+                msges.push(format!("Command: {}", _command_line));
+                AlertMessages::output(msges);
             }
         };
     }
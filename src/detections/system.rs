@@ -1,10 +1,9 @@
-use crate::detections::print::MessageNotation;
+use crate::detections::print::AlertMessages;
+use crate::detections::signature::Target;
 use crate::detections::utils;
 use crate::models::event;
 use std::collections::HashMap;
 
-use super::configs;
-
 #[derive(Debug)]
 pub struct System {}
 
@@ -23,8 +22,6 @@ impl System {
             .and_then(System::print_console);
         self.windows_event_log(&event_id, &event_data, &system.time_created.system_time)
             .and_then(System::print_console);
-        self.new_service_created(&event_id, &event_data, &system.time_created.system_time)
-            .and_then(System::print_console);
         self.interactive_service_warning(&event_id, &event_data, &system.time_created.system_time)
             .and_then(System::print_console);
         self.suspicious_service_name(&event_id, &event_data, &system.time_created.system_time)
@@ -32,54 +29,7 @@ impl System {
     }
 
     fn print_console(v: Vec<String>) -> Option<Vec<String>> {
-        let stdout = std::io::stdout();
-        let mut stdout = stdout.lock();
-        if !v.is_empty() {
-            v.iter().for_each(|s| {
-                MessageNotation::info_noheader(&mut stdout, format!("{}", s)).ok();
-            });
-            MessageNotation::info_noheader(&mut stdout, format!("")).ok();
-        }
-        return Option::Some(v);
-    }
-
-    fn new_service_created(
-        &mut self,
-        event_id: &String,
-        event_data: &HashMap<String, String>,
-        system_time: &String,
-    ) -> Option<Vec<String>> {
-        if event_id != "7045" {
-            return Option::None;
-        }
-
-        let default = String::from("");
-        let servicename = &event_data.get("ServiceName").unwrap_or(&default);
-        let commandline = &event_data.get("ImagePath").unwrap_or(&default);
-        let text = utils::check_regex(&servicename, 1);
-        let mut msges: Vec<String> = Vec::new();
-        if !text.is_empty() {
-            msges.push(format!("Date: {}", system_time));
-            msges.push("Message: New Service Created".to_string());
-            msges.push("EventID: 7045".to_string());
-            msges.push(format!("Command: {}", commandline));
-            msges.push(format!("Results: Service name: {}", servicename));
-            msges.push(format!("Results: {}", text));
-        }
-        if !commandline.is_empty() {
-            let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
-            let value = configs["minlength"].as_i64().unwrap_or(1000).clone();
-            utils::check_command(
-                7045,
-                &commandline,
-                value as usize,
-                1,
-                &servicename,
-                &"",
-                &system_time,
-            );
-        }
-        return Option::Some(msges);
+        return AlertMessages::output(v);
     }
 
     fn interactive_service_warning(
@@ -102,7 +52,10 @@ impl System {
         msges.push(
             "Results: Malware (and some third party software) trigger this warning".to_string(),
         );
-        msges.push(format!("{}", utils::check_regex(&servicename, 1)));
+        msges.push(format!(
+            "{}",
+            utils::check_regex(&servicename, Target::ServiceName)
+        ));
         return Option::Some(msges);
     }
 
@@ -118,7 +71,7 @@ impl System {
 
         let default = String::from("");
         let servicename = &event_data.get("param1").unwrap_or(&default);
-        let text = utils::check_regex(&servicename, 1);
+        let text = utils::check_regex(&servicename, Target::ServiceName);
         let mut msges: Vec<String> = Vec::new();
         if !text.is_empty() {
             msges.push(format!("Date: {}", system_time));
@@ -183,8 +136,8 @@ impl System {
 mod tests {
     extern crate quick_xml;
 
+    use crate::detections::print::MessageNotation;
     use crate::detections::system;
-    use crate::detections::system::MessageNotation;
     use crate::models::event;
 
     // 正しくヒットするパターン
@@ -247,28 +200,6 @@ mod tests {
         assert_eq!(Option::None, option_v);
     }
 
-    // eventidが異なりヒットしないパターン
-    #[test]
-    fn test_new_service_created_noteq_eventid() {
-        let xml_str = get_system_service_created_xml()
-            .replace(r"<EventID>7045</EventID>", r"<EventID>7046</EventID>");
-        let event: event::Evtx = quick_xml::de::from_str(&xml_str)
-            .map_err(|e| {
-                let stdout = std::io::stdout();
-                let mut stdout = stdout.lock();
-                MessageNotation::alert(&mut stdout, format!("{}", e.to_string())).ok();
-            })
-            .unwrap();
-
-        let mut sys = system::System::new();
-        let option_v = sys.new_service_created(
-            &event.system.event_id.to_string(),
-            &event.parse_event_data(),
-            &event.system.time_created.system_time,
-        );
-        assert_eq!(Option::None, option_v);
-    }
-
     #[test]
     fn test_interactive_service_warning() {
         let xml_str = get_interactive_service_warning();
@@ -525,40 +456,6 @@ mod tests {
         </Event>"#.to_string();
     }
 
-    fn get_system_service_created_xml() -> String {
-        return r#"
-        <?xml version="1.0" encoding="utf-8"?>
-        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
-          <System>
-            <Provider Name="Service Control Manager" Guid="{555908d1-a6d7-4695-8e1e-26931d2012f4}" EventSourceName="Service Control Manager">
-            </Provider>
-            <EventID>7045</EventID>
-            <Version>0</Version>
-            <Level>4</Level>
-            <Task>0</Task>
-            <Opcode>0</Opcode>
-            <Keywords>0x8080000000000000</Keywords>
-            <TimeCreated SystemTime="2017-07-12 17:16:29.401630 UTC">
-            </TimeCreated>
-            <EventRecordID>45</EventRecordID>
-            <Correlation>
-            </Correlation>
-            <Execution ProcessID="620" ThreadID="1796">
-            </Execution>
-            <Channel>System</Channel>
-            <Computer>WIN-P4SIAA0SQCO</Computer>
-            <Security UserID="S-1-5-18">
-            </Security>
-          </System>
-          <EventData>
-            <Data Name="ServiceName">ijklmnopIJKLMNOP</Data>
-            <Data Name="ImagePath">\SystemRoot\System32\drivers\WUDFRd.sys</Data>
-            <Data Name="ServiceType">kernel mode driver</Data>
-            <Data Name="StartType">demand start</Data>
-            <Data Name="AccountName"></Data>
-          </EventData>
-        </Event>"#.to_string();
-    }
     fn get_interactive_service_warning() -> String {
         return r#"
         <?xml version="1.0" encoding="utf-8"?>
@@ -0,0 +1,740 @@
+use crate::detections::configs;
+use crate::detections::print::AlertMessages;
+use crate::detections::state;
+use crate::detections::timestamp::TimeFormatter;
+use crate::models::event;
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const SYSMON: &str = "Microsoft-Windows-Sysmon/Operational";
+
+lazy_static! {
+    // wevtutil cl <log>, wevtutil clear-log <log>
+    static ref WEVTUTIL_CLEAR_REGEX: Regex =
+        Regex::new(r#"(?i)\bwevtutil(\.exe)?"?\s+(cl|clear-log)\s"#).unwrap();
+    // Clear-EventLog, Remove-EventLog, [System.Diagnostics.Eventing.Reader.EventLogSession]::GlobalSession.ClearLog()
+    static ref POWERSHELL_CLEAR_REGEX: Regex =
+        Regex::new(r"(?i)\b(Clear-EventLog|Remove-EventLog)\b|EventLogSession\]::GlobalSession\.ClearLog\(")
+            .unwrap();
+}
+
+// 4719のSubcategoryGuid(監査ポリシーの詳細なサブカテゴリ)
+const AUDIT_SUBCATEGORIES: &[(&str, &str)] = &[
+    // System
+    ("0CCE9210", "Security State Change"),
+    ("0CCE9211", "Security System Extension"),
+    ("0CCE9212", "System Integrity"),
+    ("0CCE9213", "IPsec Driver"),
+    ("0CCE9214", "Other System Events"),
+    // Logon/Logoff
+    ("0CCE9215", "Logon"),
+    ("0CCE9216", "Logoff"),
+    ("0CCE9217", "Account Lockout"),
+    ("0CCE9218", "IPsec Main Mode"),
+    ("0CCE9219", "IPsec Quick Mode"),
+    ("0CCE921A", "IPsec Extended Mode"),
+    ("0CCE921B", "Special Logon"),
+    ("0CCE921C", "Other Logon/Logoff Events"),
+    ("0CCE9243", "Network Policy Server"),
+    ("0CCE9247", "User / Device Claims"),
+    ("0CCE9249", "Group Membership"),
+    // Object Access
+    ("0CCE921D", "File System"),
+    ("0CCE921E", "Registry"),
+    ("0CCE921F", "Kernel Object"),
+    ("0CCE9220", "SAM"),
+    ("0CCE9221", "Certification Services"),
+    ("0CCE9222", "Application Generated"),
+    ("0CCE9223", "Handle Manipulation"),
+    ("0CCE9224", "File Share"),
+    ("0CCE9225", "Filtering Platform Packet Drop"),
+    ("0CCE9226", "Filtering Platform Connection"),
+    ("0CCE9227", "Other Object Access Events"),
+    ("0CCE9244", "Detailed File Share"),
+    ("0CCE9245", "Removable Storage"),
+    ("0CCE9246", "Central Policy Staging"),
+    // Privilege Use
+    ("0CCE9228", "Sensitive Privilege Use"),
+    ("0CCE9229", "Non Sensitive Privilege Use"),
+    ("0CCE922A", "Other Privilege Use Events"),
+    // Detailed Tracking
+    ("0CCE922B", "Process Creation"),
+    ("0CCE922C", "Process Termination"),
+    ("0CCE922D", "DPAPI Activity"),
+    ("0CCE922E", "RPC Events"),
+    ("0CCE9248", "Plug and Play Events"),
+    ("0CCE924A", "Token Right Adjusted Events"),
+    // Policy Change
+    ("0CCE922F", "Audit Policy Change"),
+    ("0CCE9230", "Authentication Policy Change"),
+    ("0CCE9231", "Authorization Policy Change"),
+    ("0CCE9232", "MPSSVC Rule-Level Policy Change"),
+    ("0CCE9233", "Filtering Platform Policy Change"),
+    ("0CCE9234", "Other Policy Change Events"),
+    // Account Management
+    ("0CCE9235", "User Account Management"),
+    ("0CCE9236", "Computer Account Management"),
+    ("0CCE9237", "Security Group Management"),
+    ("0CCE9238", "Distribution Group Management"),
+    ("0CCE9239", "Application Group Management"),
+    ("0CCE923A", "Other Account Management Events"),
+    // DS Access
+    ("0CCE923B", "Directory Service Access"),
+    ("0CCE923C", "Directory Service Changes"),
+    ("0CCE923D", "Directory Service Replication"),
+    ("0CCE923E", "Detailed Directory Service Replication"),
+    // Account Logon
+    ("0CCE923F", "Credential Validation"),
+    ("0CCE9240", "Kerberos Service Ticket Operations"),
+    ("0CCE9241", "Other Account Logon Events"),
+    ("0CCE9242", "Kerberos Authentication Service"),
+];
+
+// 4719のAuditPolicyChanges
+const AUDIT_POLICY_CHANGES: &[(&str, &str)] = &[
+    ("%%8448", "Success removed"),
+    ("%%8449", "Success added"),
+    ("%%8450", "Failure removed"),
+    ("%%8451", "Failure added"),
+];
+
+//
+// ログの改ざんや監査の無効化の痕跡1件分
+//
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TamperEvent {
+    pub computer: String,
+    pub channel: String,
+    pub event_id: String,
+    #[serde(with = "state::rfc3339")]
+    pub time: Option<DateTime<Utc>>,
+    pub description: String,
+}
+
+//
+// Record IDの欠落1件分
+// from_record, to_recordは欠落の前後で実際に記録されていたRecord ID
+//
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordGap {
+    pub computer: String,
+    pub channel: String,
+    pub from_record: u64,
+    pub to_record: u64,
+    #[serde(with = "state::rfc3339")]
+    pub from_time: Option<DateTime<Utc>>,
+    #[serde(with = "state::rfc3339")]
+    pub to_time: Option<DateTime<Utc>>,
+}
+
+impl RecordGap {
+    pub fn missing(&self) -> u64 {
+        return self.to_record - self.from_record - 1;
+    }
+}
+
+//
+// 監査ポリシーの変更、ログの消去、イベントログサービスの停止等を検知し、
+// Record IDの欠落と関連付けるために記録しておく
+//
+pub struct Tampering {
+    events: Vec<TamperEvent>,
+}
+
+impl Tampering {
+    pub fn new() -> Tampering {
+        Tampering { events: Vec::new() }
+    }
+
+    pub fn take_events(&mut self) -> Vec<TamperEvent> {
+        return std::mem::replace(&mut self.events, Vec::new());
+    }
+
+    pub fn detection(
+        &mut self,
+        channel: &str,
+        event_id: &str,
+        system: &event::System,
+        event_data: &HashMap<String, String>,
+        time: &Option<DateTime<Utc>>,
+    ) {
+        let default = String::from("");
+        let get = |key: &str| event_data.get(key).unwrap_or(&default).to_string();
+        let system_time = &system.time_created.system_time;
+        let provider = system.provider.name.as_ref().unwrap_or(&default);
+
+        // 1102, 104, 7040はSecurityとSystemで検知済みなので、ここでは記録だけする
+        let (description, msges) = match (channel, event_id) {
+            ("Security", "4719") => Tampering::audit_policy_changed(event_data, system_time),
+            ("Security", "4906") => Tampering::crash_on_audit_fail(event_data, system_time),
+            ("Security", "4907") => {
+                match Tampering::object_audit_changed(event_data, system_time) {
+                    Some(v) => v,
+                    None => return,
+                }
+            }
+            ("Security", "1100") => Tampering::simple_alert(
+                "Event logging service shut down",
+                "Event Logging Service Shut Down",
+                event_id,
+                system_time,
+            ),
+            ("Security", "1104") => Tampering::simple_alert(
+                "Security log full",
+                "Security Log Full",
+                event_id,
+                system_time,
+            ),
+            ("Security", "1102") => ("Security log cleared".to_string(), Option::None),
+            ("Security", "4688") | (SYSMON, "1") => {
+                match Tampering::log_clear_command(event_id, event_data, system_time) {
+                    Some(v) => v,
+                    None => return,
+                }
+            }
+            ("System", "104") => ("Event log cleared".to_string(), Option::None),
+            ("System", "7040") if get("param1") == "Windows Event Log" => (
+                format!("Event log service start type changed to {}", get("param2")),
+                Option::None,
+            ),
+            ("System", "6005") if provider == "EventLog" => {
+                ("Event log service started".to_string(), Option::None)
+            }
+            ("System", "6006") if provider == "EventLog" => (
+                "Event log service stopped (clean shutdown)".to_string(),
+                Option::None,
+            ),
+            ("System", "6008") if provider == "EventLog" => {
+                Tampering::unexpected_shutdown(event_data, system_time)
+            }
+            _ => return,
+        };
+
+        msges.and_then(Tampering::print_console);
+        self.events.push(TamperEvent {
+            computer: system.computer.to_string(),
+            channel: channel.to_string(),
+            event_id: event_id.to_string(),
+            time: *time,
+            description: description,
+        });
+    }
+
+    fn print_console(v: Vec<String>) -> Option<Vec<String>> {
+        return AlertMessages::output(v);
+    }
+
+    fn simple_alert(
+        description: &str,
+        message: &str,
+        event_id: &str,
+        system_time: &str,
+    ) -> (String, Option<Vec<String>>) {
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push(format!("Message: {}", message));
+        msges.push(format!("EventID: {}", event_id));
+        return (description.to_string(), Option::Some(msges));
+    }
+
+    // System audit policy was changed.
+    // 監査が無効にされた場合は重要度を上げる
+    fn audit_policy_changed(
+        event_data: &HashMap<String, String>,
+        system_time: &str,
+    ) -> (String, Option<Vec<String>>) {
+        let default = String::from("");
+        let get = |key: &str| event_data.get(key).unwrap_or(&default).to_string();
+        let subcategory = subcategory_name(&get("SubcategoryGuid"));
+        let changes: Vec<String> = get("AuditPolicyChanges")
+            .split(",")
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                AUDIT_POLICY_CHANGES
+                    .iter()
+                    .find(|(code, _)| *code == s)
+                    .map(|(_, name)| name.to_string())
+                    .unwrap_or(s.to_string())
+            })
+            .collect();
+        let changes = changes.join(", ");
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: System Audit Policy Changed".to_string());
+        msges.push("EventID: 4719".to_string());
+        msges.push(format!("Subcategory: {}", subcategory));
+        msges.push(format!("Changes: {}", changes));
+        msges.push(format!(
+            "Changed by: {}\\{}",
+            get("SubjectDomainName"),
+            get("SubjectUserName")
+        ));
+        if changes.contains("removed") {
+            msges.push("Level: high".to_string());
+        }
+
+        let description = format!("Audit policy changed: {} ({})", subcategory, changes);
+        return (description, Option::Some(msges));
+    }
+
+    // CrashOnAuditFail value has changed.
+    fn crash_on_audit_fail(
+        event_data: &HashMap<String, String>,
+        system_time: &str,
+    ) -> (String, Option<Vec<String>>) {
+        let default = String::from("");
+        let value = event_data.get("CrashOnAuditFailValue").unwrap_or(&default);
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: CrashOnAuditFail Changed".to_string());
+        msges.push("EventID: 4906".to_string());
+        msges.push(format!("New value: {}", value));
+
+        let description = format!("CrashOnAuditFail changed to {}", value);
+        return (description, Option::Some(msges));
+    }
+
+    // Auditing settings on object were changed.
+    // Windows Updateによる変更は対象外
+    fn object_audit_changed(
+        event_data: &HashMap<String, String>,
+        system_time: &str,
+    ) -> Option<(String, Option<Vec<String>>)> {
+        let default = String::from("");
+        let get = |key: &str| event_data.get(key).unwrap_or(&default).to_string();
+        let process = get("ProcessName");
+        let image = process.to_lowercase();
+        if image.ends_with("\\trustedinstaller.exe") || image.ends_with("\\tiworker.exe") {
+            return Option::None;
+        }
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: Object Audit Settings Changed".to_string());
+        msges.push("EventID: 4907".to_string());
+        msges.push(format!(
+            "Object: {} {}",
+            get("ObjectType"),
+            get("ObjectName")
+        ));
+        msges.push(format!("Process: {}", process));
+        msges.push(format!(
+            "Changed by: {}\\{}",
+            get("SubjectDomainName"),
+            get("SubjectUserName")
+        ));
+        msges.push(format!("Old SACL: {}", get("OldSd")));
+        msges.push(format!("New SACL: {}", get("NewSd")));
+
+        let description = format!(
+            "Audit settings changed on {} {}",
+            get("ObjectType"),
+            get("ObjectName")
+        );
+        return Option::Some((description, Option::Some(msges)));
+    }
+
+    // The previous system shutdown was unexpected.
+    fn unexpected_shutdown(
+        event_data: &HashMap<String, String>,
+        system_time: &str,
+    ) -> (String, Option<Vec<String>>) {
+        let default = String::from("");
+        let get = |key: &str| event_data.get(key).unwrap_or(&default).to_string();
+        // Data1が時刻、Data2が日付
+        let previous = format!("{} {}", get("Data2"), get("Data1"));
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: Unexpected Shutdown".to_string());
+        msges.push("EventID: 6008".to_string());
+        msges.push(format!("Previous shutdown: {}", previous.trim()));
+
+        let description = format!("Unexpected shutdown at {}", previous.trim());
+        return (description, Option::Some(msges));
+    }
+
+    // wevtutil cl, Clear-EventLogによるログの消去
+    fn log_clear_command(
+        event_id: &str,
+        event_data: &HashMap<String, String>,
+        system_time: &str,
+    ) -> Option<(String, Option<Vec<String>>)> {
+        let default = String::from("");
+        let command_line = event_data.get("CommandLine").unwrap_or(&default);
+        if !WEVTUTIL_CLEAR_REGEX.is_match(command_line)
+            && !POWERSHELL_CLEAR_REGEX.is_match(command_line)
+        {
+            return Option::None;
+        }
+
+        let image = if event_id == "4688" {
+            event_data.get("NewProcessName")
+        } else {
+            event_data.get("Image")
+        };
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push("Message: Event Log Cleared via Command Line".to_string());
+        msges.push(format!("EventID: {}", event_id));
+        msges.push(format!("Process: {}", image.unwrap_or(&default)));
+        msges.push(format!("Command: {}", command_line));
+
+        let description = format!("Event log cleared via command line: {}", command_line);
+        return Option::Some((description, Option::Some(msges)));
+    }
+}
+
+// {0cce922b-69ae-11d9-bed3-505054503030} -> Process Creation
+fn subcategory_name(guid: &str) -> String {
+    let key = guid.trim_start_matches('{').to_uppercase();
+    return AUDIT_SUBCATEGORIES
+        .iter()
+        .find(|(prefix, _)| key.starts_with(prefix) && key.contains("-69AE-11D9-"))
+        .map(|(_, name)| name.to_string())
+        .unwrap_or(guid.to_string());
+}
+
+pub fn correlation_window() -> i64 {
+    let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
+    return configs["tamper_correlation_window"].as_i64().unwrap_or(600);
+}
+
+//
+// Record IDの欠落の前後correlation_window秒以内に、同じコンピュータで記録された痕跡を関連付ける
+//
+pub fn correlate<'a>(
+    gap: &RecordGap,
+    events: &Vec<&'a TamperEvent>,
+    window: i64,
+) -> Vec<&'a TamperEvent> {
+    let (from, to) = match (gap.from_time, gap.to_time) {
+        (Some(from), Some(to)) => (
+            from - Duration::seconds(window),
+            to + Duration::seconds(window),
+        ),
+        _ => return vec![],
+    };
+    return events
+        .iter()
+        .filter(|event| event.computer == gap.computer)
+        .filter(|event| match event.time {
+            Some(time) => from <= time && time <= to,
+            None => false,
+        })
+        .copied()
+        .collect();
+}
+
+//
+// --summaryの最後に表示する、改ざんの痕跡とRecord IDの欠落の一覧
+//
+pub fn create_anti_forensics_report(
+    events: &Vec<&TamperEvent>,
+    gaps: &Vec<&RecordGap>,
+    time_formatter: &TimeFormatter,
+) -> Vec<String> {
+    if events.is_empty() && gaps.is_empty() {
+        return vec![];
+    }
+    let format_time = |time: &Option<DateTime<Utc>>| -> String {
+        return time
+            .map(|time| time_formatter.format(&time))
+            .unwrap_or("-".to_string());
+    };
+
+    let mut events = events.clone();
+    events.sort_by(|a, b| a.time.cmp(&b.time).then(a.computer.cmp(&b.computer)));
+    let mut msges: Vec<String> = Vec::new();
+    msges.push("==================== Anti-Forensics ====================".to_string());
+    msges.push("Time  Computer  Channel  EventID  Description".to_string());
+    events.iter().for_each(|event| {
+        msges.push(format!(
+            "{}  {}  {}  {}  {}",
+            format_time(&event.time),
+            event.computer,
+            event.channel,
+            event.event_id,
+            event.description
+        ));
+    });
+
+    if gaps.is_empty() {
+        return msges;
+    }
+    let window = correlation_window();
+    msges.push("".to_string());
+    msges.push("Record ID gaps:".to_string());
+    msges.push("From  To  Computer  Channel  Records  (Missing)".to_string());
+    gaps.iter().for_each(|gap| {
+        msges.push(format!(
+            "{}  {}  {}  {}  {} - {}  ({})",
+            format_time(&gap.from_time),
+            format_time(&gap.to_time),
+            gap.computer,
+            gap.channel,
+            gap.from_record,
+            gap.to_record,
+            gap.missing()
+        ));
+        correlate(gap, &events, window).iter().for_each(|event| {
+            msges.push(format!(
+                "    Related: {}  {} {}  {}",
+                format_time(&event.time),
+                event.channel,
+                event.event_id,
+                event.description
+            ));
+        });
+    });
+
+    return msges;
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate quick_xml;
+
+    use crate::detections::tampering;
+    use crate::detections::timestamp;
+    use crate::models::event;
+
+    fn parse(xml_str: &str) -> event::Evtx {
+        return quick_xml::de::from_str(xml_str).unwrap();
+    }
+
+    fn detect(tampering: &mut tampering::Tampering, event: &event::Evtx) {
+        tampering.detection(
+            &event.system.channel,
+            &event.system.event_id,
+            &event.system,
+            &event.parse_event_data(),
+            &timestamp::parse_system_time(&event.system.time_created.system_time),
+        );
+    }
+
+    #[test]
+    fn test_audit_policy_changed() {
+        let event = parse(&get_audit_policy_changed_xml());
+        let (description, msges) = tampering::Tampering::audit_policy_changed(
+            &event.parse_event_data(),
+            "2021-08-01T09:00:00Z",
+        );
+        assert_eq!(
+            "Audit policy changed: Process Creation (Success removed, Failure removed)",
+            description
+        );
+        let expected = vec![
+            "Date: 2021-08-01T09:00:00Z",
+            "Message: System Audit Policy Changed",
+            "EventID: 4719",
+            "Subcategory: Process Creation",
+            "Changes: Success removed, Failure removed",
+            "Changed by: CORP\\bob",
+            "Level: high",
+        ];
+        assert_eq!(expected, msges.unwrap());
+
+        // 監査の追加は重要度を上げない、知らないGUIDはそのまま表示する
+        let event = parse(
+            &get_audit_policy_changed_xml()
+                .replace(
+                    "{0cce922b-69ae-11d9-bed3-505054503030}",
+                    "{12345678-0000-0000-0000-000000000000}",
+                )
+                .replace("%%8448, %%8450", "%%8449"),
+        );
+        let (description, msges) = tampering::Tampering::audit_policy_changed(
+            &event.parse_event_data(),
+            "2021-08-01T09:00:00Z",
+        );
+        assert_eq!(
+            "Audit policy changed: {12345678-0000-0000-0000-000000000000} (Success added)",
+            description
+        );
+        assert!(!msges.unwrap().contains(&"Level: high".to_string()));
+    }
+
+    #[test]
+    fn test_log_clear_command() {
+        let data = |command_line: &str| {
+            let mut event_data = std::collections::HashMap::new();
+            event_data.insert("CommandLine".to_string(), command_line.to_string());
+            event_data.insert(
+                "Image".to_string(),
+                r"C:\Windows\System32\wevtutil.exe".to_string(),
+            );
+            return event_data;
+        };
+        let hits = vec![
+            "wevtutil cl Security",
+            r#""C:\Windows\System32\wevtutil.exe" clear-log System"#,
+            "powershell -c Clear-EventLog -LogName Security",
+            "powershell [System.Diagnostics.Eventing.Reader.EventLogSession]::GlobalSession.ClearLog('Security')",
+        ];
+        hits.iter().for_each(|command_line| {
+            let (_, msges) =
+                tampering::Tampering::log_clear_command("1", &data(command_line), "time").unwrap();
+            let msges = msges.unwrap();
+            assert_eq!("Message: Event Log Cleared via Command Line", msges[1]);
+            assert_eq!(r"Process: C:\Windows\System32\wevtutil.exe", msges[3]);
+        });
+
+        let no_hits = vec!["wevtutil qe Security /c:10", "wevtutil el", "Get-EventLog"];
+        no_hits.iter().for_each(|command_line| {
+            assert!(
+                tampering::Tampering::log_clear_command("1", &data(command_line), "time").is_none()
+            );
+        });
+    }
+
+    #[test]
+    fn test_create_anti_forensics_report() {
+        let mut tampering = tampering::Tampering::new();
+        detect(&mut tampering, &parse(&get_audit_log_cleared_xml()));
+        detect(&mut tampering, &parse(&get_eventlog_stopped_xml()));
+        // 別のプロバイダの6005は記録しない
+        detect(
+            &mut tampering,
+            &parse(
+                &get_eventlog_stopped_xml()
+                    .replace("<EventID>6006</EventID>", "<EventID>6005</EventID>")
+                    .replace("Name=\"EventLog\"", "Name=\"Microsoft-Windows-Foo\""),
+            ),
+        );
+        // 無関係なイベントは記録しない
+        detect(&mut tampering, &parse(&get_process_created_xml()));
+        let events = tampering.take_events();
+        assert_eq!(2, events.len());
+
+        let gap = tampering::RecordGap {
+            computer: "WS01".to_string(),
+            channel: "Security".to_string(),
+            from_record: 1200,
+            to_record: 1350,
+            from_time: timestamp::parse_system_time("2021-08-01T09:05:00Z"),
+            to_time: timestamp::parse_system_time("2021-08-01T09:06:00Z"),
+        };
+        assert_eq!(149, gap.missing());
+
+        let events: Vec<&tampering::TamperEvent> = events.iter().collect();
+        let formatter = timestamp::TimeFormatter::new();
+        let msges = tampering::create_anti_forensics_report(&events, &vec![&gap], &formatter);
+        let expected = vec![
+            "==================== Anti-Forensics ====================",
+            "Time  Computer  Channel  EventID  Description",
+            "2021-08-01T06:00:00.000000Z  WS01  System  6006  Event log service stopped (clean shutdown)",
+            "2021-08-01T09:10:00.000000Z  WS01  Security  1102  Security log cleared",
+            "",
+            "Record ID gaps:",
+            "From  To  Computer  Channel  Records  (Missing)",
+            "2021-08-01T09:05:00.000000Z  2021-08-01T09:06:00.000000Z  WS01  Security  1200 - 1350  (149)",
+            "    Related: 2021-08-01T09:10:00.000000Z  Security 1102  Security log cleared",
+        ];
+        assert_eq!(expected, msges);
+
+        let msges = tampering::create_anti_forensics_report(&vec![], &vec![], &formatter);
+        assert!(msges.is_empty());
+    }
+
+    fn get_audit_policy_changed_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-Security-Auditing"/>
+            <EventID>4719</EventID>
+            <Version>0</Version>
+            <Level>4</Level>
+            <Task>0</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x8020000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:00Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Channel>Security</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+          <EventData>
+            <Data Name="SubjectUserName">bob</Data>
+            <Data Name="SubjectDomainName">CORP</Data>
+            <Data Name="CategoryId">%%8276</Data>
+            <Data Name="SubcategoryId">%%13312</Data>
+            <Data Name="SubcategoryGuid">{0cce922b-69ae-11d9-bed3-505054503030}</Data>
+            <Data Name="AuditPolicyChanges">%%8448, %%8450</Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_audit_log_cleared_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-Eventlog"/>
+            <EventID>1102</EventID>
+            <Version>0</Version>
+            <Level>4</Level>
+            <Task>0</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x8020000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:10:00Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Channel>Security</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_eventlog_stopped_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="EventLog"/>
+            <EventID>6006</EventID>
+            <Version>0</Version>
+            <Level>4</Level>
+            <Task>0</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x8020000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T06:00:00Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Channel>System</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+        </Event>"#
+            .to_string();
+    }
+
+    fn get_process_created_xml() -> String {
+        return r#"
+        <Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event">
+          <System>
+            <Provider Name="Microsoft-Windows-Security-Auditing"/>
+            <EventID>4688</EventID>
+            <Version>0</Version>
+            <Level>4</Level>
+            <Task>0</Task>
+            <Opcode>0</Opcode>
+            <Keywords>0x8020000000000000</Keywords>
+            <TimeCreated SystemTime="2021-08-01T09:00:00Z"/>
+            <EventRecordID>100</EventRecordID>
+            <Channel>Security</Channel>
+            <Computer>WS01</Computer>
+            <Security/>
+          </System>
+          <EventData>
+            <Data Name="CommandLine">cmd.exe /c whoami</Data>
+          </EventData>
+        </Event>"#
+            .to_string();
+    }
+}
@@ -0,0 +1,463 @@
+extern crate quick_xml;
+
+use crate::detections::configs;
+use crate::detections::print::AlertMessages;
+use crate::detections::utils;
+use crate::models::event;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use regex::Regex;
+use std::collections::HashMap;
+
+lazy_static! {
+    // 一時フォルダとAppData
+    static ref TEMP_PATH_REGEX: Regex =
+        Regex::new(r"(?i)\\(AppData|Temp|Tmp)\\|%(temp|tmp|appdata|localappdata)%").unwrap();
+}
+
+// SYSTEMのアカウント名とSID
+const SYSTEM_ACCOUNTS: &[&str] = &["s-1-5-18", "system", "nt authority\\system", "localsystem"];
+
+// TaskContentのExecまたはComHandler
+#[derive(Debug, Clone, Default, PartialEq)]
+struct TaskAction {
+    command: String,
+    arguments: String,
+}
+
+impl TaskAction {
+    fn command_line(&self) -> String {
+        if self.arguments.is_empty() {
+            return self.command.to_string();
+        }
+        return format!("{} {}", self.command, self.arguments);
+    }
+}
+
+//
+// 4698と4702のTaskContent(タスクのXML)から取り出した値
+//
+#[derive(Debug, Clone, Default)]
+struct TaskDefinition {
+    author: String,
+    user_id: String,
+    run_level: String,
+    hidden: bool,
+    triggers: Vec<String>,
+    actions: Vec<TaskAction>,
+}
+
+impl TaskDefinition {
+    fn runs_as_system(&self) -> bool {
+        return SYSTEM_ACCOUNTS.contains(&self.user_id.to_lowercase().as_str());
+    }
+}
+
+// 削除との関連付けのため、タスクを最後に登録・実行した日時を覚えておく
+#[derive(Debug, Clone)]
+struct TaskHistory {
+    event_id: String,
+    time: DateTime<Utc>,
+    system_time: String,
+}
+
+//
+// Securityの4698-4702とTaskScheduler/Operationalからスケジュールされたタスクを検査する
+//
+pub struct ScheduledTask {
+    // key: ホスト名:タスク名(小文字)
+    histories: HashMap<String, TaskHistory>,
+}
+
+impl ScheduledTask {
+    pub fn new() -> ScheduledTask {
+        ScheduledTask {
+            histories: HashMap::new(),
+        }
+    }
+
+    pub fn detection(
+        &mut self,
+        channel: &str,
+        event_id: &str,
+        system: &event::System,
+        event_data: &HashMap<String, String>,
+        time: &Option<DateTime<Utc>>,
+    ) {
+        let default = String::from("");
+        let task_name = event_data.get("TaskName").unwrap_or(&default);
+        let system_time = &system.time_created.system_time;
+        match (channel, event_id) {
+            ("Security", "4698") | ("Security", "4702") => {
+                // 4702は更新後の内容がTaskContentNewに入る
+                let content = event_data
+                    .get("TaskContent")
+                    .or(event_data.get("TaskContentNew"))
+                    .unwrap_or(&default);
+                let task = parse_task_content(content);
+                self.task_registered(event_id, task_name, &task, system_time);
+                self.set_history(&system.computer, task_name, event_id, time, system_time);
+            }
+            ("Microsoft-Windows-TaskScheduler/Operational", "106")
+            | ("Microsoft-Windows-TaskScheduler/Operational", "140") => {
+                self.set_history(&system.computer, task_name, event_id, time, system_time);
+            }
+            ("Microsoft-Windows-TaskScheduler/Operational", "200")
+            | ("Microsoft-Windows-TaskScheduler/Operational", "201") => {
+                // ActionNameは実行したファイルのパス
+                let action = event_data.get("ActionName").unwrap_or(&default);
+                self.task_action_from_temp(event_id, task_name, action, system_time);
+                self.set_history(&system.computer, task_name, event_id, time, system_time);
+            }
+            ("Security", "4699") | ("Microsoft-Windows-TaskScheduler/Operational", "141") => {
+                self.task_deleted(&system.computer, event_id, task_name, time, system_time);
+            }
+            _ => (),
+        }
+    }
+
+    fn set_history(
+        &mut self,
+        computer: &str,
+        task_name: &str,
+        event_id: &str,
+        time: &Option<DateTime<Utc>>,
+        system_time: &String,
+    ) {
+        if let Some(time) = time {
+            self.histories.insert(
+                format!("{}:{}", computer, task_name.to_lowercase()),
+                TaskHistory {
+                    event_id: event_id.to_string(),
+                    time: *time,
+                    system_time: system_time.to_string(),
+                },
+            );
+        }
+    }
+
+    fn task_registered(
+        &self,
+        event_id: &str,
+        task_name: &str,
+        task: &TaskDefinition,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        // 各アクションのコマンドラインはシグネチャ等で検査する
+        let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
+        let minlength = configs["minlength"].as_i64().unwrap_or(1000) as usize;
+        task.actions.iter().for_each(|action| {
+            utils::check_command(
+                event_id.parse().unwrap_or(0),
+                &action.command_line(),
+                minlength,
+                &[],
+                "",
+                system_time,
+            );
+        });
+
+        let mut results: Vec<String> = vec![];
+        if task
+            .actions
+            .iter()
+            .any(|action| TEMP_PATH_REGEX.is_match(&action.command))
+        {
+            results.push("Task runs a program in a temp or AppData folder".to_string());
+        }
+        if task.runs_as_system() && task.hidden {
+            results.push("Hidden task runs as SYSTEM".to_string());
+        }
+        if results.is_empty() {
+            return Option::None;
+        }
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push(format!("EventID: {}", event_id));
+        msges.push("Message: Suspicious Scheduled Task".to_string());
+        msges.push(format!("Task name: {}", task_name));
+        msges.push(format!("Author: {}", task.author));
+        msges.push(format!(
+            "Principal: {} (RunLevel: {})",
+            task.user_id, task.run_level
+        ));
+        msges.push(format!("Triggers: {}", task.triggers.join(", ")));
+        task.actions.iter().for_each(|action| {
+            msges.push(format!("Action: {}", action.command_line()));
+        });
+        results.iter().for_each(|result| {
+            msges.push(format!("Results: {}", result));
+        });
+        return AlertMessages::output(msges);
+    }
+
+    fn task_action_from_temp(
+        &self,
+        event_id: &str,
+        task_name: &str,
+        action: &str,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        if !TEMP_PATH_REGEX.is_match(action) {
+            return Option::None;
+        }
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push(format!("EventID: {}", event_id));
+        msges.push("Message: Suspicious Scheduled Task".to_string());
+        msges.push(format!("Task name: {}", task_name));
+        msges.push(format!("Action: {}", action));
+        msges.push("Results: Task runs a program in a temp or AppData folder".to_string());
+        return AlertMessages::output(msges);
+    }
+
+    // 登録や実行から一定時間以内に削除されたタスク (Impacket atexec等)
+    fn task_deleted(
+        &mut self,
+        computer: &str,
+        event_id: &str,
+        task_name: &str,
+        time: &Option<DateTime<Utc>>,
+        system_time: &String,
+    ) -> Option<Vec<String>> {
+        let history =
+            self.histories
+                .remove(&format!("{}:{}", computer, task_name.to_lowercase()))?;
+        let elapsed = time.as_ref()?.signed_duration_since(history.time);
+        if elapsed.num_seconds() < 0 || elapsed.num_seconds() > delete_window() {
+            return Option::None;
+        }
+
+        let mut msges: Vec<String> = Vec::new();
+        msges.push(format!("Date: {}", system_time));
+        msges.push(format!("EventID: {}", event_id));
+        msges.push("Message: Scheduled Task Deleted Shortly After Running".to_string());
+        msges.push(format!("Task name: {}", task_name));
+        msges.push(format!(
+            "Results: Deleted {} seconds after EventID {} at {}",
+            elapsed.num_seconds(),
+            history.event_id,
+            history.system_time
+        ));
+        return AlertMessages::output(msges);
+    }
+}
+
+fn delete_window() -> i64 {
+    let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
+    return configs["task_delete_window"].as_i64().unwrap_or(300);
+}
+
+//
+// TaskContentのXMLからPrincipal、Settings、Triggers、Actionsを取り出す
+// 壊れたXMLはそこまでに読めた値を返す
+//
+fn parse_task_content(content: &str) -> TaskDefinition {
+    let mut task = TaskDefinition::default();
+    let mut reader = Reader::from_str(content);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut path: Vec<String> = vec![];
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) => {
+                let name = String::from_utf8_lossy(e.name()).to_string();
+                task_element(&mut task, &path, &name);
+                path.push(name);
+            }
+            Ok(Event::Empty(ref e)) => {
+                let name = String::from_utf8_lossy(e.name()).to_string();
+                task_element(&mut task, &path, &name);
+            }
+            Ok(Event::Text(ref e)) => {
+                let text = e.unescape_and_decode(&reader).unwrap_or_default();
+                task_text(&mut task, &path, text);
+            }
+            Ok(Event::End(_)) => {
+                path.pop();
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+    return task;
+}
+
+fn task_element(task: &mut TaskDefinition, path: &Vec<String>, name: &str) {
+    match path.last().map(|s| s.as_str()) {
+        Some("Triggers") => task.triggers.push(name.to_string()),
+        Some("Actions") if name == "Exec" || name == "ComHandler" => {
+            task.actions.push(TaskAction::default())
+        }
+        _ => (),
+    }
+}
+
+fn task_text(task: &mut TaskDefinition, path: &Vec<String>, text: String) {
+    let len = path.len();
+    if len < 2 {
+        return;
+    }
+    match (path[len - 2].as_str(), path[len - 1].as_str()) {
+        ("RegistrationInfo", "Author") => task.author = text,
+        ("Principal", "UserId") => task.user_id = text,
+        ("Principal", "GroupId") if task.user_id.is_empty() => task.user_id = text,
+        ("Principal", "RunLevel") => task.run_level = text,
+        ("Settings", "Hidden") => task.hidden = text.trim() == "true",
+        ("Exec", "Command") | ("ComHandler", "ClassId") => {
+            if let Some(action) = task.actions.last_mut() {
+                action.command = text;
+            }
+        }
+        ("Exec", "Arguments") | ("ComHandler", "Data") => {
+            if let Some(action) = task.actions.last_mut() {
+                action.arguments = text;
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::detections::task;
+    use chrono::{TimeZone, Utc};
+
+    fn get_hidden_task_xml() -> String {
+        return r#"<?xml version="1.0" encoding="UTF-16"?>
+<Task version="1.2" xmlns="http://schemas.microsoft.com/windows/2004/02/mit/task">
+  <RegistrationInfo>
+    <Author>CORP\user</Author>
+  </RegistrationInfo>
+  <Triggers>
+    <LogonTrigger>
+      <Enabled>true</Enabled>
+    </LogonTrigger>
+    <TimeTrigger>
+      <StartBoundary>2021-08-01T09:00:00</StartBoundary>
+    </TimeTrigger>
+  </Triggers>
+  <Principals>
+    <Principal id="Author">
+      <UserId>S-1-5-18</UserId>
+      <RunLevel>HighestAvailable</RunLevel>
+    </Principal>
+  </Principals>
+  <Settings>
+    <Hidden>true</Hidden>
+  </Settings>
+  <Actions Context="Author">
+    <Exec>
+      <Command>C:\Windows\System32\cmd.exe</Command>
+      <Arguments>/c whoami &gt; C:\Windows\Temp\out.tmp</Arguments>
+    </Exec>
+  </Actions>
+</Task>"#
+            .to_string();
+    }
+
+    #[test]
+    fn test_parse_task_content() {
+        let task = task::parse_task_content(&get_hidden_task_xml());
+        assert_eq!("CORP\\user", task.author);
+        assert_eq!("S-1-5-18", task.user_id);
+        assert_eq!("HighestAvailable", task.run_level);
+        assert!(task.hidden);
+        assert!(task.runs_as_system());
+        assert_eq!(vec!["LogonTrigger", "TimeTrigger"], task.triggers);
+        assert_eq!(1, task.actions.len());
+        assert_eq!(
+            "C:\\Windows\\System32\\cmd.exe /c whoami > C:\\Windows\\Temp\\out.tmp",
+            task.actions[0].command_line()
+        );
+
+        // 壊れたXMLでもそこまでの値は読める
+        let task = task::parse_task_content("<Task><RegistrationInfo><Author>a</Author>");
+        assert_eq!("a", task.author);
+        assert!(task.actions.is_empty());
+    }
+
+    #[test]
+    fn test_task_registered() {
+        let st = task::ScheduledTask::new();
+        let content = get_hidden_task_xml()
+            .replace(
+                r"<Command>C:\Windows\System32\cmd.exe</Command>",
+                r"<Command>%LOCALAPPDATA%\Temp\update.exe</Command>",
+            )
+            .replace(
+                "<UserId>S-1-5-18</UserId>",
+                r"<UserId>NT AUTHORITY\SYSTEM</UserId>",
+            );
+        let v = st
+            .task_registered(
+                "4698",
+                "\\Updater",
+                &task::parse_task_content(&content),
+                &"".to_string(),
+            )
+            .unwrap();
+        assert_eq!("Message: Suspicious Scheduled Task", v[2]);
+        assert_eq!("Task name: \\Updater", v[3]);
+        assert_eq!("Triggers: LogonTrigger, TimeTrigger", v[6]);
+        assert_eq!(
+            "Results: Task runs a program in a temp or AppData folder",
+            v[8]
+        );
+        assert_eq!("Results: Hidden task runs as SYSTEM", v[9]);
+
+        // 隠しでないSYSTEMのタスクは問題ない
+        let content =
+            get_hidden_task_xml().replace("<Hidden>true</Hidden>", "<Hidden>false</Hidden>");
+        assert!(st
+            .task_registered(
+                "4698",
+                "\\Updater",
+                &task::parse_task_content(&content),
+                &"".to_string(),
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn test_task_deleted() {
+        let mut st = task::ScheduledTask::new();
+        let run = Option::Some(Utc.ymd(2021, 8, 1).and_hms(9, 0, 0));
+        st.set_history(
+            "WS01",
+            "\\ZgXAtkyA",
+            "201",
+            &run,
+            &"2021-08-01T09:00:00Z".to_string(),
+        );
+        // 他のタスクやホストは関係ない
+        let deleted = Option::Some(Utc.ymd(2021, 8, 1).and_hms(9, 0, 5));
+        assert!(st
+            .task_deleted("WS02", "4699", "\\ZgXAtkyA", &deleted, &"".to_string())
+            .is_none());
+
+        let v = st
+            .task_deleted("WS01", "4699", "\\zgxatkya", &deleted, &"".to_string())
+            .unwrap();
+        assert_eq!(
+            "Message: Scheduled Task Deleted Shortly After Running",
+            v[2]
+        );
+        assert_eq!(
+            "Results: Deleted 5 seconds after EventID 201 at 2021-08-01T09:00:00Z",
+            v[4]
+        );
+
+        // 長く使われたタスクの削除は問題ない
+        st.set_history("WS01", "\\Backup", "200", &run, &"".to_string());
+        let deleted = Option::Some(Utc.ymd(2021, 9, 1).and_hms(9, 0, 0));
+        assert!(st
+            .task_deleted("WS01", "141", "\\Backup", &deleted, &"".to_string())
+            .is_none());
+    }
+}
@@ -0,0 +1,243 @@
+extern crate chrono;
+extern crate chrono_tz;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{
+    DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use clap::ArgMatches;
+
+#[derive(Debug, Clone)]
+enum OutputTimezone {
+    Utc,
+    Local,
+    Offset(FixedOffset),
+    Named(Tz),
+}
+
+//
+// --timezoneと--time-formatに従って日時を表示用の文字列に変換する
+// 指定がない場合はUTCのRFC3339(2019-04-27T21:04:25.733401Z)で表示する
+//
+#[derive(Debug, Clone)]
+pub struct TimeFormatter {
+    timezone: OutputTimezone,
+    // Noneの場合はRFC3339
+    format: Option<String>,
+}
+
+impl TimeFormatter {
+    pub fn new() -> TimeFormatter {
+        TimeFormatter {
+            timezone: OutputTimezone::Utc,
+            format: Option::None,
+        }
+    }
+
+    pub fn from_args(args: &ArgMatches) -> Result<TimeFormatter, String> {
+        return TimeFormatter::build(args.value_of("timezone"), args.value_of("time-format"));
+    }
+
+    fn build(timezone: Option<&str>, format: Option<&str>) -> Result<TimeFormatter, String> {
+        let mut formatter = TimeFormatter::new();
+        if let Some(timezone) = timezone {
+            formatter.timezone = TimeFormatter::parse_timezone(timezone)?;
+        }
+        if let Some(format) = format {
+            if format.to_lowercase() != "rfc3339" {
+                // 不正な書式はformat時にpanicするので、ここで弾く
+                if format.is_empty() || StrftimeItems::new(format).any(|item| item == Item::Error) {
+                    return Result::Err(format!("invalid time format: {}", format));
+                }
+                formatter.format = Option::Some(format.to_string());
+            }
+        }
+        return Result::Ok(formatter);
+    }
+
+    // UTC, local, +09:00のようなオフセット, Asia/TokyoのようなIANAのタイムゾーン名を指定できる
+    fn parse_timezone(timezone: &str) -> Result<OutputTimezone, String> {
+        let lower = timezone.to_lowercase();
+        if lower == "utc" || lower == "z" {
+            return Result::Ok(OutputTimezone::Utc);
+        }
+        if lower == "local" {
+            return Result::Ok(OutputTimezone::Local);
+        }
+        if timezone.starts_with("+") || timezone.starts_with("-") {
+            // 日付を付けてRFC3339としてパースすることでオフセットだけを取り出す
+            if let Ok(datetime) =
+                DateTime::parse_from_rfc3339(&format!("2000-01-01T00:00:00{}", timezone))
+            {
+                return Result::Ok(OutputTimezone::Offset(*datetime.offset()));
+            }
+        }
+        if let Ok(tz) = timezone.parse::<Tz>() {
+            return Result::Ok(OutputTimezone::Named(tz));
+        }
+        return Result::Err(format!(
+            "invalid timezone: {}. use UTC, local, an offset like +09:00 or a name like Asia/Tokyo.",
+            timezone
+        ));
+    }
+
+    pub fn format(&self, datetime: &DateTime<Utc>) -> String {
+        return match &self.timezone {
+            OutputTimezone::Utc => self.format_with_tz(datetime),
+            OutputTimezone::Local => self.format_with_tz(&datetime.with_timezone(&Local)),
+            OutputTimezone::Offset(offset) => self.format_with_tz(&datetime.with_timezone(offset)),
+            OutputTimezone::Named(tz) => self.format_with_tz(&datetime.with_timezone(tz)),
+        };
+    }
+
+    fn format_with_tz<T: TimeZone>(&self, datetime: &DateTime<T>) -> String
+    where
+        T::Offset: std::fmt::Display,
+    {
+        return match &self.format {
+            Some(format) => datetime.format(format).to_string(),
+            None => datetime.to_rfc3339_opts(SecondsFormat::Micros, true),
+        };
+    }
+
+    // TimeCreated/SystemTimeを表示用に変換する。パースできない場合はそのまま返す
+    pub fn format_system_time(&self, system_time: &str) -> String {
+        return match parse_system_time(system_time) {
+            Some(datetime) => self.format(&datetime),
+            None => system_time.to_string(),
+        };
+    }
+}
+
+//
+// TimeCreated/SystemTimeをUTCの日時に変換する
+// evtxから変換した場合は"2019-04-27 21:04:25.733401 UTC"、
+// wevtutil等で出力したXMLの場合は"2019-04-27T21:04:25.7334010Z"の形式になっている
+//
+pub fn parse_system_time(system_time: &str) -> Option<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(system_time) {
+        return Option::Some(datetime.with_timezone(&Utc));
+    }
+
+    return NaiveDateTime::parse_from_str(system_time, "%Y-%m-%d %H:%M:%S%.f UTC")
+        .ok()
+        .map(|naive| Utc.from_utc_datetime(&naive));
+}
+
+//
+// コマンドラインで指定された日時をUTCの日時に変換する
+// オフセットがない日時(2021-08-01 09:00:00など)はUTCとして扱う
+//
+pub fn parse_arg_time(arg: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(arg) {
+        return Result::Ok(datetime.with_timezone(&Utc));
+    }
+
+    for format in &["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(arg, format) {
+            return Result::Ok(Utc.from_utc_datetime(&naive));
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(arg, "%Y-%m-%d") {
+        return Result::Ok(Utc.from_utc_datetime(&date.and_hms(0, 0, 0)));
+    }
+
+    return Result::Err(format!(
+        "invalid time: {}. use RFC3339 (e.g. 2021-08-01T00:00:00Z, 2021-08-01T09:00:00+09:00). times without an offset are treated as UTC.",
+        arg
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::detections::timestamp;
+    use crate::detections::timestamp::TimeFormatter;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_parse_system_time() {
+        assert_eq!(
+            Option::Some(Utc.ymd(2019, 4, 27).and_hms_micro(21, 4, 25, 733401)),
+            timestamp::parse_system_time("2019-04-27 21:04:25.733401 UTC")
+        );
+        assert_eq!(
+            Option::Some(Utc.ymd(2013, 10, 23).and_hms_micro(16, 22, 39, 973500)),
+            timestamp::parse_system_time("2013-10-23T16:22:39.9735000Z")
+        );
+        assert_eq!(
+            Option::None,
+            timestamp::parse_system_time("9/19/2016 9:13:04 PM")
+        );
+    }
+
+    #[test]
+    fn test_parse_arg_time() {
+        assert_eq!(
+            Result::Ok(Utc.ymd(2021, 8, 1).and_hms(0, 0, 0)),
+            timestamp::parse_arg_time("2021-08-01T09:00:00+09:00")
+        );
+        assert_eq!(
+            Result::Ok(Utc.ymd(2021, 8, 1).and_hms(9, 0, 0)),
+            timestamp::parse_arg_time("2021-08-01 09:00:00")
+        );
+        assert_eq!(
+            Result::Ok(Utc.ymd(2021, 8, 1).and_hms(0, 0, 0)),
+            timestamp::parse_arg_time("2021-08-01")
+        );
+        assert!(timestamp::parse_arg_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_format_default() {
+        let formatter = TimeFormatter::new();
+        assert_eq!(
+            "2019-04-27T21:04:25.733401Z",
+            formatter.format_system_time("2019-04-27 21:04:25.733401 UTC")
+        );
+        assert_eq!(
+            "2013-10-23T16:22:39.973500Z",
+            formatter.format_system_time("2013-10-23T16:22:39.9735000Z")
+        );
+        // パースできない場合はそのまま
+        assert_eq!(
+            "9/19/2016 9:13:04 PM",
+            formatter.format_system_time("9/19/2016 9:13:04 PM")
+        );
+    }
+
+    #[test]
+    fn test_format_timezone() {
+        let datetime = Utc.ymd(2019, 4, 27).and_hms_micro(21, 4, 25, 733401);
+
+        let formatter = TimeFormatter::build(Option::Some("+09:00"), Option::None).unwrap();
+        assert_eq!(
+            "2019-04-28T06:04:25.733401+09:00",
+            formatter.format(&datetime)
+        );
+
+        let formatter =
+            TimeFormatter::build(Option::Some("America/New_York"), Option::None).unwrap();
+        assert_eq!(
+            "2019-04-27T17:04:25.733401-04:00",
+            formatter.format(&datetime)
+        );
+
+        let formatter = TimeFormatter::build(
+            Option::Some("Asia/Tokyo"),
+            Option::Some("%Y/%m/%d %H:%M:%S %z"),
+        )
+        .unwrap();
+        assert_eq!("2019/04/28 06:04:25 +0900", formatter.format(&datetime));
+
+        let formatter = TimeFormatter::build(Option::Some("utc"), Option::Some("rfc3339")).unwrap();
+        assert_eq!("2019-04-27T21:04:25.733401Z", formatter.format(&datetime));
+    }
+
+    #[test]
+    fn test_build_formatter_error() {
+        assert!(TimeFormatter::build(Option::Some("Mars/Olympus"), Option::None).is_err());
+        assert!(TimeFormatter::build(Option::Some("+25:00"), Option::None).is_err());
+        assert!(TimeFormatter::build(Option::None, Option::Some("%Y-%m-%d %Q")).is_err());
+    }
+}
@@ -1,29 +1,82 @@
-extern crate base64;
 extern crate csv;
 extern crate regex;
 
 use crate::detections::configs;
-use crate::detections::print::MessageNotation;
-use flate2::read::GzDecoder;
-use std::io::prelude::*;
-use std::str;
+use crate::detections::deobfuscate;
+use crate::detections::heuristic::ProcessHeuristic;
+use crate::detections::level::Level;
+use crate::detections::mitre;
+use crate::detections::print::AlertMessages;
+use crate::detections::signature::{Signature, Target};
 use std::string::String;
 
+//
+// コマンドラインの検査結果
+//
+pub struct CommandResult {
+    // 不審な点を1行ずつ
+    pub text: String,
+    pub layers: Vec<deobfuscate::Layer>,
+    // 一致したシグネチャの中で最も高い重要度
+    pub level: Option<Level>,
+    pub techniques: Vec<String>,
+}
+
+// heuristicsは4688とSysmonの1で一致したprocess_heuristics.ymlの親子関係
+// pathは4104のスクリプトをファイルから読み込んだ場合のパス
 pub fn check_command(
     event_id: usize,
     commandline: &str,
     minlength: usize,
-    servicecmd: usize,
-    servicename: &str,
-    creator: &str,
+    heuristics: &[&ProcessHeuristic],
+    path: &str,
     system_time: &String,
 ) {
+    // 4104はスクリプトブロック
+    let target = if event_id == 4104 {
+        Target::ScriptBlock
+    } else {
+        Target::CommandLine
+    };
+    let result = match inspect_command(commandline, minlength, target, heuristics) {
+        Some(result) => result,
+        None => return,
+    };
+
+    let mut msges: Vec<String> = Vec::new();
+    msges.push(format!("Date: {}", system_time));
+    msges.push(format!("EventID: {}", event_id));
+    msges.push("Message: Suspicious Command Line".to_string());
+    if !path.is_empty() {
+        msges.push(format!("Path: {}", path));
+    }
+    msges.push(format!("command: {}", commandline));
+    result.layers.iter().enumerate().for_each(|(i, layer)| {
+        msges.push(format!("Layer {} ({}): {}", i + 1, layer.name, layer.text));
+    });
+    msges.push(format!("result: {}", result.text));
+    if let Some(level) = result.level {
+        msges.push(format!("Level: {}", level));
+    }
+    if !result.techniques.is_empty() {
+        msges.push(format!("MITRE ATT&CK: {}", result.techniques.join(", ")));
+    }
+    AlertMessages::output(msges);
+}
+
+// 不審な点がなければNoneを返す
+pub fn inspect_command(
+    commandline: &str,
+    minlength: usize,
+    target: Target,
+    heuristics: &[&ProcessHeuristic],
+) -> Option<CommandResult> {
     let mut text = "".to_string();
-    let mut base64 = "".to_string();
+    let mut signatures: Vec<&Signature> = vec![];
 
     for regex in &configs::CONFIG.whitelist_regex {
         if regex.is_match(commandline) {
-            return;
+            return Option::None;
         }
     }
 
@@ -33,77 +86,58 @@ pub fn check_command(
         text.push_str("bytes\n");
     }
     text.push_str(&check_obfu(commandline));
-    text.push_str(&check_regex(commandline, 0));
-    text.push_str(&check_creator(commandline, creator));
-    if configs::CONFIG.encode_regex.is_match(commandline) {
-        base64.push_str(
-            &configs::CONFIG
-                .encoded_command_regex
-                .replace_all(commandline, ""),
-        );
-    } else if configs::CONFIG.base64_regex.is_match(commandline) {
-        base64.push_str(
-            &configs::CONFIG
-                .base64_with_before_after_regex
-                .replace_all(commandline, ""),
-        );
-        base64.push_str(
-            &configs::CONFIG
-                .singlequote_regex
-                .replace_all(&base64.to_string(), ""),
-        );
-    }
-    if let Ok(decoded) = base64::decode(&base64) {
-        if !base64.is_empty() {
-            if configs::CONFIG.compress_regex.is_match(commandline) {
-                let mut d = GzDecoder::new(decoded.as_slice());
-                let mut uncompressed = String::new();
-                let stdout = std::io::stdout();
-                let mut stdout = stdout.lock();
-                d.read_to_string(&mut uncompressed).unwrap();
-                MessageNotation::info_noheader(&mut stdout, format!("Decoded: {}", uncompressed))
-                    .ok();
-                text.push_str("Base64-encoded and compressed function\n");
-            } else {
-                let stdout = std::io::stdout();
-                let mut stdout = stdout.lock();
-                MessageNotation::info_noheader(
-                    &mut stdout,
-                    format!("Decoded: {}", str::from_utf8(decoded.as_slice()).unwrap()),
-                )
-                .ok();
-                text.push_str("Base64-encoded function\n");
-                text.push_str(&check_obfu(str::from_utf8(decoded.as_slice()).unwrap()));
-                text.push_str(&check_regex(str::from_utf8(decoded.as_slice()).unwrap(), 0));
-            }
-        }
+    signatures.extend(match_signatures(commandline, target));
+    text.push_str(&signatures_to_text(&signatures));
+    heuristics.iter().for_each(|heuristic| {
+        text.push_str(&heuristic.description);
+        text.push_str("\n");
+    });
+    // 難読化を解除した最終的な平文をもう一度検査する
+    let layers = deobfuscate::deobfuscate(commandline, max_depth());
+    if let Some(cleartext) = layers.last().map(|layer| &layer.text) {
+        layers
+            .iter()
+            .filter(|layer| layer.is_encoding)
+            .for_each(|layer| {
+                text.push_str(&layer.name);
+                text.push_str("\n");
+            });
+        text.push_str(&check_obfu(cleartext));
+        let decoded_signatures: Vec<&Signature> = match_signatures(cleartext, target)
+            .into_iter()
+            .filter(|s| !signatures.iter().any(|matched| std::ptr::eq(*matched, *s)))
+            .collect();
+        text.push_str(&signatures_to_text(&decoded_signatures));
+        signatures.extend(decoded_signatures);
     }
-    if !text.is_empty() {
-        let stdout = std::io::stdout();
-        let mut stdout = stdout.lock();
-        MessageNotation::info_noheader(&mut stdout, format!("Date: {}", system_time)).ok();
-        MessageNotation::info_noheader(&mut stdout, format!("EventID: {}", event_id)).ok();
-        if servicecmd != 0 {
-            MessageNotation::info_noheader(
-                &mut stdout,
-                format!("Message: Suspicious Service Command"),
-            )
-            .ok();
-            MessageNotation::info_noheader(
-                &mut stdout,
-                format!("Results: Service name: {}\n", servicename),
-            )
-            .ok();
-        } else {
-            MessageNotation::info_noheader(
-                &mut stdout,
-                format!("Message: Suspicious Command Line"),
-            )
-            .ok();
-        }
-        MessageNotation::info_noheader(&mut stdout, format!("command: {}", commandline)).ok();
-        MessageNotation::info_noheader(&mut stdout, format!("result: {}", text)).ok();
+    if text.is_empty() {
+        return Option::None;
     }
+
+    let mut techniques = mitre::techniques_of_results(&text);
+    signatures
+        .iter()
+        .for_each(|s| techniques.extend(s.techniques.iter().cloned()));
+    heuristics
+        .iter()
+        .for_each(|h| techniques.extend(h.techniques.iter().cloned()));
+    techniques.sort();
+    techniques.dedup();
+    return Option::Some(CommandResult {
+        text: text,
+        layers: layers,
+        level: signatures
+            .iter()
+            .filter_map(|s| s.level)
+            .chain(heuristics.iter().filter_map(|h| h.level))
+            .max(),
+        techniques: techniques,
+    });
+}
+
+fn max_depth() -> usize {
+    let configs: &yaml_rust::Yaml = &configs::CONFIG.configs;
+    return configs["deobfuscation_maxdepth"].as_i64().unwrap_or(10) as usize;
 }
 
 fn check_obfu(string: &str) -> std::string::String {
@@ -147,75 +181,57 @@ fn check_obfu(string: &str) -> std::string::String {
     return obfutext;
 }
 
-pub fn check_regex(string: &str, r#type: usize) -> std::string::String {
-    let empty = "".to_string();
-    let mut regextext = "".to_string();
-    for line in &configs::CONFIG.regex {
-        let type_str = line.get(0).unwrap_or(&empty);
-        if type_str != &r#type.to_string() {
-            continue;
-        }
-
-        let regex_str = line.get(1).unwrap_or(&empty);
-        if regex_str.is_empty() {
-            continue;
-        }
-
-        if configs::CONFIG
-            .regexes
-            .get(regex_str)
-            .unwrap()
-            .is_match(string)
-            == false
-        {
-            continue;
-        }
+pub fn check_regex(string: &str, target: Target) -> std::string::String {
+    return signatures_to_text(&match_signatures(string, target));
+}
 
-        let text = line.get(2).unwrap_or(&empty);
-        if text.is_empty() {
-            continue;
-        }
+pub fn match_signatures(string: &str, target: Target) -> Vec<&'static Signature> {
+    return configs::CONFIG.signatures.matches(string, target);
+}
 
-        regextext.push_str(text);
+fn signatures_to_text(signatures: &Vec<&Signature>) -> std::string::String {
+    let mut regextext = "".to_string();
+    signatures.iter().for_each(|signature| {
+        regextext.push_str(&signature.description);
         regextext.push_str("\n");
-    }
-
+    });
     return regextext;
 }
 
-fn check_creator(command: &str, creator: &str) -> std::string::String {
-    let mut creatortext = "".to_string();
-    if !creator.is_empty() {
-        if command == "powershell" {
-            if creator == "PSEXESVC" {
-                creatortext.push_str("PowerShell launched via PsExec: ");
-                creatortext.push_str(creator);
-                creatortext.push_str("\n");
-            } else if creator == "WmiPrvSE" {
-                creatortext.push_str("PowerShell launched via WMI: ");
-                creatortext.push_str(creator);
-                creatortext.push_str("\n");
-            }
-        }
-    }
-    return creatortext;
-}
-
 #[cfg(test)]
 mod tests {
+    use crate::detections::configs;
+    use crate::detections::level::Level;
+    use crate::detections::signature::Target;
     use crate::detections::utils;
     #[test]
     fn test_check_regex() {
-        let regextext = utils::check_regex("\\cvtres.exe", 0);
+        let regextext = utils::check_regex("\\cvtres.exe", Target::CommandLine);
         assert!(regextext == "Resource File To COFF Object Conversion Utility cvtres.exe\n");
+        let regextext = utils::check_regex("\\cvtres.exe", Target::ServiceName);
+        assert!(regextext.is_empty());
+
+        let signatures = utils::match_signatures("Invoke-Mimikatz -DumpCreds", Target::ScriptBlock);
+        assert_eq!(1, signatures.len());
+        assert_eq!(Option::Some(Level::Critical), signatures[0].level);
+        assert_eq!(vec!["T1003.001"], signatures[0].techniques);
     }
 
     #[test]
-    fn test_check_creator() {
-        let mut creatortext = utils::check_creator("powershell", "PSEXESVC");
-        assert!(creatortext == "PowerShell launched via PsExec: PSEXESVC\n");
-        creatortext = utils::check_creator("powershell", "WmiPrvSE");
-        assert!(creatortext == "PowerShell launched via WMI: WmiPrvSE\n");
+    fn test_inspect_command_heuristics() {
+        let heuristic = configs::CONFIG
+            .process_heuristics
+            .iter()
+            .find(|h| h.description == "Shell launched via PsExec")
+            .unwrap();
+        assert!(utils::inspect_command("whoami", 1000, Target::CommandLine, &[]).is_none());
+
+        // 親子関係に一致しただけでも検知する
+        let result =
+            utils::inspect_command("whoami", 1000, Target::CommandLine, &[heuristic]).unwrap();
+        assert_eq!("Shell launched via PsExec\n", result.text);
+        assert_eq!(heuristic.level, result.level);
+        assert_eq!(vec!["T1021.002", "T1569.002"], result.techniques);
     }
 
     #[test]
@@ -226,24 +242,15 @@ mod tests {
 
     #[test]
     fn test_check_command() {
-        utils::check_command(
-            1,
-            "dir",
-            100,
-            100,
-            "dir",
-            "dir",
-            &"9/19/2016 9:13:04 PM".to_string(),
-        );
+        utils::check_command(1, "dir", 100, &[], "", &"9/19/2016 9:13:04 PM".to_string());
 
         //test return with whitelist.
         utils::check_command(
             1,
             "\"C:\\Program Files\\Google\\Update\\GoogleUpdate.exe\"",
             100,
-            100,
-            "dir",
-            "dir",
+            &[],
+            "",
             &"9/19/2016 9:13:04 PM".to_string(),
         );
     }
@@ -7,6 +7,7 @@ use std::{fs, io};
 
 use yaml_rust::YamlLoader;
 
+use crate::detections::mitre;
 use crate::detections::print::MessageNotation;
 
 pub struct ParseYaml {
@@ -47,6 +48,15 @@ impl ParseYaml {
         return Result::Ok(());
     }
 
+    // ルールのtags:のattack.tXXXXを、検知結果に加える"MITRE ATT&CK: T1059"の行にする
+    pub fn techniques_line(rule: &yaml_rust::Yaml) -> Option<String> {
+        let techniques = mitre::techniques_of_rule(rule);
+        if techniques.is_empty() {
+            return Option::None;
+        }
+        return Option::Some(format!("MITRE ATT&CK: {}", techniques.join(", ")));
+    }
+
     pub fn read_dir<P: AsRef<Path>>(&mut self, path: P) -> io::Result<String> {
         Ok(fs::read_dir(path)?
             .filter_map(|entry| {
@@ -95,6 +105,7 @@ impl ParseYaml {
 #[cfg(test)]
 mod tests {
 
+    use crate::detections::print::AlertMessages;
     use crate::detections::yaml;
     use std::path::Path;
     use yaml_rust::YamlLoader;
@@ -116,6 +127,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_techniques_line() {
+        let yaml = yaml::ParseYaml::new();
+        let path = Path::new("test_files/rules/yaml/1.yml");
+        let ret = yaml.read_file(path.to_path_buf()).unwrap();
+        let rule = YamlLoader::load_from_str(&ret).unwrap();
+        let line = yaml::ParseYaml::techniques_line(&rule[0]).unwrap();
+        assert_eq!("MITRE ATT&CK: T1059", line);
+
+        // 検知結果のテクニックになる
+        let mut alert_messages = AlertMessages::new();
+        let alert = alert_messages.insert(&vec![
+            "Message: Sysmon Check command lines".to_string(),
+            line,
+        ]);
+        assert_eq!(vec!["T1059"], alert.techniques);
+
+        // 戦術のタグしかなければ何も加えない
+        let rule = YamlLoader::load_from_str("tags:\n    - attack.execution\n").unwrap();
+        assert_eq!(Option::None, yaml::ParseYaml::techniques_line(&rule[0]));
+    }
+
     #[test]
     fn test_failed_read_yaml() {
         let yaml = yaml::ParseYaml::new();
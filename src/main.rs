@@ -3,20 +3,186 @@ extern crate serde;
 use evtx::EvtxParser;
 use rusty_blue::detections::configs;
 use rusty_blue::detections::detection;
-use rusty_blue::detections::print::MessageNotation;
-use std::{fs, path::PathBuf, process};
+use rusty_blue::detections::exclusion;
+use rusty_blue::detections::filter;
+use rusty_blue::detections::html_report;
+use rusty_blue::detections::level;
+use rusty_blue::detections::mitre;
+use rusty_blue::detections::print::{MessageNotation, ALERT_MESSAGES};
+use rusty_blue::detections::rdp;
+use rusty_blue::detections::signature;
+use rusty_blue::detections::state;
+use rusty_blue::detections::summary;
+use rusty_blue::detections::timestamp;
+use std::collections::HashMap;
+use std::{fs, path::Path, path::PathBuf, process, thread, time::Duration};
 
 fn main() {
+    if let Some(csvfile) = configs::CONFIG.args.value_of("import-regexes") {
+        import_regexes(csvfile);
+        return;
+    }
+
+    let filter = match filter::RecordFilter::from_args(&configs::CONFIG.args) {
+        Ok(filter) => filter,
+        Err(e) => {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            MessageNotation::alert(&mut stdout, e).ok();
+            process::exit(1);
+        }
+    };
+    let time_formatter = match timestamp::TimeFormatter::from_args(&configs::CONFIG.args) {
+        Ok(time_formatter) => time_formatter,
+        Err(e) => {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            MessageNotation::alert(&mut stdout, e).ok();
+            process::exit(1);
+        }
+    };
+
+    if let Some(min_level) = configs::CONFIG.args.value_of("min-level") {
+        match level::Level::from_str(min_level) {
+            Ok(min_level) => ALERT_MESSAGES.lock().unwrap().set_min_level(min_level),
+            Err(e) => {
+                let stdout = std::io::stdout();
+                let mut stdout = stdout.lock();
+                MessageNotation::alert(&mut stdout, e).ok();
+                process::exit(1);
+            }
+        }
+    }
+
+    load_exclusions();
+
+    // --output html <file>
+    let output: Option<Vec<&str>> = configs::CONFIG
+        .args
+        .values_of("output")
+        .map(|values| values.collect());
+    if let Some(output) = &output {
+        if output[0].to_lowercase() != "html" {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            MessageNotation::alert(
+                &mut stdout,
+                format!("invalid output format: {}. use html.", output[0]),
+            )
+            .ok();
+            process::exit(1);
+        }
+    }
+
+    if configs::CONFIG.args.is_present("follow") {
+        follow(&filter, &time_formatter);
+        return;
+    }
+
+    let is_summary = configs::CONFIG.args.is_present("summary");
+    let is_rdp = configs::CONFIG.args.is_present("rdp");
+    if is_summary || is_rdp {
+        ALERT_MESSAGES.lock().unwrap().set_print(false);
+    }
+
+    // --state: 前回の実行で処理したレコードを読み飛ばす
+    let state_path = configs::CONFIG.args.value_of("state");
+    let mut state = state_path.map(load_state);
+
+    let mut summaries: Vec<summary::FileSummary> = Vec::new();
     if let Some(filepath) = configs::CONFIG.args.value_of("filepath") {
-        parse_file(&filepath.to_string());
+        summaries.push(parse_file(
+            &filepath.to_string(),
+            &filter,
+            &time_formatter,
+            state.as_mut(),
+        ));
+        if let (Some(state), Some(state_path)) = (&state, state_path) {
+            save_state(state, state_path);
+        }
     } else if let Some(dirpath) = configs::CONFIG.args.value_of("dirpath") {
         let target_paths = parse_dir(&dirpath.to_string());
         for target_path in target_paths {
-            println!("---------------------");
-            println!("{}", target_path.display().to_string());
-            println!("");
-            parse_file(&target_path.display().to_string());
-            println!("---------------------");
+            if !is_summary && !is_rdp {
+                println!("---------------------");
+                println!("{}", target_path.display().to_string());
+                println!("");
+            }
+            summaries.push(parse_file(
+                &target_path.display().to_string(),
+                &filter,
+                &time_formatter,
+                state.as_mut(),
+            ));
+            // 途中で止まっても処理済みのファイルをやり直さなくて済むように、ファイル毎に保存する
+            if let (Some(state), Some(state_path)) = (&state, state_path) {
+                save_state(state, state_path);
+            }
+            if !is_summary && !is_rdp {
+                println!("---------------------");
+            }
+        }
+    }
+
+    if is_summary && !summaries.is_empty() {
+        let alert_messages = ALERT_MESSAGES.lock().unwrap();
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        summary::create_summary(&summaries, &alert_messages.alerts, &time_formatter)
+            .iter()
+            .for_each(|msg| {
+                MessageNotation::info_noheader(&mut stdout, msg.to_string()).ok();
+            });
+    }
+
+    // SecurityとTerminalServicesのログは別のファイルなので、全ファイルのイベントをまとめる
+    if is_rdp && !summaries.is_empty() {
+        let rdp_events: Vec<&rdp::RdpEvent> = summaries
+            .iter()
+            .flat_map(|summary| summary.rdp_events.iter())
+            .collect();
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        rdp::create_rdp_report(&rdp_events, &time_formatter)
+            .iter()
+            .for_each(|msg| {
+                MessageNotation::info_noheader(&mut stdout, msg.to_string()).ok();
+            });
+    }
+
+    {
+        let alert_messages = ALERT_MESSAGES.lock().unwrap();
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        alert_messages
+            .get_exclusions()
+            .create_report()
+            .iter()
+            .for_each(|msg| {
+                MessageNotation::info_noheader(&mut stdout, msg.to_string()).ok();
+            });
+    }
+
+    if let Some(output) = &output {
+        let alert_messages = ALERT_MESSAGES.lock().unwrap();
+        if let Err(e) = html_report::write_html_report(
+            output[1],
+            &summaries,
+            &alert_messages.alerts,
+            &time_formatter,
+        ) {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            MessageNotation::alert(&mut stdout, e).ok();
+        }
+    }
+
+    if let Some(path) = configs::CONFIG.args.value_of("attack-layer") {
+        let alert_messages = ALERT_MESSAGES.lock().unwrap();
+        if let Err(e) = mitre::write_navigator_layer(path, &alert_messages.alerts) {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            MessageNotation::alert(&mut stdout, e).ok();
         }
     }
 
@@ -48,7 +214,64 @@ fn print_credits() {
     }
 }
 
-fn parse_file(filepath: &str) {
+// 既定のexclusions.ymlがない場合は除外設定なしで動かす
+fn load_exclusions() {
+    let (path, is_default) = match configs::CONFIG.args.value_of("exclusions") {
+        Some(path) => (path, false),
+        None => ("exclusions.yml", true),
+    };
+    if is_default && !PathBuf::from(path).exists() {
+        return;
+    }
+
+    let (exclusions, errors) = exclusion::read_exclusions(path);
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    errors.into_iter().for_each(|e| {
+        MessageNotation::warn(&mut stdout, e).ok();
+    });
+    ALERT_MESSAGES.lock().unwrap().set_exclusions(exclusions);
+}
+
+// 旧形式のregexes.txtをsignatures.ymlの形式に変換して表示する
+fn import_regexes(csvfile: &str) {
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    match signature::import_legacy_csv(&configs::read_csv(csvfile)) {
+        Ok(yaml) => {
+            MessageNotation::info_noheader(&mut stdout, yaml).ok();
+        }
+        Err(e) => {
+            MessageNotation::alert(&mut stdout, e).ok();
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_file(
+    filepath: &str,
+    filter: &filter::RecordFilter,
+    time_formatter: &timestamp::TimeFormatter,
+    state: Option<&mut state::StateFile>,
+) -> summary::FileSummary {
+    let mut detection = detection::Detection::new(filepath, filter.clone(), time_formatter.clone());
+    let current = match &state {
+        Some(_) => state::FileState::read(Path::new(filepath)).ok(),
+        None => Option::None,
+    };
+    if let (Some(state), Some(current)) = (&state, &current) {
+        let previous = state.get(filepath);
+        // 前回から変わっていないファイルは開かずに、前回までの集計値だけを返す
+        if previous.is_unchanged(current) {
+            let mut file_summary = summary::FileSummary::new(filepath);
+            previous.counters.add_to(&mut file_summary);
+            return file_summary;
+        }
+        if previous.is_continued(current) {
+            detection.resume(&previous);
+        }
+    }
+
     let fp = PathBuf::from(filepath);
     let parser = match EvtxParser::from_path(fp) {
         Ok(pointer) => pointer,
@@ -60,8 +283,120 @@ fn parse_file(filepath: &str) {
         }
     };
 
-    let mut detection = detection::Detection::new();
     &detection.start(parser);
+    if let (Some(state), Some(mut current)) = (state, current) {
+        current.update(detection.last_record_id(), detection.counters());
+        state.set(filepath, current);
+    }
+    return detection.into_summary();
+}
+
+fn load_state(path: &str) -> state::StateFile {
+    match state::StateFile::load(path) {
+        Ok(state) => return state,
+        Err(e) => {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            MessageNotation::alert(&mut stdout, e).ok();
+            process::exit(1);
+        }
+    }
+}
+
+fn save_state(state: &state::StateFile, path: &str) {
+    if let Err(e) = state.save(path) {
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        MessageNotation::alert(&mut stdout, e).ok();
+    }
+}
+
+//
+// --follow: ファイル(ディレクトリの場合は全ての.evtx)を監視し、追記されたレコードを処理し続ける
+// 検知の状態はファイル毎に引き継ぎ、処理済みのEventRecordIDは状態ファイルに残して再起動後に再開する
+//
+fn follow(filter: &filter::RecordFilter, time_formatter: &timestamp::TimeFormatter) {
+    let configs = &configs::CONFIG.configs;
+    let interval = configs["follow_interval"].as_i64().unwrap_or(5).max(1) as u64;
+    // --stateがなければconfig.ymlの状態ファイルを使う
+    let state_path = configs::CONFIG
+        .args
+        .value_of("state")
+        .or_else(|| configs["follow_state_file"].as_str())
+        .unwrap_or("follow_state.json");
+    let mut state = load_state(state_path);
+
+    // key: ファイルのパス, value: (検知, 前回処理した時のファイルの状態)
+    let mut detections: HashMap<String, (detection::Detection, state::FileState)> = HashMap::new();
+    loop {
+        // ディレクトリに新しく作られたファイルも対象にする
+        let target_paths = if let Some(filepath) = configs::CONFIG.args.value_of("filepath") {
+            vec![PathBuf::from(filepath)]
+        } else if let Some(dirpath) = configs::CONFIG.args.value_of("dirpath") {
+            parse_dir(dirpath)
+        } else {
+            vec![]
+        };
+
+        // (ファイルのパス, 今回処理した時のファイルの状態)
+        let mut updated: Vec<(String, state::FileState)> = vec![];
+        for target_path in target_paths {
+            let filepath = target_path.display().to_string();
+            let current = match state::FileState::read(&target_path) {
+                Ok(current) => current,
+                Err(_) => continue,
+            };
+            let (detection, previous) = detections.entry(filepath.clone()).or_insert_with(|| {
+                let previous = state.get(&filepath);
+                let mut detection =
+                    detection::Detection::new(&filepath, filter.clone(), time_formatter.clone());
+                if previous.is_continued(&current) {
+                    detection.resume(&previous);
+                }
+                (detection, previous)
+            });
+            // 最後のチャンクに書き足されただけでも、最後のレコードのEventRecordIDが変わるので処理する
+            if previous.is_unchanged(&current) {
+                continue;
+            }
+            // ログが消去されて新しいログになった場合は、最初から処理し直す
+            if !previous.is_continued(&current) {
+                *detection =
+                    detection::Detection::new(&filepath, filter.clone(), time_formatter.clone());
+            }
+
+            let parser = match EvtxParser::from_path(&target_path) {
+                Ok(parser) => parser,
+                Err(e) => {
+                    let stdout = std::io::stdout();
+                    let mut stdout = stdout.lock();
+                    MessageNotation::warn(&mut stdout, format!("{}: {}", filepath, e)).ok();
+                    continue;
+                }
+            };
+            detection.process(parser).ok();
+            updated.push((filepath, current));
+        }
+
+        // 失敗したログオンの合計等の集計が必要な検知や、残りが記録されない4104のスクリプトブロックを表示する
+        // 改ざんの痕跡もここで集計に移すので、状態ファイルに残すのはこの後にする
+        detections
+            .values_mut()
+            .for_each(|(detection, _)| detection.flush());
+        if !updated.is_empty() {
+            for (filepath, mut next) in updated {
+                if let Some((detection, previous)) = detections.get_mut(&filepath) {
+                    next.update(detection.last_record_id(), detection.counters());
+                    state.set(&filepath, next.clone());
+                    *previous = next;
+                }
+            }
+            save_state(&state, state_path);
+        }
+        // --followではレポートを作らないので、表示済みの検知結果は残さない
+        ALERT_MESSAGES.lock().unwrap().alerts.clear();
+        thread::sleep(Duration::from_secs(interval));
+    }
 }
 
 fn parse_dir(dirpath: &str) -> Vec<PathBuf> {
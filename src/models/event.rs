@@ -41,7 +41,7 @@ pub struct System {
     #[serde(rename = "Version")]
     version: Option<String>,
     #[serde(rename = "Level")]
-    level: String,
+    pub level: String,
     #[serde(rename = "Task")]
     task: String,
     #[serde(rename = "Opcode")]
@@ -59,7 +59,7 @@ pub struct System {
     #[serde(rename = "Channel")]
     pub channel: String, // Security, System, Application ...etc
     #[serde(rename = "Computer")]
-    computer: String,
+    pub computer: String,
     #[serde(rename = "Security")]
     security: String,
     #[serde(rename = "Message")]
@@ -76,6 +76,10 @@ pub struct EventData {
 pub struct UserData {
     #[serde(rename = "LogFileCleared")]
     pub log_file_cleared: Option<LogFileCleared>,
+    #[serde(rename = "RuleAndFileData")]
+    pub rule_and_file_data: Option<RuleAndFileData>,
+    #[serde(rename = "EventXML")]
+    pub event_xml: Option<EventXML>,
 }
 
 #[derive(Debug, Deserialize, PartialEq)]
@@ -90,6 +94,44 @@ pub struct LogFileCleared {
     pub subject_logon_id: Option<String>,
 }
 
+// AppLocker(8003,8004,8006,8007,8021,8022,8024,8025,8027)のUserData
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct RuleAndFileData {
+    #[serde(rename = "PolicyName")]
+    pub policy_name: Option<String>,
+    #[serde(rename = "RuleId")]
+    pub rule_id: Option<String>,
+    #[serde(rename = "RuleName")]
+    pub rule_name: Option<String>,
+    #[serde(rename = "TargetUser")]
+    pub target_user: Option<String>,
+    #[serde(rename = "TargetProcessId")]
+    pub target_process_id: Option<String>,
+    #[serde(rename = "FilePath")]
+    pub file_path: Option<String>,
+    #[serde(rename = "FileHash")]
+    pub file_hash: Option<String>,
+    #[serde(rename = "Fqbn")]
+    pub fqbn: Option<String>,
+}
+
+// TerminalServices-LocalSessionManager(21-25)とRemoteConnectionManager(1149)のUserData
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct EventXML {
+    #[serde(rename = "User")]
+    pub user: Option<String>,
+    #[serde(rename = "SessionID")]
+    pub session_id: Option<String>,
+    #[serde(rename = "Address")]
+    pub address: Option<String>,
+    #[serde(rename = "Param1")]
+    pub param1: Option<String>,
+    #[serde(rename = "Param2")]
+    pub param2: Option<String>,
+    #[serde(rename = "Param3")]
+    pub param3: Option<String>,
+}
+
 #[derive(Debug, Deserialize, PartialEq)]
 pub struct Evtx {
     #[serde(rename = "System")]
@@ -120,14 +162,59 @@ impl Evtx {
 
         if let Some(event_data) = &self.event_data {
             if let Some(data) = &event_data.data {
+                // Windows PowerShellのログ等、名前のないDataはData1, Data2, ...として詰める
+                let mut unnamed = 0;
                 for v in data.iter() {
                     if let Some(name) = &v.name {
                         values.insert(name.to_string(), Evtx::get_string(v));
+                    } else {
+                        unnamed += 1;
+                        values.insert(format!("Data{}", unnamed), Evtx::get_string(v));
                     }
                 }
             }
         }
 
+        // AppLockerはEventDataではなくUserDataに値が入っているので、同じHashMapに詰める
+        if let Some(rule_data) = self
+            .user_data
+            .as_ref()
+            .and_then(|u| u.rule_and_file_data.as_ref())
+        {
+            let fields = vec![
+                ("PolicyName", &rule_data.policy_name),
+                ("RuleId", &rule_data.rule_id),
+                ("RuleName", &rule_data.rule_name),
+                ("TargetUser", &rule_data.target_user),
+                ("TargetProcessId", &rule_data.target_process_id),
+                ("FilePath", &rule_data.file_path),
+                ("FileHash", &rule_data.file_hash),
+                ("Fqbn", &rule_data.fqbn),
+            ];
+            for (name, value) in fields {
+                if let Some(value) = value {
+                    values.insert(name.to_string(), value.to_string());
+                }
+            }
+        }
+
+        // TerminalServicesもUserDataに値が入っている
+        if let Some(event_xml) = self.user_data.as_ref().and_then(|u| u.event_xml.as_ref()) {
+            let fields = vec![
+                ("User", &event_xml.user),
+                ("SessionID", &event_xml.session_id),
+                ("Address", &event_xml.address),
+                ("Param1", &event_xml.param1),
+                ("Param2", &event_xml.param2),
+                ("Param3", &event_xml.param3),
+            ];
+            for (name, value) in fields {
+                if let Some(value) = value {
+                    values.insert(name.to_string(), value.to_string());
+                }
+            }
+        }
+
         values
     }
 }